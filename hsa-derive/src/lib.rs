@@ -0,0 +1,104 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Index, Member};
+
+#[proc_macro_derive(KernelArgs)]
+pub fn derive_kernel_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => compile_error(&e).into(),
+    }
+}
+
+// syn's to_compile_error expands to ::core::compile_error!, which 2015 edition crates cannot name
+fn compile_error(e: &Error) -> TokenStream2 {
+    let message = e.to_string();
+    quote_spanned!(e.span() => compile_error!(#message);)
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            } else if meta.input.peek(syn::token::Paren) {
+                let _ = meta.input.parse::<TokenStream2>();
+            }
+            Ok(())
+        });
+    }
+    repr_c
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match input.data {
+        Data::Struct(ref s) => &s.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "KernelArgs can only be derived for structs",
+            ))
+        }
+    };
+    if !is_repr_c(input) {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "KernelArgs requires #[repr(C)] so the field layout matches the kernarg segment",
+        ));
+    }
+
+    let (members, names): (Vec<Member>, Vec<String>) = match *fields {
+        Fields::Named(ref f) => f.named.iter()
+            .map(|f| {
+                let ident = f.ident.clone().unwrap();
+                (Member::Named(ident.clone()), ident.to_string())
+            })
+            .unzip(),
+        Fields::Unnamed(ref f) => (0..f.unnamed.len())
+            .map(|i| (Member::Unnamed(Index::from(i)), i.to_string()))
+            .unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+    };
+    let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut predicates = where_clause.map(|w| w.predicates.clone()).unwrap_or_default();
+    for ty in &types {
+        predicates.push(parse_quote!(#ty: ::hsa::KernelArg));
+    }
+
+    let size = match (members.last(), types.last()) {
+        (Some(member), Some(ty)) => quote! {
+            ::std::mem::offset_of!(Self, #member) + ::std::mem::size_of::<#ty>()
+        },
+        _ => quote!(0),
+    };
+
+    Ok(quote! {
+        unsafe impl #impl_generics ::hsa::KernelArgs for #name #ty_generics
+        where
+            #predicates
+        {
+            const FIELDS: &'static [::hsa::KernargField] = &[
+                #(::hsa::KernargField {
+                    name: #names,
+                    offset: ::std::mem::offset_of!(Self, #members),
+                    size: ::std::mem::size_of::<#types>(),
+                    alignment: ::std::mem::align_of::<#types>(),
+                    access: <#types as ::hsa::KernelArg>::ACCESS,
+                },)*
+            ];
+            const SIZE: usize = #size;
+            const ALIGNMENT: usize = ::std::mem::align_of::<Self>();
+        }
+    })
+}
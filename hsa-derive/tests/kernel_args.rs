@@ -0,0 +1,119 @@
+#![allow(deprecated)]
+
+extern crate hsa;
+
+use hsa::emulator::{register_kernel, WorkItem};
+use hsa::{DeviceConstPtr, DevicePtr, KernargField, KernelArgs};
+use hsa::AccessPermission::{RO, RW};
+
+#[repr(C)]
+#[derive(Clone, Copy, KernelArgs)]
+struct Args<'a> {
+    flag: u8,
+    out: DevicePtr<'a, f32>,
+    scale: [f32; 4],
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, KernelArgs)]
+struct Pair<'a>(DeviceConstPtr<'a, u8>, DevicePtr<'a, u8>);
+
+#[test]
+fn layout_follows_natural_alignment() {
+    assert_eq!(
+        Args::FIELDS,
+        &[
+            KernargField { name: "flag", offset: 0, size: 1, alignment: 1, access: None },
+            KernargField { name: "out", offset: 8, size: 8, alignment: 8, access: Some(RW) },
+            KernargField { name: "scale", offset: 16, size: 16, alignment: 4, access: None },
+            KernargField { name: "count", offset: 32, size: 4, alignment: 4, access: None },
+        ]
+    );
+    assert_eq!(Args::SIZE, 36);
+    assert_eq!(Args::ALIGNMENT, 8);
+    assert_eq!(Pair::SIZE, 16);
+    assert_eq!(Pair::FIELDS[1].name, "1");
+}
+
+#[test]
+fn validates_against_the_kernel_symbol() {
+    let runtime = hsa::init().unwrap();
+    register_kernel("&__derive_pair", |_: &WorkItem, _: &Pair<'static>| {});
+    register_kernel("&__derive_wide", |_: &WorkItem, _: &[u64; 3]| {});
+    let agent = hsa::Agent::from_device_type(hsa::DeviceType::CPU).unwrap()[0];
+    let executable = hsa::Executable::new(
+        &runtime,
+        hsa::Profile::Full,
+        hsa::DefaultFloatRoundingMode::Default,
+        "",
+    ).unwrap();
+    executable.freeze("").unwrap();
+
+    let pair = executable.get_symbol("", "&__derive_pair", agent, 0).unwrap();
+    let wide = executable.get_symbol("", "&__derive_wide", agent, 0).unwrap();
+    assert!(Pair::validate(&pair).is_ok());
+    let e = Pair::validate(&wide).unwrap_err();
+    assert_eq!(e, hsa::ErrorStatus::InvalidArgument);
+    assert!(e.to_string().contains("16 bytes but the kernel expects 24"));
+
+    let queue = hsa::Queue::new(&runtime, agent, 64, hsa::QueueType::Single).unwrap();
+    let ring = queue.kernarg_ring(agent.kernarg_global_regions().unwrap()[0], 1024).unwrap();
+    let region = agent.fine_grained_global_regions().unwrap()[0];
+    let input = hsa::DeviceBuffer::<u8>::new(&runtime, region, 4).unwrap();
+    let mut output = hsa::DeviceBuffer::<u8>::new(&runtime, region, 4).unwrap();
+    let args = Pair(input.device_const_ptr(), output.device_ptr());
+    assert!(ring.allocate(&pair).unwrap().write_args(&args).is_ok());
+    assert!(ring.allocate(&wide).unwrap().write_args(&args).is_err());
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, KernelArgs)]
+struct Fill<'a> {
+    out: DevicePtr<'a, u32>,
+}
+
+#[test]
+fn dispatch_checks_buffer_access() {
+    let runtime = hsa::init().unwrap();
+    register_kernel("&__derive_fill", |item: &WorkItem, args: &Fill<'static>| unsafe {
+        *args.out.as_ptr().add(item.absolute_id(0) as usize) = 1;
+    });
+    let agent = hsa::Agent::from_device_type(hsa::DeviceType::CPU).unwrap()[0];
+    let executable = hsa::Executable::new(
+        &runtime,
+        hsa::Profile::Full,
+        hsa::DefaultFloatRoundingMode::Default,
+        "",
+    ).unwrap();
+    executable.freeze("").unwrap();
+    let symbol = executable.get_symbol("", "&__derive_fill", agent, 0).unwrap();
+    let queue = hsa::Queue::new(&runtime, agent, 64, hsa::QueueType::Single).unwrap();
+    let ring = queue.kernarg_ring(agent.kernarg_global_regions().unwrap()[0], 1024).unwrap();
+    let region = agent.fine_grained_global_regions().unwrap()[0];
+    let mut buffer = hsa::DeviceBuffer::<u32>::new(&runtime, region, 4).unwrap();
+    let packet = || {
+        hsa::KernelDispatchPacket::builder(symbol.kernel_object().unwrap())
+            .grid_size(&[4])
+            .workgroup_size(&[4])
+            .build()
+            .unwrap()
+    };
+    let mut dispatch = |access| {
+        buffer.access().grant(&[agent], access).unwrap();
+        let mut slot = ring.allocate(&symbol).unwrap();
+        slot.write_args(&Fill { out: buffer.device_ptr() }).unwrap();
+        ring.dispatch(slot, packet()).map(|c| c.wait().unwrap())
+    };
+
+    if cfg!(debug_assertions) {
+        let e = dispatch(RO).unwrap_err();
+        assert_eq!(e, hsa::ErrorStatus::InvalidArgument);
+        assert!(e.to_string().contains("needs RW access"));
+    }
+    dispatch(RW).unwrap();
+    assert_eq!(buffer.access().granted(), vec![(agent, RW)]);
+    buffer.access().forget(agent);
+    assert_eq!(buffer.access().get(agent), None);
+    assert_eq!(buffer.as_slice().unwrap(), &[1, 1, 1, 1]);
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use native::*;
+use super::{check, Error, ErrorStatus};
+
+struct Tracked {
+    id: u64,
+    end: usize,
+    // every agent may use the memory until it is granted to specific ones
+    shared: bool,
+    grants: Vec<(Agent, AccessPermission)>,
+}
+
+static ALLOCATIONS: Mutex<BTreeMap<usize, Tracked>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn allocations() -> MutexGuard<'static, BTreeMap<usize, Tracked>> {
+    ALLOCATIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// fine-grained global memory needs no assignment before an agent uses it
+pub(crate) fn fine_grained(region: Region) -> bool {
+    region.global_flags().map(|f| f.contains(&RegionGlobalFlag::FineGrained)).unwrap_or(false)
+}
+
+fn covers(granted: AccessPermission, required: AccessPermission) -> bool {
+    granted as u32 & required as u32 == required as u32
+}
+
+pub struct AccessSet {
+    id: u64,
+    start: usize,
+    size: usize,
+}
+
+impl AccessSet {
+    pub(crate) fn new(ptr: *const c_void, size: usize, shared: bool) -> AccessSet {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        if size != 0 {
+            allocations().insert(
+                ptr as usize,
+                Tracked {
+                    id,
+                    end: ptr as usize + size,
+                    shared,
+                    grants: Vec::new(),
+                },
+            );
+        }
+        AccessSet {
+            id,
+            start: ptr as usize,
+            size,
+        }
+    }
+
+    pub fn grant(&self, agents: &[Agent], access: AccessPermission) -> Result<(), Error> {
+        if self.size == 0 {
+            return Ok(());
+        }
+        for agent in agents {
+            check(
+                unsafe { hsa_memory_assign_agent(self.start as *mut c_void, *agent, access) },
+                (),
+            )?;
+            self.record(&[*agent], access);
+        }
+        Ok(())
+    }
+
+    // for grants made through another call, such as hsa_amd_agents_allow_access
+    pub(crate) fn record(&self, agents: &[Agent], access: AccessPermission) {
+        if let Some(tracked) = self.tracked(&mut allocations()) {
+            for agent in agents {
+                match tracked.grants.iter_mut().find(|g| g.0 == *agent) {
+                    Some(grant) => grant.1 = access,
+                    None => tracked.grants.push((*agent, access)),
+                }
+            }
+        }
+    }
+
+    // HSA has no call to withdraw an assignment, so the agent keeps its access; this only
+    // drops the grant from the bookkeeping that dispatch-time validation checks
+    pub fn forget(&self, agent: Agent) {
+        if let Some(tracked) = self.tracked(&mut allocations()) {
+            tracked.grants.retain(|g| g.0 != agent);
+        }
+    }
+
+    pub fn get(&self, agent: Agent) -> Option<AccessPermission> {
+        self.tracked(&mut allocations())
+            .and_then(|t| t.grants.iter().find(|g| g.0 == agent).map(|g| g.1))
+    }
+
+    pub fn granted(&self) -> Vec<(Agent, AccessPermission)> {
+        self.tracked(&mut allocations()).map(|t| t.grants.clone()).unwrap_or_default()
+    }
+
+    // the owner frees its memory before this drops, so another allocation may already
+    // have registered the same address
+    fn tracked<'a>(
+        &self,
+        allocations: &'a mut BTreeMap<usize, Tracked>,
+    ) -> Option<&'a mut Tracked> {
+        allocations.get_mut(&self.start).filter(|t| t.id == self.id)
+    }
+}
+
+impl Drop for AccessSet {
+    fn drop(&mut self) {
+        let mut allocations = allocations();
+        if self.tracked(&mut allocations).is_some() {
+            allocations.remove(&self.start);
+        }
+    }
+}
+
+// shared allocations are only checked once they were granted to specific agents, and
+// pointers the crate does not track are not checked at all
+pub(crate) fn validate(agent: Agent, pointers: &[(usize, AccessPermission)]) -> Result<(), Error> {
+    let allocations = allocations();
+    for &(ptr, required) in pointers {
+        let tracked = match allocations.range(..=ptr).next_back() {
+            Some((_, tracked)) if ptr < tracked.end => tracked,
+            _ => continue,
+        };
+        if tracked.grants.is_empty() {
+            if tracked.shared {
+                continue;
+            }
+            return Err(Error::from(ErrorStatus::InvalidArgument).with_context(format!(
+                "buffer at {:#x} was never granted to any agent",
+                ptr
+            )));
+        }
+        match tracked.grants.iter().find(|g| g.0 == agent) {
+            Some(&(_, granted)) if covers(granted, required) => (),
+            granted => {
+                return Err(Error::from(ErrorStatus::InvalidArgument).with_context(format!(
+                    "buffer at {:#x} needs {:?} access but the dispatching agent has {:?}",
+                    ptr,
+                    required,
+                    granted.map(|g| g.1)
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use buffer::DeviceBuffer;
+    use super::validate;
+
+    fn region(flag: RegionGlobalFlag) -> Region {
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        *agent.regions().unwrap().iter()
+            .find(|r| r.global_flags().unwrap().contains(&flag))
+            .unwrap()
+    }
+
+    #[test]
+    fn coarse_grained_memory_must_be_granted() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let fine = DeviceBuffer::<u32>::new(&runtime, region(RegionGlobalFlag::FineGrained), 4)
+            .unwrap();
+        let coarse = DeviceBuffer::<u32>::new(&runtime, region(RegionGlobalFlag::CoarseGrained), 4)
+            .unwrap();
+        let rw = |buffer: &DeviceBuffer<u32>| [(buffer.as_ptr() as usize + 4, AccessPermission::RW)];
+
+        assert!(validate(agent, &rw(&fine)).is_ok());
+        assert!(validate(agent, &rw(&coarse)).is_err());
+        coarse.assign_agent(agent, AccessPermission::RO).unwrap();
+        assert!(validate(agent, &rw(&coarse)).is_err());
+        coarse.assign_agent(agent, AccessPermission::RW).unwrap();
+        assert!(validate(agent, &rw(&coarse)).is_ok());
+
+        coarse.access().forget(agent);
+        assert_eq!(coarse.access().get(agent), None);
+        assert!(validate(agent, &rw(&coarse)).is_err());
+    }
+}
@@ -1,17 +1,19 @@
 use std::os::raw::c_void;
 
-use super::{check, get_fixed_str, get_info, iter_callback_helper, ErrorStatus, FromPrimitive};
+use super::{bitflags, check, get_fixed_str, get_info, iter_callback_helper, Error, Flags,
+            FromPrimitive};
 use native::*;
 use system::get_extension_name;
+use select::RegionSelector;
 
 impl Agent {
-    pub fn list() -> Result<Vec<Agent>, ErrorStatus> {
+    pub fn list() -> Result<Vec<Agent>, Error> {
         let mut v: Vec<Agent> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(unsafe { hsa_iterate_agents(agent_list_callback, p) }, v)
     }
 
-    pub fn from_device_type(device_type: DeviceType) -> Result<Vec<Agent>, ErrorStatus> {
+    pub fn from_device_type(device_type: DeviceType) -> Result<Vec<Agent>, Error> {
         let mut v = try!(Agent::list());
         v.retain(|&agent| match agent.device() {
             Ok(t) => t == device_type,
@@ -20,115 +22,115 @@ impl Agent {
         Ok(v)
     }
 
-    pub fn name(&self) -> Result<String, ErrorStatus> {
+    pub fn name(&self) -> Result<String, Error> {
         get_fixed_str(|x| self.get_info(AgentInfo::Name, x), 64)
     }
 
-    pub fn vendor(&self) -> Result<String, ErrorStatus> {
+    pub fn vendor(&self) -> Result<String, Error> {
         get_fixed_str(|x| self.get_info(AgentInfo::VendorName, x), 64)
     }
 
-    pub fn feature(&self) -> Result<AgentFeature, ErrorStatus> {
-        get_info(|x| self.get_info(AgentInfo::Feature, x))
+    pub fn feature(&self) -> Result<Flags<AgentFeature>, Error> {
+        get_info(|x| self.get_info(AgentInfo::Feature, x)).map(|flags: u32| bitflags(flags))
     }
 
     #[deprecated]
-    pub fn machine_model(&self) -> Result<MachineModel, ErrorStatus> {
+    pub fn machine_model(&self) -> Result<MachineModel, Error> {
         get_info(|x| self.get_info(AgentInfo::MachineModel, x))
     }
 
     #[deprecated]
-    pub fn profile(&self) -> Result<Profile, ErrorStatus> {
+    pub fn profile(&self) -> Result<Profile, Error> {
         get_info(|x| self.get_info(AgentInfo::Profile, x))
     }
 
     #[deprecated]
-    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, ErrorStatus> {
+    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, Error> {
         get_info(|x| self.get_info(AgentInfo::DefaultFloatRoundingMode, x))
     }
 
     #[deprecated]
     pub fn base_profile_default_float_rounding_mode(
         &self,
-    ) -> Result<DefaultFloatRoundingMode, ErrorStatus> {
+    ) -> Result<DefaultFloatRoundingMode, Error> {
         get_info(|x| {
             self.get_info(AgentInfo::BaseProfileDefaultFloatRoundingModes, x)
         })
     }
 
     #[deprecated]
-    pub fn fast_f16_operation(&self) -> Result<bool, ErrorStatus> {
+    pub fn fast_f16_operation(&self) -> Result<bool, Error> {
         get_info(|x| self.get_info(AgentInfo::FastF16Operation, x))
     }
 
     #[deprecated]
-    pub fn wavefront_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn wavefront_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::WavefrontSize, x))
     }
 
     #[deprecated]
-    pub fn workgroup_max_dim(&self) -> Result<[u16; 3], ErrorStatus> {
+    pub fn workgroup_max_dim(&self) -> Result<[u16; 3], Error> {
         get_info(|x| self.get_info(AgentInfo::WorkgroupMaxDim, x))
     }
 
     #[deprecated]
-    pub fn workgroup_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn workgroup_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::WorkgroupMaxSize, x))
     }
 
     #[deprecated]
-    pub fn grid_max_dim(&self) -> Result<Dim3, ErrorStatus> {
+    pub fn grid_max_dim(&self) -> Result<Dim3, Error> {
         get_info(|x| self.get_info(AgentInfo::GridMaxDim, x))
     }
 
     #[deprecated]
-    pub fn grid_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn grid_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::GridMaxSize, x))
     }
 
     #[deprecated]
-    pub fn fbarrier_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn fbarrier_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::FbarrierMaxSize, x))
     }
 
     #[deprecated]
-    pub fn queues_max(&self) -> Result<u32, ErrorStatus> {
+    pub fn queues_max(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::QueuesMax, x))
     }
 
-    pub fn queue_min_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn queue_min_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::QueueMinSize, x))
     }
 
-    pub fn queue_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn queue_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::QueueMaxSize, x))
     }
 
-    pub fn queue_type(&self) -> Result<QueueType, ErrorStatus> {
+    pub fn queue_type(&self) -> Result<QueueType, Error> {
         get_info(|x| self.get_info(AgentInfo::QueueType, x))
     }
 
     #[deprecated]
-    pub fn node(&self) -> Result<u32, ErrorStatus> {
+    pub fn node(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(AgentInfo::Node, x))
     }
 
-    pub fn device(&self) -> Result<DeviceType, ErrorStatus> {
+    pub fn device(&self) -> Result<DeviceType, Error> {
         get_info(|x| self.get_info(AgentInfo::Device, x))
     }
 
     #[deprecated]
-    pub fn cache_size(&self) -> Result<[u32; 4], ErrorStatus> {
+    pub fn cache_size(&self) -> Result<[u32; 4], Error> {
         get_info(|x| self.get_info(AgentInfo::CacheSize, x))
     }
 
     #[deprecated]
-    pub fn isa(&self) -> Result<ISA, ErrorStatus> {
+    pub fn isa(&self) -> Result<ISA, Error> {
         get_info(|x| self.get_info(AgentInfo::ISA, x))
     }
 
-    pub fn extensions(&self) -> Result<Vec<Extension>, ErrorStatus> {
-        let ret: Result<[u8; 128], ErrorStatus> =
+    pub fn extensions(&self) -> Result<Vec<Extension>, Error> {
+        let ret: Result<[u8; 128], Error> =
             get_info(|x| self.get_info(AgentInfo::Extensions, x));
         ret.map(|x| {
             let mut v = Vec::new();
@@ -143,7 +145,7 @@ impl Agent {
         })
     }
 
-    pub fn extension_names(&self) -> Result<Vec<String>, ErrorStatus> {
+    pub fn extension_names(&self) -> Result<Vec<String>, Error> {
         self.extensions().map(|e| {
             e.iter()
                 .filter_map(|id| get_extension_name(*id).ok())
@@ -151,11 +153,11 @@ impl Agent {
         })
     }
 
-    pub fn version_major(&self) -> Result<u16, ErrorStatus> {
+    pub fn version_major(&self) -> Result<u16, Error> {
         get_info(|x| self.get_info(AgentInfo::VersionMajor, x))
     }
 
-    pub fn version_minor(&self) -> Result<u16, ErrorStatus> {
+    pub fn version_minor(&self) -> Result<u16, Error> {
         get_info(|x| self.get_info(AgentInfo::VersionMinor, x))
     }
 
@@ -163,7 +165,7 @@ impl Agent {
         unsafe { hsa_agent_get_info(*self, attr, v) }
     }
 
-    pub fn caches(&self) -> Result<Vec<Cache>, ErrorStatus> {
+    pub fn caches(&self) -> Result<Vec<Cache>, Error> {
         let mut v: Vec<Cache> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -178,7 +180,7 @@ impl Agent {
         extension: Extension,
         version_major: u16,
         version_minor: u16,
-    ) -> Result<bool, ErrorStatus> {
+    ) -> Result<bool, Error> {
         let mut result = false;
         check(
             unsafe {
@@ -198,7 +200,7 @@ impl Agent {
         &self,
         extension: Extension,
         version_major: u16,
-    ) -> Result<(u16, bool), ErrorStatus> {
+    ) -> Result<(u16, bool), Error> {
         let mut result = false;
         let mut version_minor = 0u16;
         check(
@@ -215,7 +217,7 @@ impl Agent {
         )
     }
 
-    pub fn regions(&self) -> Result<Vec<Region>, ErrorStatus> {
+    pub fn regions(&self) -> Result<Vec<Region>, Error> {
         let mut v: Vec<Region> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -224,37 +226,36 @@ impl Agent {
         )
     }
 
-    pub fn fine_grained_global_regions(&self) -> Result<Vec<Region>, ErrorStatus> {
-        let mut regions = try!(self.regions());
-        regions.retain(|&r| {
-            match r.segment() {
-                Ok(RegionSegment::Global) => (),
-                _ => return false,
-            }
-            match r.global_flags() {
-                Ok(x) => x.contains(&RegionGlobalFlag::FineGrained),
-                _ => false,
-            }
-        });
+    pub fn fine_grained_global_regions(&self) -> Result<Vec<Region>, Error> {
+        let selector = RegionSelector::new()
+            .segment(RegionSegment::Global)
+            .flag(RegionGlobalFlag::FineGrained);
+        // runtime order, unlike RegionSelector::select
+        let mut regions = self.regions()?;
+        regions.retain(|&r| selector.matches(r));
         Ok(regions)
     }
 
-    pub fn kernarg_global_regions(&self) -> Result<Vec<Region>, ErrorStatus> {
-        let mut regions = try!(self.regions());
-        regions.retain(|&r| {
-            match r.segment() {
-                Ok(RegionSegment::Global) => (),
-                _ => return false,
-            }
-            match r.global_flags() {
-                Ok(x) => x.contains(&RegionGlobalFlag::KernArg),
-                _ => false,
-            }
-        });
+    pub fn kernarg_global_regions(&self) -> Result<Vec<Region>, Error> {
+        let selector = RegionSelector::new()
+            .segment(RegionSegment::Global)
+            .flag(RegionGlobalFlag::KernArg);
+        // runtime order, unlike RegionSelector::select
+        let mut regions = self.regions()?;
+        regions.retain(|&r| selector.matches(r));
         Ok(regions)
     }
 
-    pub fn isas(&self) -> Result<Vec<ISA>, ErrorStatus> {
+    pub fn memory_pools(&self) -> Result<Vec<MemoryPool>, Error> {
+        let mut v: Vec<MemoryPool> = Vec::new();
+        let p: *mut c_void = &mut v as *mut _ as *mut c_void;
+        check(
+            unsafe { hsa_amd_agent_iterate_memory_pools(*self, memory_pool_list_callback, p) },
+            v,
+        )
+    }
+
+    pub fn isas(&self) -> Result<Vec<ISA>, Error> {
         let mut v: Vec<ISA> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -276,6 +277,10 @@ extern "C" fn region_list_callback(region: Region, data: *mut c_void) -> HSAStat
     iter_callback_helper(region, data)
 }
 
+extern "C" fn memory_pool_list_callback(pool: MemoryPool, data: *mut c_void) -> HSAStatus {
+    iter_callback_helper(pool, data)
+}
+
 extern "C" fn isa_list_callback(isa: ISA, data: *mut c_void) -> HSAStatus {
     iter_callback_helper(isa, data)
 }
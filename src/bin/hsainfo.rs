@@ -3,7 +3,7 @@ extern crate hsa;
 use std::collections::HashMap;
 
 fn main() {
-    hsa::init().unwrap();
+    let runtime = hsa::init().unwrap();
 
     let ts: u64 = hsa::timestamp().unwrap();
     let ts_freq: u64 = hsa::timestamp_frequency().unwrap();
@@ -27,6 +27,8 @@ fn main() {
 
     let agents = hsa::Agent::list().unwrap();
     assert!(!agents.is_empty());
+    // the deprecated properties are still printed
+    #[allow(deprecated)]
     for agent in agents {
         println!(r#"[Agent ({:?})]
   * name: {}
@@ -122,7 +124,24 @@ fn main() {
                 );
             }
         }
-        println!("");
+        // memory pools are an AMD extension that other runtimes do not provide
+        for pool in agent.memory_pools().unwrap_or_default() {
+            println!("  [Memory pool ({:?})]\n    * size: {}\n    * global flags: {:?}",
+                     pool.segment().unwrap(),
+                     pool.size().unwrap(),
+                     pool.global_flags().unwrap(),
+            );
+            println!("    * access: {:?}", pool.access(agent).unwrap());
+            if pool.runtime_alloc_allowed().unwrap() {
+                println!(
+                    "    * alloc max_size/granule/align: {} {} {}",
+                    pool.alloc_max_size().unwrap(),
+                    pool.runtime_alloc_granule().unwrap(),
+                    pool.runtime_alloc_alignment().unwrap()
+                );
+            }
+        }
+        println!();
     }
-    hsa::shutdown().unwrap();
+    runtime.shutdown().unwrap();
 }
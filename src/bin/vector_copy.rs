@@ -1,23 +1,49 @@
 extern crate hsa;
 
-use std::u64;
-use std::os::raw::c_void;
+use hsa::{AccessPermission, DeviceConstPtr, DevicePtr, KernargField};
 
-use hsa::SignalBase;
-
-fn check<T>(r: Result<T, hsa::ErrorStatus>, msg: &str) -> T {
+fn check<T>(r: Result<T, hsa::Error>, msg: &str) -> T {
     match r {
         Ok(x) => {
             println!("{} succeeded.", msg);
             x
         }
-        Err(e) => panic!("{} failed. reason={:?}", msg, e),
+        Err(e) => panic!("{} failed. reason={}", msg, e),
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Args<'a> {
+    in_ptr: DeviceConstPtr<'a, u8>,
+    out_ptr: DevicePtr<'a, u8>,
+}
+
+// what #[derive(hsa::KernelArgs)] generates with the `derive` feature
+unsafe impl<'a> hsa::KernelArgs for Args<'a> {
+    const FIELDS: &'static [KernargField] = &[
+        KernargField {
+            name: "in_ptr",
+            offset: 0,
+            size: 8,
+            alignment: 8,
+            access: Some(AccessPermission::RO),
+        },
+        KernargField {
+            name: "out_ptr",
+            offset: 8,
+            size: 8,
+            alignment: 8,
+            access: Some(AccessPermission::RW),
+        },
+    ];
+    const SIZE: usize = 16;
+    const ALIGNMENT: usize = 8;
+}
+
 fn main() {
     let test_size = 1024 * 1024 * 4;
-    hsa::init().unwrap();
+    let runtime = hsa::init().unwrap();
     check(
         hsa::major_extension_supported(hsa::Extension::Finalizer, 1),
         "Checking finalizer 1.0 extension support",
@@ -33,7 +59,7 @@ fn main() {
         "The agent name is {}.",
         check(agent.name(), "Querying the agent name")
     );
-    let isa = *check(agent.isas(), "Getting a ISA").get(0).expect(
+    let isa = *check(agent.isas(), "Getting a ISA").first().expect(
         "ISA not found",
     );
     let (profile, brig) = {
@@ -53,12 +79,15 @@ fn main() {
     );
     println!("The maximum queue size is {}.", queue_size);
     let queue = check(
-        hsa::Queue::new(agent, queue_size, hsa::QueueType::Single),
+        hsa::Queue::new(&runtime, agent, queue_size, hsa::QueueType::Single),
         "Creating the queue",
     );
+    // the BRIG path goes through the HSA 1.0 finalizer and code object API
+    #[allow(deprecated)]
     let code_object = {
         let ext_prog = check(
             hsa::ExtProgram::new(
+                &runtime,
                 machine_model,
                 profile,
                 hsa::DefaultFloatRoundingMode::Default,
@@ -76,126 +105,64 @@ fn main() {
         )
     };
     let executable = check(
-        hsa::Executable::new(profile, hsa::DefaultFloatRoundingMode::Default, ""),
+        hsa::Executable::new(&runtime, profile, hsa::DefaultFloatRoundingMode::Default, ""),
         "Create the executable",
     );
+    #[allow(deprecated)]
     check(
         executable.load_code_object(agent, code_object, ""),
         "Loading the code object",
     );
     check(executable.freeze(""), "Freeze the executable");
-    let symbol = check(
-        executable.get_symbol("", "&__vector_copy_kernel", agent, 0),
-        "Extract the symbol from the executable",
+    let kernel = check(
+        executable.kernel("&__vector_copy_kernel", agent),
+        "Extracting the kernel from the executable",
     );
-    let kernel_object = check(
-        symbol.kernel_object(),
-        "Extracting the kernel object from the executable",
-    );
-    let group_segment_size = check(
-        symbol.kernel_group_segment_size(),
-        "Extracting the group segment size from the executable",
-    );
-    let private_segment_size = check(
-        symbol.kernel_private_segment_size(),
-        "Extracting the private segment size from the executable",
-    );
-    let signal = check(hsa::Signal::new(1, &[]), "Creating a HSA signal");
     let fine_grained_region = check(
         agent.fine_grained_global_regions(),
         "Finding a fine grained memory region",
-    ).pop()
+    ).into_iter()
+        .next()
         .expect("Not found fine grained memory region");
     let kernarg_region = check(
         agent.kernarg_global_regions(),
         "Finding a kernarg memory region",
-    ).pop()
+    ).into_iter()
+        .next()
         .expect("Not found kernarg memory region");
+    let input: Vec<u8> = (0..test_size).map(|i| i as u8).collect();
     let in_mem = check(
-        hsa::Memory::allocate(fine_grained_region, test_size),
+        hsa::DeviceBuffer::from_slice(&runtime, fine_grained_region, &input),
         "Allocating argument memory for input parameter",
     );
-    let out_mem = check(
-        hsa::Memory::allocate(fine_grained_region, test_size),
+    let mut out_mem = check(
+        hsa::DeviceBuffer::from_slice(&runtime, fine_grained_region, &vec![0u8; test_size]),
         "Allocating argument memory for output parameter",
     );
-    unsafe {
-        let pi = in_mem.as_mut_ptr();
-        let po = out_mem.as_mut_ptr();
-        for i in 0..test_size as isize {
-            *pi.offset(i) = i as u8;
-            *po.offset(i) = 0;
-        }
-    }
 
-    #[repr(C)]
-    struct Args {
-        in_ptr: *const c_void,
-        out_ptr: *mut c_void,
-    };
-    let args = {
-        let mut m = check(
-            hsa::Memory::<Args>::new(kernarg_region),
-            "Allocating kernel argument memory buffer",
-        );
-        let tmp = Args {
-            in_ptr: in_mem.as_ptr() as *const c_void,
-            out_ptr: out_mem.as_mut_ptr() as *mut c_void,
-        };
-        m.copy_from(&tmp);
-        m
-    };
-    let packet = hsa::KernelDispatchPacket::new(
-        hsa::PacketType::KernelDispatch,
-        &[
-            (
-                hsa::FenceScope::System,
-                hsa::PacketHeader::ScacquireFenceScope,
-            ),
-            (
-                hsa::FenceScope::System,
-                hsa::PacketHeader::ScreleaseFenceScope,
-            ),
-        ],
-        1,
-        &[256],
-        &[1024 * 1024],
-        private_segment_size,
-        group_segment_size,
-        kernel_object,
-        args.as_ptr(),
-        signal.handle(),
-    ).unwrap();
-    let index: u64 = queue.load_write_index_relaxed();
-    queue.copy_kernel_dispatch_packet(&packet, index);
-    queue.add_write_index_relaxed(1);
-    queue.doorbell_signal().store_screlease(index as i64);
+    let kernargs = check(
+        queue.kernarg_ring(kernarg_region, 4096),
+        "Allocating the kernel argument ring",
+    );
     println!("Dispatching the kernel");
-    signal.wait_scacquire(
-        hsa::SignalCondition::Eq,
-        0,
-        u64::MAX,
-        hsa::WaitState::Blocked,
+    let completion = check(
+        kernel.launch()
+            .grid_size(&[1024 * 1024])
+            .workgroup_size(&[256])
+            .kernel_args(&Args {
+                in_ptr: in_mem.device_const_ptr(),
+                out_ptr: out_mem.device_ptr(),
+            })
+            .dispatch(&kernargs),
+        "Dispatching the kernel",
     );
-    let (valid, fail_index) = unsafe {
-        let x = in_mem.as_ptr();
-        let y = out_mem.as_ptr();
-        let mut valid = true;
-        let mut fail_index = 0;
-        for i in 0..1024 * 1024 * 4 {
-            if *x.offset(i) != *y.offset(i) {
-                valid = false;
-                fail_index = i;
-                break;
-            }
+    check(completion.wait(), "Waiting for the kernel");
+    let output = check(out_mem.copy_to_vec(), "Reading back the output");
+    match input.iter().zip(&output).position(|(x, y)| x != y) {
+        None => println!("Passed validation."),
+        Some(fail_index) => {
+            println!("VALIDATION FAILED!");
+            println!("Bad index: {}", fail_index);
         }
-        (valid, fail_index)
-    };
-    if valid {
-        println!("Passed validation.");
-    } else {
-        println!("VALIDATION FAILED!");
-        println!("Bad index: {}", fail_index);
     }
-    hsa::shutdown().unwrap();
 }
@@ -0,0 +1,137 @@
+extern crate hsa;
+
+use hsa::emulator::WorkItem;
+use hsa::{AccessPermission, DeviceConstPtr, DevicePtr, KernargField};
+
+fn check<T>(r: Result<T, hsa::Error>, msg: &str) -> T {
+    match r {
+        Ok(x) => {
+            println!("{} succeeded.", msg);
+            x
+        }
+        Err(e) => panic!("{} failed. reason={}", msg, e),
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Args<'a> {
+    in_ptr: DeviceConstPtr<'a, u8>,
+    out_ptr: DevicePtr<'a, u8>,
+}
+
+// what #[derive(hsa::KernelArgs)] generates with the `derive` feature
+unsafe impl<'a> hsa::KernelArgs for Args<'a> {
+    const FIELDS: &'static [KernargField] = &[
+        KernargField {
+            name: "in_ptr",
+            offset: 0,
+            size: 8,
+            alignment: 8,
+            access: Some(AccessPermission::RO),
+        },
+        KernargField {
+            name: "out_ptr",
+            offset: 8,
+            size: 8,
+            alignment: 8,
+            access: Some(AccessPermission::RW),
+        },
+    ];
+    const SIZE: usize = 16;
+    const ALIGNMENT: usize = 8;
+}
+
+fn main() {
+    let test_size = 1024 * 1024 * 4;
+    let runtime = hsa::init().unwrap();
+    hsa::emulator::register_kernel("&__vector_copy_kernel", |item: &WorkItem, args: &Args<'static>| {
+        let i = item.absolute_id(0) as usize * 4;
+        unsafe {
+            for j in i..i + 4 {
+                *args.out_ptr.as_ptr().add(j) = *args.in_ptr.as_ptr().add(j);
+            }
+        }
+    });
+    let agent = {
+        let mut cpu_agents = check(
+            hsa::Agent::from_device_type(hsa::DeviceType::CPU),
+            "Getting a cpu agent",
+        );
+        cpu_agents.pop().expect("CPU agent not found")
+    };
+    println!(
+        "The agent name is {}.",
+        check(agent.name(), "Querying the agent name")
+    );
+    let queue_size = check(
+        agent.queue_max_size(),
+        "Querying the agent maximum queue size",
+    );
+    println!("The maximum queue size is {}.", queue_size);
+    let queue = check(
+        hsa::Queue::new(&runtime, agent, queue_size, hsa::QueueType::Single),
+        "Creating the queue",
+    );
+    let executable = check(
+        hsa::Executable::new(
+            &runtime,
+            hsa::Profile::Full,
+            hsa::DefaultFloatRoundingMode::Default,
+            "",
+        ),
+        "Create the executable",
+    );
+    check(executable.freeze(""), "Freeze the executable");
+    let kernel = check(
+        executable.kernel("&__vector_copy_kernel", agent),
+        "Extract the kernel from the executable",
+    );
+    let fine_grained_region = check(
+        agent.fine_grained_global_regions(),
+        "Finding a fine grained memory region",
+    ).into_iter()
+        .next()
+        .expect("Not found fine grained memory region");
+    let kernarg_region = check(
+        agent.kernarg_global_regions(),
+        "Finding a kernarg memory region",
+    ).into_iter()
+        .next()
+        .expect("Not found kernarg memory region");
+    let input: Vec<u8> = (0..test_size).map(|i| i as u8).collect();
+    let in_mem = check(
+        hsa::DeviceBuffer::from_slice(&runtime, fine_grained_region, &input),
+        "Allocating argument memory for input parameter",
+    );
+    let mut out_mem = check(
+        hsa::DeviceBuffer::from_slice(&runtime, fine_grained_region, &vec![0u8; test_size]),
+        "Allocating argument memory for output parameter",
+    );
+
+    let kernargs = check(
+        queue.kernarg_ring(kernarg_region, 4096),
+        "Allocating the kernel argument ring",
+    );
+    println!("Dispatching the kernel");
+    let completion = check(
+        kernel.launch()
+            .grid_size(&[1024 * 1024])
+            .workgroup_size(&[256])
+            .kernel_args(&Args {
+                in_ptr: in_mem.device_const_ptr(),
+                out_ptr: out_mem.device_ptr(),
+            })
+            .dispatch(&kernargs),
+        "Dispatching the kernel",
+    );
+    check(completion.wait(), "Waiting for the kernel");
+    let output = check(out_mem.copy_to_vec(), "Reading back the output");
+    match input.iter().zip(&output).position(|(x, y)| x != y) {
+        None => println!("Passed validation."),
+        Some(fail_index) => {
+            println!("VALIDATION FAILED!");
+            println!("Bad index: {}", fail_index);
+        }
+    }
+}
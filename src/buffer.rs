@@ -0,0 +1,272 @@
+use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ptr::{self, null_mut, NonNull};
+use std::slice;
+
+use native::*;
+use runtime::Runtime;
+use kernarg::{DeviceConstPtr, DevicePtr, KernelArg};
+use access::{self, AccessSet};
+use super::{check, Error, ErrorStatus};
+
+pub struct DeviceBuffer<'rt, T: KernelArg> {
+    ptr: *mut T,
+    len: usize,
+    host_accessible: bool,
+    access: AccessSet,
+    runtime: PhantomData<&'rt Runtime>,
+}
+
+fn invalid_argument(context: &str) -> Error {
+    Error::from(ErrorStatus::InvalidArgument).with_context(context)
+}
+
+// largest host staging buffer used to zero memory the host cannot write directly
+const ZERO_CHUNK: usize = 1 << 20;
+
+impl<'rt, T: KernelArg> DeviceBuffer<'rt, T> {
+    // the memory is zeroed so that the host side readers never see uninitialized bytes
+    pub fn new(
+        runtime: &'rt Runtime,
+        region: Region,
+        len: usize,
+    ) -> Result<DeviceBuffer<'rt, T>, Error> {
+        let buffer = DeviceBuffer::allocate(runtime, region, len)?;
+        buffer.zero()?;
+        Ok(buffer)
+    }
+
+    fn allocate(
+        _runtime: &'rt Runtime,
+        region: Region,
+        len: usize,
+    ) -> Result<DeviceBuffer<'rt, T>, Error> {
+        let host_accessible = region.host_accessible()?;
+        let size = len.checked_mul(size_of::<T>())
+            .ok_or_else(|| invalid_argument("device buffer size overflows usize"))?;
+        if size == 0 {
+            return Ok(DeviceBuffer {
+                ptr: NonNull::dangling().as_ptr(),
+                len,
+                host_accessible,
+                access: AccessSet::new(null_mut(), 0, true),
+                runtime: PhantomData,
+            });
+        }
+        if align_of::<T>() > region.runtime_alloc_alignment()? {
+            return Err(invalid_argument(
+                "element alignment exceeds the region's runtime_alloc_alignment",
+            ));
+        }
+        if size > region.alloc_max_size()? {
+            return Err(invalid_argument("device buffer exceeds the region's alloc_max_size"));
+        }
+        let mut ptr: *mut c_void = null_mut();
+        check(unsafe { hsa_memory_allocate(region, size, &mut ptr) }, ())?;
+        let buffer = DeviceBuffer {
+            ptr: ptr as *mut T,
+            len,
+            host_accessible,
+            access: AccessSet::new(ptr, size, access::fine_grained(region)),
+            runtime: PhantomData,
+        };
+        if !(ptr as usize).is_multiple_of(align_of::<T>()) {
+            return Err(ErrorStatus::InvalidAllocation.into());
+        }
+        Ok(buffer)
+    }
+
+    pub fn from_slice(
+        runtime: &'rt Runtime,
+        region: Region,
+        src: &[T],
+    ) -> Result<DeviceBuffer<'rt, T>, Error> {
+        let mut buffer = DeviceBuffer::allocate(runtime, region, src.len())?;
+        buffer.copy_from_slice(src)?;
+        Ok(buffer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_host_accessible(&self) -> bool {
+        self.host_accessible
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr
+    }
+
+    pub fn device_const_ptr(&self) -> DeviceConstPtr<'_, T> {
+        DeviceConstPtr::new(self.ptr)
+    }
+
+    pub fn device_ptr(&mut self) -> DevicePtr<'_, T> {
+        DevicePtr::new(self.ptr)
+    }
+
+    pub fn as_slice(&self) -> Result<&[T], Error> {
+        if !self.host_accessible {
+            return Err(invalid_argument("device buffer is not host accessible"));
+        }
+        Ok(unsafe { slice::from_raw_parts(self.ptr, self.len) })
+    }
+
+    pub fn as_mut_slice(&mut self) -> Result<&mut [T], Error> {
+        if !self.host_accessible {
+            return Err(invalid_argument("device buffer is not host accessible"));
+        }
+        Ok(unsafe { slice::from_raw_parts_mut(self.ptr, self.len) })
+    }
+
+    pub fn write(&mut self, offset: usize, src: &[T]) -> Result<(), Error> {
+        self.check_range(offset, src.len())?;
+        self.copy(src.as_ptr(), unsafe { self.ptr.add(offset) }, src.len())
+    }
+
+    pub fn read(&self, offset: usize, dst: &mut [T]) -> Result<(), Error> {
+        self.check_range(offset, dst.len())?;
+        self.copy(unsafe { self.ptr.add(offset) }, dst.as_mut_ptr(), dst.len())
+    }
+
+    pub fn copy_from_slice(&mut self, src: &[T]) -> Result<(), Error> {
+        if src.len() != self.len {
+            return Err(invalid_argument("source slice length does not match the device buffer"));
+        }
+        self.write(0, src)
+    }
+
+    pub fn copy_to_slice(&self, dst: &mut [T]) -> Result<(), Error> {
+        if dst.len() != self.len {
+            return Err(invalid_argument(
+                "destination slice length does not match the device buffer",
+            ));
+        }
+        self.read(0, dst)
+    }
+
+    pub fn copy_to_vec(&self) -> Result<Vec<T>, Error> {
+        let mut v = Vec::with_capacity(self.len);
+        self.copy(self.ptr, v.as_mut_ptr(), self.len)?;
+        unsafe { v.set_len(self.len) };
+        Ok(v)
+    }
+
+    pub fn assign_agent(&self, agent: Agent, access: AccessPermission) -> Result<(), Error> {
+        self.access.grant(&[agent], access)
+    }
+
+    pub fn access(&self) -> &AccessSet {
+        &self.access
+    }
+
+    fn allocated(&self) -> bool {
+        self.len * size_of::<T>() != 0
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), Error> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => Ok(()),
+            _ => Err(invalid_argument("range is out of the device buffer's bounds")),
+        }
+    }
+
+    fn zero(&self) -> Result<(), Error> {
+        let size = self.len * size_of::<T>();
+        if size == 0 {
+            return Ok(());
+        }
+        if self.host_accessible {
+            unsafe { ptr::write_bytes(self.ptr as *mut u8, 0, size) };
+            return Ok(());
+        }
+        let zeros = vec![0u8; size.min(ZERO_CHUNK)];
+        let mut offset = 0;
+        while offset < size {
+            let bytes = (size - offset).min(zeros.len());
+            check(unsafe {
+                hsa_memory_copy(
+                    (self.ptr as *mut u8).add(offset) as *mut c_void,
+                    zeros.as_ptr() as *const c_void,
+                    bytes,
+                )
+            }, ())?;
+            offset += bytes;
+        }
+        Ok(())
+    }
+
+    fn copy(&self, src: *const T, dst: *mut T, len: usize) -> Result<(), Error> {
+        let bytes = len * size_of::<T>();
+        if bytes == 0 {
+            return Ok(());
+        }
+        check(unsafe { hsa_memory_copy(dst as *mut c_void, src as *const c_void, bytes) }, ())
+    }
+}
+
+impl<'rt, T: KernelArg> Drop for DeviceBuffer<'rt, T> {
+    fn drop(&mut self) {
+        if self.allocated() {
+            unsafe {
+                hsa_memory_free(self.ptr as *mut c_void);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use super::DeviceBuffer;
+    use ErrorStatus;
+
+    fn region(flag: RegionGlobalFlag) -> Region {
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        *agent.regions().unwrap().iter()
+            .find(|r| r.global_flags().unwrap().contains(&flag))
+            .unwrap()
+    }
+
+    #[test]
+    fn host_accessible_buffer_round_trip() {
+        let runtime = ::init().unwrap();
+        let region = region(RegionGlobalFlag::FineGrained);
+        let mut buffer = DeviceBuffer::<u32>::new(&runtime, region, 4).unwrap();
+        buffer.as_mut_slice().unwrap().copy_from_slice(&[1, 2, 3, 4]);
+        buffer.write(2, &[7, 8]).unwrap();
+        assert_eq!(buffer.as_slice().unwrap(), &[1, 2, 7, 8]);
+        assert_eq!(buffer.write(3, &[0, 0]).unwrap_err(), ErrorStatus::InvalidArgument);
+        assert_eq!(buffer.read(usize::MAX, &mut [0]).unwrap_err(), ErrorStatus::InvalidArgument);
+    }
+
+    #[test]
+    fn device_only_buffer_requires_copies() {
+        let runtime = ::init().unwrap();
+        let region = region(RegionGlobalFlag::CoarseGrained);
+        let buffer = DeviceBuffer::from_slice(&runtime, region, &[1.0f64, 2.0, 3.0]).unwrap();
+        assert!(!buffer.is_host_accessible());
+        assert!(buffer.as_slice().is_err());
+        assert_eq!(buffer.copy_to_vec().unwrap(), vec![1.0, 2.0, 3.0]);
+        assert!(DeviceBuffer::<u8>::new(&runtime, region, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn new_buffers_are_zeroed() {
+        let runtime = ::init().unwrap();
+        let fine = DeviceBuffer::<u32>::new(&runtime, region(RegionGlobalFlag::FineGrained), 16).unwrap();
+        assert_eq!(fine.as_slice().unwrap(), &[0; 16]);
+        let coarse = DeviceBuffer::<u64>::new(&runtime, region(RegionGlobalFlag::CoarseGrained), 16).unwrap();
+        assert_eq!(coarse.copy_to_vec().unwrap(), vec![0; 16]);
+    }
+}
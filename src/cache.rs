@@ -1,10 +1,10 @@
 use std::os::raw::{c_char, c_void};
 
-use super::{check, get_info, ErrorStatus};
+use super::{check, get_info, Error};
 use native::*;
 
 impl Cache {
-    pub fn name(&self) -> Result<String, ErrorStatus> {
+    pub fn name(&self) -> Result<String, Error> {
         unsafe {
             use std::ptr::null;
             use std::ffi::CStr;
@@ -15,11 +15,11 @@ impl Cache {
         }
     }
 
-    pub fn level(&self) -> Result<u8, ErrorStatus> {
+    pub fn level(&self) -> Result<u8, Error> {
         get_info(|x| self.get_info(CacheInfo::Level, x))
     }
 
-    pub fn size(&self) -> Result<u32, ErrorStatus> {
+    pub fn size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(CacheInfo::Size, x))
     }
 
@@ -1,38 +1,45 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::mem::zeroed;
 use std::os::raw::c_void;
+use std::path::Path;
 
 use native::*;
 use native::CodeObject as CodeObjectHandle;
-use super::{get_info, ErrorStatus};
+use native::CodeObjectReader as CodeObjectReaderHandle;
+use runtime::Runtime;
+use super::{check, get_info, Error, ErrorStatus};
 
 pub struct CodeObject {
     pub handle: CodeObjectHandle,
 }
 
 impl CodeObject {
-    pub fn version(&self) -> Result<String, ErrorStatus> {
+    pub fn version(&self) -> Result<String, Error> {
         get_info(|x| self.get_info(CodeObjectInfo::Version, x)).map(|x: [u8; 64]| {
             let x = x.splitn(2, |c| *c == 0).next().unwrap_or(&[]);
             String::from_utf8_lossy(x).to_string()
         })
     }
 
-    pub fn type_info(&self) -> Result<CodeObjectType, ErrorStatus> {
+    pub fn type_info(&self) -> Result<CodeObjectType, Error> {
         get_info(|x| self.get_info(CodeObjectInfo::Type, x))
     }
 
-    pub fn isa(&self) -> Result<ISA, ErrorStatus> {
+    pub fn isa(&self) -> Result<ISA, Error> {
         get_info(|x| self.get_info(CodeObjectInfo::ISA, x))
     }
 
-    pub fn machine_model(&self) -> Result<MachineModel, ErrorStatus> {
+    pub fn machine_model(&self) -> Result<MachineModel, Error> {
         get_info(|x| self.get_info(CodeObjectInfo::MachineModel, x))
     }
 
-    pub fn profile(&self) -> Result<Profile, ErrorStatus> {
+    pub fn profile(&self) -> Result<Profile, Error> {
         get_info(|x| self.get_info(CodeObjectInfo::Profile, x))
     }
 
-    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, ErrorStatus> {
+    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, Error> {
         get_info(|x| {
             self.get_info(CodeObjectInfo::DefaultFloatRoundingMode, x)
         })
@@ -50,3 +57,140 @@ impl Drop for CodeObject {
         }
     }
 }
+
+// the runtime reads from the source until the reader is destroyed, so the reader keeps
+// it alive
+enum Source<'a> {
+    Bytes(Cow<'a, [u8]>),
+    File(File),
+}
+
+pub struct CodeObjectReader<'a> {
+    pub(crate) handle: CodeObjectReaderHandle,
+    _source: Source<'a>,
+    runtime: PhantomData<&'a Runtime>,
+}
+
+impl<'a> CodeObjectReader<'a> {
+    pub fn from_bytes(_runtime: &'a Runtime, bytes: &'a [u8]) -> Result<CodeObjectReader<'a>, Error> {
+        CodeObjectReader::from_source(Source::Bytes(Cow::Borrowed(bytes)))
+    }
+
+    pub(crate) fn from_memory(bytes: &'a [u8]) -> Result<CodeObjectReader<'a>, Error> {
+        CodeObjectReader::from_source(Source::Bytes(Cow::Borrowed(bytes)))
+    }
+
+    pub fn from_vec(_runtime: &'a Runtime, bytes: Vec<u8>) -> Result<CodeObjectReader<'a>, Error> {
+        CodeObjectReader::from_source(Source::Bytes(Cow::Owned(bytes)))
+    }
+
+    // reads from the current offset of the file
+    pub fn from_file(_runtime: &'a Runtime, file: File) -> Result<CodeObjectReader<'a>, Error> {
+        CodeObjectReader::from_open_file(file)
+    }
+
+    pub fn from_path<P: AsRef<Path>>(
+        _runtime: &'a Runtime,
+        path: P,
+    ) -> Result<CodeObjectReader<'a>, Error> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            Error::from(ErrorStatus::InvalidFile).with_context(format!("{}: {}", path.display(), e))
+        })?;
+        CodeObjectReader::from_open_file(file)
+    }
+
+    #[cfg(unix)]
+    fn from_open_file(file: File) -> Result<CodeObjectReader<'a>, Error> {
+        CodeObjectReader::from_source(Source::File(file))
+    }
+
+    #[cfg(not(unix))]
+    fn from_open_file(mut file: File) -> Result<CodeObjectReader<'a>, Error> {
+        use std::io::Read;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|e| Error::from(ErrorStatus::InvalidFile).with_context(e.to_string()))?;
+        CodeObjectReader::from_source(Source::Bytes(Cow::Owned(bytes)))
+    }
+
+    fn from_source(source: Source<'a>) -> Result<CodeObjectReader<'a>, Error> {
+        let mut handle: CodeObjectReaderHandle = unsafe { zeroed() };
+        let status = match source {
+            Source::Bytes(ref bytes) => unsafe {
+                hsa_code_object_reader_create_from_memory(
+                    bytes.as_ptr() as *const c_void,
+                    bytes.len(),
+                    &mut handle,
+                )
+            },
+            #[cfg(unix)]
+            Source::File(ref file) => unsafe {
+                use std::os::unix::io::AsRawFd;
+                hsa_code_object_reader_create_from_file(file.as_raw_fd(), &mut handle)
+            },
+            #[cfg(not(unix))]
+            Source::File(_) => unreachable!(),
+        };
+        check(status, ())?;
+        Ok(CodeObjectReader {
+            handle,
+            _source: source,
+            runtime: PhantomData,
+        })
+    }
+}
+
+impl<'a> Drop for CodeObjectReader<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            hsa_code_object_reader_destroy(self.handle);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use native::*;
+    use executable::Executable;
+    use ErrorStatus;
+    use super::CodeObjectReader;
+
+    #[test]
+    fn readers_load_into_executables() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "")
+                .unwrap();
+        let mut image = b"\x7fELF".to_vec();
+        image.resize(64, 0);
+
+        let path = std::env::temp_dir().join(format!("hsa-reader-{}.co", std::process::id()));
+        File::create(&path).unwrap().write_all(&image).unwrap();
+        let readers = vec![
+            CodeObjectReader::from_bytes(&runtime, &image).unwrap(),
+            CodeObjectReader::from_file(&runtime, File::open(&path).unwrap()).unwrap(),
+            CodeObjectReader::from_path(&runtime, &path).unwrap(),
+        ];
+        fs::remove_file(&path).unwrap();
+        let mut loaded = Vec::new();
+        for reader in &readers {
+            loaded.push(executable.load_agent_code_object(agent, reader, "").unwrap());
+        }
+        loaded.dedup();
+        assert_eq!(loaded.len(), 3);
+
+        let garbage = CodeObjectReader::from_vec(&runtime, vec![0; 64]).unwrap();
+        let e = executable.load_program_code_object(&garbage, "").unwrap_err();
+        assert_eq!(e, ErrorStatus::InvalidCodeObject);
+        assert_eq!(CodeObjectReader::from_path(&runtime, &path).err().unwrap(),
+                   ErrorStatus::InvalidFile);
+        executable.freeze("").unwrap();
+        let e = executable.load_agent_code_object(agent, &readers[0], "").unwrap_err();
+        assert_eq!(e, ErrorStatus::FrozenExecutable);
+    }
+}
@@ -0,0 +1,440 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::task::{Context, Poll};
+use std::thread;
+
+use native::*;
+use super::{check, Error, ErrorStatus};
+use runtime::Runtime;
+use signal::{Signal, SignalBase};
+use waiter::SignalFuture;
+use buffer::DeviceBuffer;
+use kernarg::KernelArg;
+use region::{HostStorage, RegisteredMemory};
+use memory_pool::PoolMemory;
+
+type CopyError = Arc<Mutex<Option<Error>>>;
+
+pub struct AsyncCopy<'rt> {
+    signal: Arc<Signal<'rt>>,
+    error: CopyError,
+}
+
+struct FallbackCopy {
+    dst: *mut c_void,
+    src: *const c_void,
+    size: usize,
+    deps: Vec<SignalHandle>,
+    completion: SignalHandle,
+    error: CopyError,
+}
+
+unsafe impl Send for FallbackCopy {}
+
+impl FallbackCopy {
+    fn ready(&self) -> bool {
+        self.deps.iter().all(|dep| dep.load_scacquire() == 0)
+    }
+
+    fn run(self) {
+        if let Err(e) = check(unsafe { hsa_memory_copy(self.dst, self.src, self.size) }, ()) {
+            *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+        }
+        self.completion.store_screlease(0);
+    }
+}
+
+// how long the worker waits on a dependency before it looks at the other copies again
+const FALLBACK_POLL_HINT: u64 = 1_000_000;
+
+// copies are made with the API that was current when they were started
+type FallbackJob = (Api, FallbackCopy);
+
+static FALLBACK: Mutex<Option<Sender<FallbackJob>>> = Mutex::new(None);
+
+// a single worker runs every fallback copy; copies whose dependencies are not done yet
+// wait without holding up the others
+fn fallback_worker(jobs: Receiver<FallbackJob>) {
+    let mut pending: Vec<FallbackJob> = Vec::new();
+    loop {
+        if pending.is_empty() {
+            match jobs.recv() {
+                Ok(job) => pending.push(job),
+                Err(_) => return,
+            }
+        }
+        pending.extend(jobs.try_iter());
+        let mut ran = false;
+        let mut i = 0;
+        while i < pending.len() {
+            let ready = with_api(&pending[i].0, || pending[i].1.ready());
+            if ready {
+                let (api, job) = pending.remove(i);
+                with_api(&api, || job.run());
+                ran = true;
+            } else {
+                i += 1;
+            }
+        }
+        if !ran {
+            if let Some((api, job)) = pending.first() {
+                with_api(api, || {
+                    if let Some(dep) = job.deps.iter().find(|d| d.load_scacquire() != 0) {
+                        dep.wait_scacquire(
+                            SignalCondition::Eq,
+                            0,
+                            FALLBACK_POLL_HINT,
+                            WaitState::Blocked,
+                        );
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn submit_fallback(job: FallbackCopy) -> Result<(), Error> {
+    let mut fallback = FALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+    if fallback.is_none() {
+        let (sender, receiver) = channel();
+        thread::Builder::new()
+            .name("hsa-async-copy".to_string())
+            .spawn(move || fallback_worker(receiver))
+            .map_err(|_| Error::from(ErrorStatus::OutOfResources))?;
+        *fallback = Some(sender);
+    }
+    fallback.as_ref().unwrap().send((Api::current(), job))
+        .map_err(|_| Error::from(ErrorStatus::OutOfResources))
+}
+
+// Uses hsa_amd_memory_async_copy when the runtime provides it and a shared background
+// thread running hsa_memory_copy otherwise.
+/// # Safety
+///
+/// `src` and `dst` must be valid for `size` bytes on their agents, must not overlap and,
+/// like every dependency signal, must stay alive until the copy completes.
+pub unsafe fn copy_async<'rt, S: SignalBase>(
+    _runtime: &'rt Runtime,
+    dst: *mut c_void,
+    dst_agent: Agent,
+    src: *const c_void,
+    src_agent: Agent,
+    size: usize,
+    deps: &[S],
+) -> Result<AsyncCopy<'rt>, Error> {
+    if dst.is_null() || src.is_null() {
+        return Err(ErrorStatus::InvalidArgument.into());
+    }
+    let signal = Signal::create(1, &[])?;
+    let deps: Vec<SignalHandle> = deps.iter().map(|s| s.handle()).collect();
+    let error = Arc::new(Mutex::new(None));
+    if Api::current().hsa_amd_memory_async_copy.is_some() {
+        check(
+            hsa_amd_memory_async_copy(
+                dst,
+                dst_agent,
+                src,
+                src_agent,
+                size,
+                deps.len() as u32,
+                deps.as_ptr(),
+                signal.handle(),
+            ),
+            (),
+        )?;
+    } else {
+        submit_fallback(FallbackCopy {
+            dst,
+            src,
+            size,
+            deps,
+            completion: signal.handle(),
+            error: error.clone(),
+        })?;
+    }
+    Ok(AsyncCopy {
+        signal: Arc::new(signal),
+        error,
+    })
+}
+
+/// # Safety
+///
+/// The pointer and length must describe memory that the runtime can copy to and from and
+/// that stays valid for as long as the value is borrowed.
+pub unsafe trait CopyMemory {
+    type Elem: Copy;
+
+    fn copy_src(&self) -> (*const Self::Elem, usize);
+    fn copy_dst(&mut self) -> (*mut Self::Elem, usize);
+}
+
+unsafe impl<T: Copy> CopyMemory for [T] {
+    type Elem = T;
+
+    fn copy_src(&self) -> (*const T, usize) {
+        (self.as_ptr(), self.len())
+    }
+
+    fn copy_dst(&mut self) -> (*mut T, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+}
+
+unsafe impl<'rt, T: KernelArg> CopyMemory for DeviceBuffer<'rt, T> {
+    type Elem = T;
+
+    fn copy_src(&self) -> (*const T, usize) {
+        (self.as_ptr(), self.len())
+    }
+
+    fn copy_dst(&mut self) -> (*mut T, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+}
+
+unsafe impl<'rt, S: HostStorage> CopyMemory for RegisteredMemory<'rt, S> {
+    type Elem = S::Elem;
+
+    fn copy_src(&self) -> (*const S::Elem, usize) {
+        (self.as_slice().as_ptr(), self.len())
+    }
+
+    fn copy_dst(&mut self) -> (*mut S::Elem, usize) {
+        (self.as_mut_slice().as_mut_ptr(), self.len())
+    }
+}
+
+unsafe impl<'rt> CopyMemory for PoolMemory<'rt> {
+    type Elem = u8;
+
+    fn copy_src(&self) -> (*const u8, usize) {
+        (self.as_ptr(), self.len())
+    }
+
+    fn copy_dst(&mut self) -> (*mut u8, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+}
+
+// both sides stay borrowed until copy_scope returns, and it only returns once every copy
+// started in it is done, even when an AsyncCopy was leaked
+pub struct CopyScope<'env> {
+    runtime: &'env Runtime,
+    copies: Mutex<Vec<Arc<Signal<'env>>>>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+pub fn copy_scope<'env, F, R>(runtime: &'env Runtime, f: F) -> R
+where
+    F: FnOnce(&CopyScope<'env>) -> R,
+{
+    let scope = CopyScope {
+        runtime,
+        copies: Mutex::new(Vec::new()),
+        env: PhantomData,
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    for signal in scope.copies().drain(..) {
+        while signal.wait_scacquire(SignalCondition::Eq, 0, u64::MAX, WaitState::Blocked) != 0 {
+        }
+    }
+    match result {
+        Ok(result) => result,
+        Err(panic) => resume_unwind(panic),
+    }
+}
+
+impl<'env> CopyScope<'env> {
+    pub fn copy<D, S>(
+        &self,
+        dst: &'env mut D,
+        dst_agent: Agent,
+        src: &'env S,
+        src_agent: Agent,
+    ) -> Result<AsyncCopy<'env>, Error>
+    where
+        D: CopyMemory + ?Sized,
+        S: CopyMemory<Elem = D::Elem> + ?Sized,
+    {
+        let (dst, dst_len) = dst.copy_dst();
+        let (src, src_len) = src.copy_src();
+        if dst_len != src_len {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("copy source and destination differ in length"));
+        }
+        if dst_len == 0 {
+            return Ok(AsyncCopy {
+                signal: Arc::new(Signal::create(0, &[])?),
+                error: Arc::new(Mutex::new(None)),
+            });
+        }
+        let size = dst_len * size_of::<D::Elem>();
+        let copy = unsafe {
+            copy_async::<Signal>(
+                self.runtime,
+                dst as *mut c_void,
+                dst_agent,
+                src as *const c_void,
+                src_agent,
+                size,
+                &[],
+            )?
+        };
+        self.copies().push(copy.signal.clone());
+        Ok(copy)
+    }
+
+    fn copies(&self) -> MutexGuard<'_, Vec<Arc<Signal<'env>>>> {
+        self.copies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<'rt> AsyncCopy<'rt> {
+    pub fn signal(&self) -> &Signal<'rt> {
+        &self.signal
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.signal.load_scacquire() == 0
+    }
+
+    pub fn wait_async(&self) -> AsyncCopyFuture<'_> {
+        AsyncCopyFuture {
+            future: self.signal.wait_async(SignalCondition::Eq, 0),
+            error: &self.error,
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        while self.signal.wait_scacquire(SignalCondition::Eq, 0, u64::MAX, WaitState::Blocked) != 0 {
+        }
+        result(&self.error)
+    }
+}
+
+impl<'rt> Drop for AsyncCopy<'rt> {
+    fn drop(&mut self) {
+        // the runtime or the fallback thread still signals completion
+        let _ = self.wait();
+    }
+}
+
+fn result(error: &CopyError) -> Result<(), Error> {
+    match error.lock().unwrap_or_else(|e| e.into_inner()).clone() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+pub struct AsyncCopyFuture<'a> {
+    future: SignalFuture<'a>,
+    error: &'a CopyError,
+}
+
+impl<'a> Future for AsyncCopyFuture<'a> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.future).poll(cx) {
+            Poll::Ready(Ok(_)) => Poll::Ready(result(this.error)),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::os::raw::c_void;
+
+    use std::mem::forget;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use native::*;
+    use buffer::DeviceBuffer;
+    use signal::{Signal, SignalBase};
+    use super::{copy_async, copy_scope};
+    use ErrorStatus;
+
+    fn copy_after_dependency() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let src = [1u32, 2, 3, 4];
+        let mut dst = [0u32; 4];
+        let dep = Signal::create(1, &[]).unwrap();
+        let copy = unsafe {
+            copy_async(
+                &runtime,
+                dst.as_mut_ptr() as *mut c_void,
+                agent,
+                src.as_ptr() as *const c_void,
+                agent,
+                16,
+                &[&dep],
+            )
+        }.unwrap();
+        assert!(!copy.is_complete());
+        dep.store_screlease(0);
+        copy.wait().unwrap();
+        drop(copy);
+        assert_eq!(dst, src);
+    }
+
+    #[test]
+    fn copies_wait_for_dependencies() {
+        copy_after_dependency();
+        let mut api = Api::current();
+        api.hsa_amd_memory_async_copy = None;
+        with_api(&api, copy_after_dependency);
+    }
+
+    static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn counting_copy(dst: *mut c_void, src: *const c_void, size: usize) -> HSAStatus {
+        COPIES.fetch_add(1, Ordering::SeqCst);
+        (Api::emulated().hsa_memory_copy)(dst, src, size)
+    }
+
+    #[test]
+    fn scoped_copies_run_on_the_callers_api() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = *agent.regions().unwrap().iter()
+            .find(|r| r.global_flags().unwrap().contains(&RegionGlobalFlag::CoarseGrained))
+            .unwrap();
+        let src = [1u32, 2, 3, 4];
+        let mut device = DeviceBuffer::<u32>::new(&runtime, region, 4).unwrap();
+        let mut other = DeviceBuffer::<u32>::new(&runtime, region, 4).unwrap();
+        let mut dst = [0u32; 4];
+        let mut short = [0u32; 3];
+
+        let mut api = Api::current();
+        api.hsa_amd_memory_async_copy = None;
+        api.hsa_memory_copy = counting_copy;
+        with_api(&api, || {
+            // each buffer is borrowed until the end of the scope that copies it
+            copy_scope(&runtime, |scope| {
+                scope.copy(&mut device, agent, &src[..], agent).unwrap().wait().unwrap();
+            });
+            copy_scope(&runtime, |scope| {
+                scope.copy(&mut other, agent, &device, agent).unwrap().wait().unwrap();
+            });
+            copy_scope(&runtime, |scope| {
+                // a leaked copy is still waited for when the scope ends
+                forget(scope.copy(&mut dst[..], agent, &other, agent).unwrap());
+                let err = scope.copy(&mut short[..], agent, &src[..], agent).err().unwrap();
+                assert_eq!(err, ErrorStatus::InvalidArgument);
+            });
+        });
+        assert_eq!(dst, src);
+        assert_eq!(COPIES.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,188 @@
+use super::{Error, ErrorStatus};
+
+pub(crate) const SHT_SYMTAB: u32 = 2;
+pub(crate) const SHT_NOTE: u32 = 7;
+pub(crate) const SHT_NOBITS: u32 = 8;
+pub(crate) const SHT_DYNSYM: u32 = 11;
+
+pub(crate) const STT_OBJECT: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElfSection {
+    pub name: String,
+    pub kind: u32,
+    pub flags: u64,
+    pub address: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub entry_size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+    pub kind: u8,
+    pub binding: u8,
+    pub section: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElfNote<'a> {
+    pub name: &'a str,
+    pub kind: u32,
+    pub desc: &'a [u8],
+}
+
+pub(crate) fn truncated() -> Error {
+    Error::from(ErrorStatus::InvalidCodeObject).with_context("truncated ELF file")
+}
+
+pub(crate) fn bytes_at(bytes: &[u8], offset: u64, len: u64) -> Result<&[u8], Error> {
+    let end = offset.checked_add(len).ok_or_else(truncated)?;
+    if end > bytes.len() as u64 {
+        return Err(truncated());
+    }
+    Ok(&bytes[offset as usize..end as usize])
+}
+
+pub(crate) fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, Error> {
+    let b = bytes_at(bytes, offset as u64, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+pub(crate) fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, Error> {
+    let mut b = [0u8; 4];
+    b.copy_from_slice(bytes_at(bytes, offset as u64, 4)?);
+    Ok(u32::from_le_bytes(b))
+}
+
+pub(crate) fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, Error> {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(bytes_at(bytes, offset as u64, 8)?);
+    Ok(u64::from_le_bytes(b))
+}
+
+fn c_str(bytes: &[u8], offset: u64) -> Result<String, Error> {
+    let rest = bytes_at(bytes, offset, (bytes.len() as u64).saturating_sub(offset))?;
+    let end = rest.iter().position(|&c| c == 0).ok_or_else(truncated)?;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+// only 64-bit little-endian files reach here; the header has been checked by Hsaco
+pub(crate) fn sections(bytes: &[u8]) -> Result<Vec<ElfSection>, Error> {
+    let shoff = u64_at(bytes, 40)?;
+    let shentsize = u16_at(bytes, 58)? as u64;
+    let shnum = u16_at(bytes, 60)? as u64;
+    let shstrndx = u16_at(bytes, 62)? as u64;
+    if shnum == 0 {
+        return Ok(Vec::new());
+    }
+    if shentsize < 64 || shstrndx >= shnum {
+        return Err(Error::from(ErrorStatus::InvalidCodeObject)
+            .with_context("malformed ELF section header table"));
+    }
+    let table = bytes_at(bytes, shoff, shentsize * shnum)?;
+    let header = |i: u64| &table[(i * shentsize) as usize..((i + 1) * shentsize) as usize];
+    let strtab = header(shstrndx);
+    let names = bytes_at(bytes, u64_at(strtab, 24)?, u64_at(strtab, 32)?)?;
+    let mut sections = Vec::with_capacity(shnum as usize);
+    for i in 0..shnum {
+        let h = header(i);
+        sections.push(ElfSection {
+            name: c_str(names, u32_at(h, 0)? as u64)?,
+            kind: u32_at(h, 4)?,
+            flags: u64_at(h, 8)?,
+            address: u64_at(h, 16)?,
+            offset: u64_at(h, 24)?,
+            size: u64_at(h, 32)?,
+            link: u32_at(h, 40)?,
+            entry_size: u64_at(h, 56)?,
+        });
+    }
+    Ok(sections)
+}
+
+pub(crate) fn section_data<'a>(bytes: &'a [u8], section: &ElfSection) -> Result<&'a [u8], Error> {
+    if section.kind == SHT_NOBITS {
+        return Ok(&[]);
+    }
+    bytes_at(bytes, section.offset, section.size)
+}
+
+// .symtab when present, since it is a superset of .dynsym
+pub(crate) fn symbols(bytes: &[u8], sections: &[ElfSection]) -> Result<Vec<ElfSymbol>, Error> {
+    let table = match sections.iter().find(|s| s.kind == SHT_SYMTAB)
+        .or_else(|| sections.iter().find(|s| s.kind == SHT_DYNSYM)) {
+        Some(table) => table,
+        None => return Ok(Vec::new()),
+    };
+    let names = match sections.get(table.link as usize) {
+        Some(strtab) => section_data(bytes, strtab)?,
+        None => return Err(truncated()),
+    };
+    let data = section_data(bytes, table)?;
+    let entry_size = match table.entry_size {
+        0 => 24,
+        n if n < 24 => {
+            return Err(Error::from(ErrorStatus::InvalidCodeObject)
+                .with_context("ELF symbol entries are smaller than Elf64_Sym"));
+        }
+        n => n as usize,
+    };
+    let mut symbols = Vec::new();
+    // entry 0 is the reserved null symbol
+    for entry in data.chunks_exact(entry_size).skip(1) {
+        let info = entry[4];
+        symbols.push(ElfSymbol {
+            name: c_str(names, u32_at(entry, 0)? as u64)?,
+            kind: info & 0xf,
+            binding: info >> 4,
+            section: u16_at(entry, 6)?,
+            value: u64_at(entry, 8)?,
+            size: u64_at(entry, 16)?,
+        });
+    }
+    Ok(symbols)
+}
+
+pub(crate) fn symbol_data<'a>(
+    bytes: &'a [u8],
+    sections: &[ElfSection],
+    symbol: &ElfSymbol,
+) -> Result<&'a [u8], Error> {
+    let section = sections.get(symbol.section as usize).ok_or_else(truncated)?;
+    let offset = symbol.value.checked_sub(section.address).ok_or_else(truncated)?;
+    if offset.saturating_add(symbol.size) > section.size {
+        return Err(truncated());
+    }
+    bytes_at(bytes, section.offset.checked_add(offset).ok_or_else(truncated)?, symbol.size)
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+pub(crate) fn notes(data: &[u8]) -> Result<Vec<ElfNote<'_>>, Error> {
+    let mut notes = Vec::new();
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        let namesz = u32_at(data, offset)? as usize;
+        let descsz = u32_at(data, offset + 4)? as usize;
+        let kind = u32_at(data, offset + 8)?;
+        let name_start = offset + 12;
+        let desc_start = name_start.checked_add(align4(namesz)).ok_or_else(truncated)?;
+        let name = bytes_at(data, name_start as u64, namesz as u64)?;
+        let desc = bytes_at(data, desc_start as u64, descsz as u64)?;
+        let name = name.split(|&c| c == 0).next().unwrap_or(&[]);
+        notes.push(ElfNote {
+            name: ::std::str::from_utf8(name).map_err(|_| truncated())?,
+            kind,
+            desc,
+        });
+        offset = desc_start + align4(descsz);
+    }
+    Ok(notes)
+}
@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::ptr::copy;
+use std::os::raw::c_void;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, write_info, SUCCESS};
+use super::memory::{allocation_size, hsa_memory_allocate, hsa_memory_free, ALLOC_ALIGNMENT,
+                    ALLOC_GRANULE,
+                    COARSE_GRAINED_REGION, FINE_GRAINED_REGION, REGION_SIZE};
+use super::signal::{create, is_ipc, load, retain, subtract, wait_until};
+use super::system::CPU_AGENT;
+
+// the pools mirror the two regions and share their allocations
+const FINE_GRAINED_POOL: MemoryPool = MemoryPool { handle: 1 };
+const COARSE_GRAINED_POOL: MemoryPool = MemoryPool { handle: 2 };
+
+fn pool_region(pool: MemoryPool) -> Option<Region> {
+    if pool == FINE_GRAINED_POOL {
+        Some(FINE_GRAINED_REGION)
+    } else if pool == COARSE_GRAINED_POOL {
+        Some(COARSE_GRAINED_REGION)
+    } else {
+        None
+    }
+}
+
+struct CopyJob {
+    dst: *mut u8,
+    src: *const u8,
+    size: usize,
+    deps: Vec<SignalHandle>,
+    completion: SignalHandle,
+}
+
+unsafe impl Send for CopyJob {}
+
+impl CopyJob {
+    unsafe fn run(self) {
+        wait_until(None, || self.deps.iter().all(|s| load(*s) == 0));
+        copy(self.src, self.dst, self.size);
+        subtract(self.completion, 1);
+    }
+}
+
+pub unsafe extern "C" fn hsa_amd_memory_async_copy(
+    dst: *mut c_void,
+    dst_agent: Agent,
+    src: *const c_void,
+    src_agent: Agent,
+    size: usize,
+    num_dep_signals: u32,
+    dep_signals: *const SignalHandle,
+    completion_signal: SignalHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if dst.is_null() || src.is_null() || (num_dep_signals != 0 && dep_signals.is_null()) {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if dst_agent != CPU_AGENT || src_agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    if completion_signal.handle == 0 {
+        return error(ErrorStatus::InvalidSignal);
+    }
+    let deps = if num_dep_signals == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(dep_signals, num_dep_signals as usize).to_vec()
+    };
+    let job = CopyJob {
+        dst: dst as *mut u8,
+        src: src as *const u8,
+        size,
+        deps,
+        completion: completion_signal,
+    };
+    let spawned = thread::Builder::new()
+        .name("hsa-emulator-copy".to_string())
+        .spawn(move || unsafe { job.run() });
+    match spawned {
+        Ok(_) => SUCCESS,
+        Err(_) => error(ErrorStatus::OutOfResources),
+    }
+}
+
+pub unsafe extern "C" fn hsa_amd_memory_pool_get_info(
+    memory_pool: MemoryPool,
+    attribute: MemoryPoolInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    let fine = match pool_region(memory_pool) {
+        Some(region) => region == FINE_GRAINED_REGION,
+        None => return error(ErrorStatus::InvalidMemoryPool),
+    };
+    let flags = if fine {
+        MemoryPoolGlobalFlag::KernArgInit as u32 | MemoryPoolGlobalFlag::FineGrained as u32
+    } else {
+        MemoryPoolGlobalFlag::CoarseGrained as u32
+    };
+    match attribute {
+        MemoryPoolInfo::Segment => write_info(value, MemoryPoolSegment::Global),
+        MemoryPoolInfo::GlobalFlags => write_info(value, flags),
+        MemoryPoolInfo::Size | MemoryPoolInfo::AllocMaxSize => write_info(value, REGION_SIZE),
+        MemoryPoolInfo::RuntimeAllocAllowed => write_info(value, true),
+        MemoryPoolInfo::RuntimeAllocGranule | MemoryPoolInfo::RuntimeAllocRecGranule => {
+            write_info(value, ALLOC_GRANULE)
+        }
+        MemoryPoolInfo::RuntimeAllocAlignment => write_info(value, ALLOC_ALIGNMENT),
+        MemoryPoolInfo::AccessibleByAll => write_info(value, fine),
+        MemoryPoolInfo::Location => write_info(value, MemoryPoolLocation::CPU),
+    }
+}
+
+pub unsafe extern "C" fn hsa_amd_agent_iterate_memory_pools(
+    agent: Agent,
+    callback: extern "C" fn(MemoryPool, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    for pool in &[FINE_GRAINED_POOL, COARSE_GRAINED_POOL] {
+        let r = callback(*pool, data);
+        if r != SUCCESS {
+            return r;
+        }
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_memory_pool_allocate(
+    memory_pool: MemoryPool,
+    size: usize,
+    flags: u32,
+    ptr: *mut *mut c_void,
+) -> HSAStatus {
+    if flags != 0 {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    match pool_region(memory_pool) {
+        Some(region) => hsa_memory_allocate(region, size, ptr),
+        None => error(ErrorStatus::InvalidMemoryPool),
+    }
+}
+
+pub unsafe extern "C" fn hsa_amd_memory_pool_free(ptr: *mut c_void) -> HSAStatus {
+    hsa_memory_free(ptr)
+}
+
+pub unsafe extern "C" fn hsa_amd_agents_allow_access(
+    num_agents: u32,
+    agents: *const Agent,
+    flags: *const u32,
+    ptr: *const c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if num_agents == 0 || agents.is_null() || !flags.is_null() || ptr.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if slice::from_raw_parts(agents, num_agents as usize).iter().any(|a| *a != CPU_AGENT) {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_memory_pool_can_migrate(
+    src_memory_pool: MemoryPool,
+    dst_memory_pool: MemoryPool,
+    result: *mut bool,
+) -> HSAStatus {
+    if pool_region(src_memory_pool).is_none() || pool_region(dst_memory_pool).is_none() {
+        return error(ErrorStatus::InvalidMemoryPool);
+    }
+    if result.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    *result = true;
+    SUCCESS
+}
+
+// every pool is host memory, so there is nothing to move
+pub unsafe extern "C" fn hsa_amd_memory_migrate(
+    ptr: *const c_void,
+    memory_pool: MemoryPool,
+    flags: u32,
+) -> HSAStatus {
+    if pool_region(memory_pool).is_none() {
+        return error(ErrorStatus::InvalidMemoryPool);
+    }
+    if ptr.is_null() || flags != 0 {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_agent_memory_pool_get_info(
+    agent: Agent,
+    memory_pool: MemoryPool,
+    attribute: AgentMemoryPoolInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    let access = match pool_region(memory_pool) {
+        Some(FINE_GRAINED_REGION) => MemoryPoolAccess::AllowedByDefault,
+        Some(_) => MemoryPoolAccess::DisallowedByDefault,
+        None => return error(ErrorStatus::InvalidMemoryPool),
+    };
+    match attribute {
+        AgentMemoryPoolInfo::Access => write_info(value, access),
+        AgentMemoryPoolInfo::NumLinkHops => write_info(value, 0u32),
+    }
+}
+
+// every "process" shares this one, so an IPC handle only carries an id into these tables
+static IPC_MEMORY: Mutex<BTreeMap<u64, (usize, usize)>> = Mutex::new(BTreeMap::new());
+static IPC_ATTACHED: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+static IPC_SIGNALS: Mutex<BTreeMap<u64, SignalHandle>> = Mutex::new(BTreeMap::new());
+static NEXT_IPC_ID: AtomicU64 = AtomicU64::new(1);
+
+fn new_ipc_handle() -> (u64, IpcHandle) {
+    let id = NEXT_IPC_ID.fetch_add(1, Ordering::Relaxed);
+    let mut handle = IpcHandle { handle: [0; 8] };
+    handle.handle[0] = id as u32;
+    handle.handle[1] = (id >> 32) as u32;
+    (id, handle)
+}
+
+fn ipc_id(handle: &IpcHandle) -> u64 {
+    handle.handle[0] as u64 | (handle.handle[1] as u64) << 32
+}
+
+// runs the release under the table lock so that an attach cannot revive a signal that
+// is about to be freed
+pub(super) fn release_ipc_signal<F: FnOnce() -> bool>(signal: SignalHandle, release: F) -> bool {
+    let mut signals = IPC_SIGNALS.lock().unwrap();
+    let last = release();
+    if last {
+        signals.retain(|_, s| *s != signal);
+    }
+    last
+}
+
+pub unsafe extern "C" fn hsa_amd_signal_create(
+    initial_value: SignalValue,
+    _num_consumers: u32,
+    _consumers: *const Agent,
+    attributes: u64,
+    signal: *mut SignalHandle,
+) -> HSAStatus {
+    let known = AmdSignalAttribute::AmdGpuOnly as u64 | AmdSignalAttribute::Ipc as u64;
+    if attributes & !known != 0 {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    create(initial_value, attributes & AmdSignalAttribute::Ipc as u64 != 0, signal)
+}
+
+pub unsafe extern "C" fn hsa_amd_ipc_memory_create(
+    ptr: *mut c_void,
+    len: usize,
+    handle: *mut IpcHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if handle.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    match allocation_size(ptr) {
+        Some(size) if len != 0 && len <= size => (),
+        _ => return error(ErrorStatus::InvalidArgument),
+    }
+    let (id, h) = new_ipc_handle();
+    IPC_MEMORY.lock().unwrap().insert(id, (ptr as usize, len));
+    *handle = h;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_ipc_memory_attach(
+    handle: *const IpcHandle,
+    len: usize,
+    num_agents: u32,
+    mapping_agents: *const Agent,
+    mapped_ptr: *mut *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if handle.is_null() || mapped_ptr.is_null() || (num_agents != 0 && mapping_agents.is_null()) {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if num_agents != 0 &&
+        slice::from_raw_parts(mapping_agents, num_agents as usize).iter().any(|a| *a != CPU_AGENT)
+    {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    let ptr = match IPC_MEMORY.lock().unwrap().get(&ipc_id(&*handle)) {
+        Some(&(ptr, size)) if len <= size && allocation_size(ptr as *const c_void).is_some() => ptr,
+        _ => return error(ErrorStatus::InvalidArgument),
+    };
+    *IPC_ATTACHED.lock().unwrap().entry(ptr).or_insert(0) += 1;
+    *mapped_ptr = ptr as *mut c_void;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_ipc_memory_detach(mapped_ptr: *mut c_void) -> HSAStatus {
+    let mut attached = IPC_ATTACHED.lock().unwrap();
+    match attached.get_mut(&(mapped_ptr as usize)) {
+        Some(count) if *count > 1 => *count -= 1,
+        Some(_) => {
+            attached.remove(&(mapped_ptr as usize));
+        }
+        None => return error(ErrorStatus::InvalidArgument),
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_ipc_signal_create(
+    signal: SignalHandle,
+    handle: *mut IpcHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if handle.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if signal.handle == 0 || !is_ipc(signal) {
+        return error(ErrorStatus::InvalidSignal);
+    }
+    let (id, h) = new_ipc_handle();
+    IPC_SIGNALS.lock().unwrap().insert(id, signal);
+    *handle = h;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_amd_ipc_signal_attach(
+    handle: *const IpcHandle,
+    signal: *mut SignalHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if handle.is_null() || signal.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    match IPC_SIGNALS.lock().unwrap().get(&ipc_id(&*handle)) {
+        Some(&s) => {
+            retain(s);
+            *signal = s;
+            SUCCESS
+        }
+        None => error(ErrorStatus::InvalidArgument),
+    }
+}
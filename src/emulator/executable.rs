@@ -0,0 +1,470 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::ptr::copy_nonoverlapping;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, write_info, SUCCESS};
+use super::kernel;
+use super::system::CPU_AGENT;
+
+struct ExecutableObject {
+    profile: Profile,
+    default_float_rounding_mode: DefaultFloatRoundingMode,
+    frozen: AtomicBool,
+}
+
+unsafe fn get<'a>(executable: Executable) -> Option<&'a ExecutableObject> {
+    (executable.handle as *const ExecutableObject).as_ref()
+}
+
+pub unsafe extern "C" fn hsa_executable_create_alt(
+    profile: Profile,
+    default_float_rounding_mode: DefaultFloatRoundingMode,
+    _options: *const c_char,
+    executable: *mut Executable,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if executable.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let obj = Box::new(ExecutableObject {
+        profile,
+        default_float_rounding_mode,
+        frozen: AtomicBool::new(false),
+    });
+    *executable = Executable { handle: Box::into_raw(obj) as u64 };
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_destroy(executable: Executable) -> HSAStatus {
+    if executable.handle == 0 {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    drop(Box::from_raw(executable.handle as *mut ExecutableObject));
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_freeze(
+    executable: Executable,
+    _options: *const c_char,
+) -> HSAStatus {
+    match get(executable) {
+        Some(obj) => {
+            if obj.frozen.swap(true, Release) {
+                error(ErrorStatus::FrozenExecutable)
+            } else {
+                SUCCESS
+            }
+        }
+        None => error(ErrorStatus::InvalidExecutable),
+    }
+}
+
+pub unsafe extern "C" fn hsa_executable_get_info(
+    executable: Executable,
+    attribute: ExecutableInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    let obj = match get(executable) {
+        Some(obj) => obj,
+        None => return error(ErrorStatus::InvalidExecutable),
+    };
+    match attribute {
+        ExecutableInfo::Profile => write_info(value, obj.profile),
+        ExecutableInfo::State => {
+            if obj.frozen.load(Acquire) {
+                write_info(value, ExecutableState::Frozen)
+            } else {
+                write_info(value, ExecutableState::Unfrozen)
+            }
+        }
+        ExecutableInfo::DefaultFloatRoundingMode => {
+            write_info(value, obj.default_float_rounding_mode)
+        }
+    }
+}
+
+// the emulator cannot run device code; loading only checks that the image is an ELF
+// file, while symbols still come from registered kernels
+struct ReaderObject {
+    bytes: Vec<u8>,
+}
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+static NEXT_LOADED_CODE_OBJECT: AtomicU64 = AtomicU64::new(1);
+
+unsafe fn create_reader(bytes: Vec<u8>, code_object_reader: *mut CodeObjectReader) -> HSAStatus {
+    let obj = Box::new(ReaderObject { bytes });
+    *code_object_reader = CodeObjectReader { handle: Box::into_raw(obj) as u64 };
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_code_object_reader_create_from_memory(
+    code_object: *const c_void,
+    size: usize,
+    code_object_reader: *mut CodeObjectReader,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if code_object.is_null() || size == 0 || code_object_reader.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    create_reader(slice::from_raw_parts(code_object as *const u8, size).to_vec(), code_object_reader)
+}
+
+#[cfg(unix)]
+pub unsafe extern "C" fn hsa_code_object_reader_create_from_file(
+    file: HSAFile,
+    code_object_reader: *mut CodeObjectReader,
+) -> HSAStatus {
+    use std::fs::File;
+    use std::io::Read;
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if file < 0 || code_object_reader.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    // the caller keeps ownership of the descriptor
+    let mut f = ManuallyDrop::new(File::from_raw_fd(file));
+    let mut bytes = Vec::new();
+    if f.read_to_end(&mut bytes).is_err() {
+        return error(ErrorStatus::InvalidFile);
+    }
+    create_reader(bytes, code_object_reader)
+}
+
+#[cfg(not(unix))]
+pub unsafe extern "C" fn hsa_code_object_reader_create_from_file(
+    _file: HSAFile,
+    _code_object_reader: *mut CodeObjectReader,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidFile)
+}
+
+pub unsafe extern "C" fn hsa_code_object_reader_destroy(
+    code_object_reader: CodeObjectReader,
+) -> HSAStatus {
+    if code_object_reader.handle == 0 {
+        return error(ErrorStatus::InvalidCodeObjectReader);
+    }
+    drop(Box::from_raw(code_object_reader.handle as *mut ReaderObject));
+    SUCCESS
+}
+
+unsafe fn load(
+    executable: Executable,
+    code_object_reader: CodeObjectReader,
+    loaded_code_object: *mut LoadedCodeObject,
+) -> HSAStatus {
+    let obj = match get(executable) {
+        Some(obj) => obj,
+        None => return error(ErrorStatus::InvalidExecutable),
+    };
+    let reader = match (code_object_reader.handle as *const ReaderObject).as_ref() {
+        Some(reader) => reader,
+        None => return error(ErrorStatus::InvalidCodeObjectReader),
+    };
+    if obj.frozen.load(Acquire) {
+        return error(ErrorStatus::FrozenExecutable);
+    }
+    if !reader.bytes.starts_with(ELF_MAGIC) {
+        return error(ErrorStatus::InvalidCodeObject);
+    }
+    if !loaded_code_object.is_null() {
+        let handle = NEXT_LOADED_CODE_OBJECT.fetch_add(1, Relaxed);
+        *loaded_code_object = LoadedCodeObject { handle };
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_load_program_code_object(
+    executable: Executable,
+    code_object_reader: CodeObjectReader,
+    _options: *const c_char,
+    loaded_code_object: *mut LoadedCodeObject,
+) -> HSAStatus {
+    load(executable, code_object_reader, loaded_code_object)
+}
+
+pub unsafe extern "C" fn hsa_executable_load_agent_code_object(
+    executable: Executable,
+    agent: Agent,
+    code_object_reader: CodeObjectReader,
+    _options: *const c_char,
+    loaded_code_object: *mut LoadedCodeObject,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    load(executable, code_object_reader, loaded_code_object)
+}
+
+pub unsafe extern "C" fn hsa_executable_load_code_object(
+    _executable: Executable,
+    _agent: Agent,
+    _code_object: CodeObject,
+    _options: *const c_char,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidCodeObject)
+}
+
+pub unsafe extern "C" fn hsa_executable_get_symbol(
+    executable: Executable,
+    _module_name: *const c_char,
+    symbol_name: *const c_char,
+    agent: Agent,
+    _call_convention: i32,
+    symbol: *mut ExecutableSymbol,
+) -> HSAStatus {
+    if get(executable).is_none() {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    if symbol_name.is_null() || symbol.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let name = CStr::from_ptr(symbol_name).to_string_lossy();
+    match kernel::find_by_name(&name) {
+        Some(handle) => {
+            *symbol = ExecutableSymbol { handle };
+            SUCCESS
+        }
+        None => error(ErrorStatus::InvalidSymbolName),
+    }
+}
+
+// every registered kernel is an agent symbol of the CPU agent; there are no program
+// symbols, so a null agent finds nothing
+unsafe fn get_symbol_by_name(
+    executable: Executable,
+    name: *const c_char,
+    agent: *const Agent,
+    symbol: *mut ExecutableSymbol,
+) -> HSAStatus {
+    if get(executable).is_none() {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    if name.is_null() || symbol.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    match agent.as_ref() {
+        Some(&agent) if agent != CPU_AGENT => return error(ErrorStatus::InvalidAgent),
+        Some(_) => (),
+        None => return error(ErrorStatus::InvalidSymbolName),
+    }
+    match kernel::find_by_name(&CStr::from_ptr(name).to_string_lossy()) {
+        Some(handle) => {
+            *symbol = ExecutableSymbol { handle };
+            SUCCESS
+        }
+        None => error(ErrorStatus::InvalidSymbolName),
+    }
+}
+
+pub unsafe extern "C" fn hsa_executable_get_symbol_by_name(
+    executable: Executable,
+    symbol_name: *const c_char,
+    agent: *const Agent,
+    symbol: *mut ExecutableSymbol,
+) -> HSAStatus {
+    get_symbol_by_name(executable, symbol_name, agent, symbol)
+}
+
+pub unsafe extern "C" fn hsa_executable_get_symbol_by_linker_name(
+    executable: Executable,
+    linker_name: *const c_char,
+    agent: *const Agent,
+    symbol: *mut ExecutableSymbol,
+) -> HSAStatus {
+    get_symbol_by_name(executable, linker_name, agent, symbol)
+}
+
+unsafe fn write_name(value: *mut c_void, name: &str) -> HSAStatus {
+    if value.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    copy_nonoverlapping(name.as_ptr(), value as *mut u8, name.len());
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_symbol_get_info(
+    executable_symbol: ExecutableSymbol,
+    attribute: ExecutableSymbolInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    let kernel = match kernel::find(executable_symbol.handle) {
+        Some(k) => k,
+        None => return error(ErrorStatus::InvalidExecutableSymbol),
+    };
+    match attribute {
+        ExecutableSymbolInfo::Type => write_info(value, SymbolKind::Kernel),
+        ExecutableSymbolInfo::NameLength |
+        ExecutableSymbolInfo::LinkerNameLength => write_info(value, kernel.name.len() as u32),
+        ExecutableSymbolInfo::Name |
+        ExecutableSymbolInfo::LinkerName => write_name(value, &kernel.name),
+        ExecutableSymbolInfo::ModuleNameLength => write_info(value, 0u32),
+        ExecutableSymbolInfo::ModuleName => SUCCESS,
+        ExecutableSymbolInfo::Agent => write_info(value, CPU_AGENT),
+        ExecutableSymbolInfo::VariableAddress => write_info(value, 0u64),
+        ExecutableSymbolInfo::Linkage => write_info(value, SymbolKindLinkage::Program),
+        ExecutableSymbolInfo::IsDefinition => write_info(value, true),
+        ExecutableSymbolInfo::VariableAllocation => write_info(value, VariableAllocation::Agent),
+        ExecutableSymbolInfo::VariableSegment => write_info(value, VariableSegment::Global),
+        ExecutableSymbolInfo::VariableAlignment |
+        ExecutableSymbolInfo::VariableSize => write_info(value, 0u32),
+        ExecutableSymbolInfo::VariableIsConst => write_info(value, false),
+        ExecutableSymbolInfo::KernelObject => write_info(value, executable_symbol.handle),
+        ExecutableSymbolInfo::KernelKernArgSegmentSize => write_info(value, kernel.kernarg_size),
+        ExecutableSymbolInfo::KernelKernArgSegmentAlignment => {
+            write_info(value, kernel.kernarg_alignment)
+        }
+        ExecutableSymbolInfo::KernelGroupSegmentSize |
+        ExecutableSymbolInfo::KernelPrivateSegmentSize => write_info(value, 0u32),
+        ExecutableSymbolInfo::KernelDynamicCallstack => write_info(value, false),
+        ExecutableSymbolInfo::KernelCallConvertion |
+        ExecutableSymbolInfo::IndirectFunctionCallConvertion => write_info(value, 0u32),
+        ExecutableSymbolInfo::IndirectFunctionObject => write_info(value, 0usize),
+    }
+}
+
+pub unsafe extern "C" fn hsa_executable_iterate_agent_symbols(
+    executable: Executable,
+    agent: Agent,
+    callback: extern "C" fn(Executable, Agent, ExecutableSymbol, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if get(executable).is_none() {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    for handle in 1..kernel::count() + 1 {
+        let r = callback(executable, agent, ExecutableSymbol { handle }, data);
+        if r != SUCCESS {
+            return r;
+        }
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_iterate_program_symbols(
+    executable: Executable,
+    _callback: extern "C" fn(Executable, ExecutableSymbol, *mut c_void) -> HSAStatus,
+    _data: *mut c_void,
+) -> HSAStatus {
+    if get(executable).is_none() {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_executable_iterate_symbols(
+    executable: Executable,
+    callback: extern "C" fn(Executable, ExecutableSymbol, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if get(executable).is_none() {
+        return error(ErrorStatus::InvalidExecutable);
+    }
+    for handle in 1..kernel::count() + 1 {
+        let r = callback(executable, ExecutableSymbol { handle }, data);
+        if r != SUCCESS {
+            return r;
+        }
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_code_object_destroy(_code_object: CodeObject) -> HSAStatus {
+    error(ErrorStatus::InvalidCodeObject)
+}
+
+pub unsafe extern "C" fn hsa_code_object_get_info(
+    _code_object: CodeObject,
+    _attribute: CodeObjectInfo,
+    _value: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidCodeObject)
+}
+
+pub unsafe extern "C" fn hsa_ext_code_object_writer_create_from_memory(
+    _memory_allocate: extern "C" fn(usize, usize, *mut *mut c_void, *mut c_void) -> HSAStatus,
+    _data: *mut c_void,
+    _code_object_writer: *mut ExtCodeObjectWriterHandle,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidCodeObjectWriter)
+}
+
+pub unsafe extern "C" fn hsa_ext_code_object_writer_destroy(
+    _code_object_writer: ExtCodeObjectWriterHandle,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidCodeObjectWriter)
+}
+
+pub unsafe extern "C" fn hsa_ext_program_create(
+    _machine_model: MachineModel,
+    _profile: Profile,
+    _default_float_rouding_mode: DefaultFloatRoundingMode,
+    _options: *const c_char,
+    _program: *mut ExtProgramHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    error(ErrorStatus::InvalidProgram)
+}
+
+pub unsafe extern "C" fn hsa_ext_program_destroy(_program: ExtProgramHandle) -> HSAStatus {
+    error(ErrorStatus::InvalidProgram)
+}
+
+pub unsafe extern "C" fn hsa_ext_program_add_module(
+    _program: ExtProgramHandle,
+    _module: ExtModule,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidProgram)
+}
+
+pub unsafe extern "C" fn hsa_ext_program_get_info(
+    _program: ExtProgramHandle,
+    _attribute: ExtProgramInfo,
+    _value: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidProgram)
+}
+
+pub unsafe extern "C" fn hsa_ext_agent_code_object_finalize(
+    _program: ExtProgramHandle,
+    _isa: ISA,
+    _options: *const c_char,
+    _code_object_writer: &ExtCodeObjectWriterHandle,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidProgram)
+}
+
+pub unsafe extern "C" fn hsa_ext_program_finalize(
+    _program: ExtProgramHandle,
+    _isa: ISA,
+    _call_convention: i32,
+    _control_directives: ExtControlDirectives,
+    _options: *const c_char,
+    _code_object_type: CodeObjectType,
+    _code_object: *mut CodeObject,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidProgram)
+}
@@ -0,0 +1,196 @@
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+
+use native::*;
+use kernarg::KernelArgs;
+use ErrorStatus;
+use super::memory;
+
+pub struct WorkItem {
+    pub dims: u8,
+    pub grid_size: [u32; 3],
+    pub workgroup_size: [u16; 3],
+    pub workgroup_id: [u32; 3],
+    pub local_id: [u16; 3],
+}
+
+impl WorkItem {
+    pub fn absolute_id(&self, dim: usize) -> u32 {
+        self.workgroup_id[dim] * self.workgroup_size[dim] as u32 + self.local_id[dim] as u32
+    }
+
+    pub fn flat_absolute_id(&self) -> u64 {
+        let x = self.absolute_id(0) as u64;
+        let y = self.absolute_id(1) as u64;
+        let z = self.absolute_id(2) as u64;
+        let gx = self.grid_size[0] as u64;
+        let gy = self.grid_size[1] as u64;
+        x + y * gx + z * gx * gy
+    }
+}
+
+type KernelFn = Box<dyn Fn(&WorkItem, *const c_void) + Send + Sync>;
+
+pub(super) struct KernelEntry {
+    pub name: String,
+    pub kernarg_size: u32,
+    pub kernarg_alignment: u32,
+    // the closure reads the whole `A`, trailing padding included
+    read_size: usize,
+    func: KernelFn,
+}
+
+static KERNELS: RwLock<Vec<Arc<KernelEntry>>> = RwLock::new(Vec::new());
+
+// KernelArgs types are valid for any bytes the dispatch leaves in the kernarg segment
+pub fn register_kernel<A, F>(name: &str, kernel: F) -> u64
+where
+    A: KernelArgs + 'static,
+    F: Fn(&WorkItem, &A) + Send + Sync + 'static,
+{
+    let entry = KernelEntry {
+        name: name.to_string(),
+        kernarg_size: A::SIZE as u32,
+        kernarg_alignment: A::ALIGNMENT as u32,
+        read_size: size_of::<A>(),
+        func: Box::new(move |item, args| kernel(item, unsafe { &*(args as *const A) })),
+    };
+    let mut kernels = KERNELS.write().unwrap();
+    kernels.push(Arc::new(entry));
+    kernels.len() as u64
+}
+
+pub(super) fn find(kernel_object: u64) -> Option<Arc<KernelEntry>> {
+    if kernel_object == 0 {
+        return None;
+    }
+    KERNELS.read().unwrap().get(kernel_object as usize - 1).cloned()
+}
+
+pub(super) fn find_by_name(name: &str) -> Option<u64> {
+    let kernels = KERNELS.read().unwrap();
+    kernels.iter().rposition(|k| k.name == name).map(|i| i as u64 + 1)
+}
+
+pub(super) fn count() -> u64 {
+    KERNELS.read().unwrap().len() as u64
+}
+
+pub(super) unsafe fn dispatch(packet: &KernelDispatchPacket) -> Result<(), ErrorStatus> {
+    let kernel = match find(packet.kernel_object) {
+        Some(k) => k,
+        None => return Err(ErrorStatus::Exception),
+    };
+    // the closure reads its arguments as a reference, so they must be readable and aligned
+    let kernarg = packet.kernarg_address;
+    let size = kernel.read_size;
+    if size != 0 &&
+        (kernarg.is_null() || !(kernarg as usize).is_multiple_of(kernel.kernarg_alignment as usize) ||
+            !memory::contains(kernarg, size))
+    {
+        return Err(ErrorStatus::InvalidArgument);
+    }
+    let dims = (packet.setup >> (KernelDispatchPacketSetup::Dimensions as u16)) as u8 & 0x3;
+    let grid_size = [packet.grid_size_x, packet.grid_size_y, packet.grid_size_z];
+    let workgroup_size = [
+        packet.workgroup_size_x.max(1),
+        packet.workgroup_size_y.max(1),
+        packet.workgroup_size_z.max(1),
+    ];
+    let mut groups = [0u32; 3];
+    for i in 0..3 {
+        let wg = workgroup_size[i] as u32;
+        groups[i] = grid_size[i].div_ceil(wg);
+    }
+    let mut item = WorkItem {
+        dims,
+        grid_size,
+        workgroup_size,
+        workgroup_id: [0; 3],
+        local_id: [0; 3],
+    };
+    // a panicking kernel fails the queue like a hardware exception would
+    catch_unwind(AssertUnwindSafe(|| {
+        for gz in 0..groups[2] {
+            for gy in 0..groups[1] {
+                for gx in 0..groups[0] {
+                    item.workgroup_id = [gx, gy, gz];
+                    for lz in 0..workgroup_size[2] {
+                        for ly in 0..workgroup_size[1] {
+                            for lx in 0..workgroup_size[0] {
+                                item.local_id = [lx, ly, lz];
+                                if item.absolute_id(0) < grid_size[0] &&
+                                    item.absolute_id(1) < grid_size[1] &&
+                                    item.absolute_id(2) < grid_size[2]
+                                {
+                                    (kernel.func)(&item, kernarg);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })).map_err(|_| ErrorStatus::Exception)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::c_void;
+    use std::ptr::{null, null_mut};
+    use std::thread;
+
+    use native::*;
+    use queue::Queue;
+    use super::{register_kernel, WorkItem};
+    use ErrorStatus;
+
+    fn queue_error(kernel_object: u64, kernarg: *const u64) -> ErrorStatus {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let packet = KernelDispatchPacket::builder(kernel_object)
+            .grid_size(&[4])
+            .workgroup_size(&[4])
+            .kernarg_address(kernarg)
+            .build()
+            .unwrap();
+        let completion = queue.dispatch(packet).unwrap();
+        let error = loop {
+            match queue.error() {
+                Some(e) => break e,
+                None => thread::yield_now(),
+            }
+        };
+        drop(completion);
+        error.kind().unwrap()
+    }
+
+    #[test]
+    fn bad_kernargs_and_panics_fail_the_queue() {
+        let _runtime = ::init().unwrap();
+        let checked = register_kernel("&__kernel_checked", |_: &WorkItem, _: &[u64; 2]| {});
+        let panicking = register_kernel("&__kernel_panicking", |item: &WorkItem, _: &u64| {
+            assert!(item.absolute_id(0) < 2, "work-item out of range");
+        });
+        let region = Agent::from_device_type(DeviceType::CPU).unwrap()[0]
+            .kernarg_global_regions()
+            .unwrap()[0];
+        let mut p: *mut c_void = null_mut();
+        assert_eq!(unsafe { hsa_memory_allocate(region, 16, &mut p) }, 0);
+        let kernarg = p as *const u64;
+
+        let on_stack = 0u64;
+        assert_eq!(queue_error(checked, &on_stack), ErrorStatus::InvalidArgument);
+        assert_eq!(queue_error(checked, null()), ErrorStatus::InvalidArgument);
+        let misaligned = unsafe { (kernarg as *const u8).add(4) } as *const u64;
+        assert_eq!(queue_error(checked, misaligned), ErrorStatus::InvalidArgument);
+        // allocations are whole 4096-byte granules, so this runs one word past the end
+        let past_end = unsafe { kernarg.add(511) };
+        assert_eq!(queue_error(checked, past_end), ErrorStatus::InvalidArgument);
+        assert_eq!(queue_error(panicking, kernarg), ErrorStatus::Exception);
+        unsafe { hsa_memory_free(p) };
+    }
+}
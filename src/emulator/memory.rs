@@ -0,0 +1,160 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+use std::ptr::copy;
+use std::sync::Mutex;
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, round_up, write_info, SUCCESS};
+use super::system::CPU_AGENT;
+
+pub(super) const FINE_GRAINED_REGION: Region = Region { handle: 1 };
+pub(super) const COARSE_GRAINED_REGION: Region = Region { handle: 2 };
+
+pub(super) const REGION_SIZE: usize = 1 << 30;
+pub(super) const ALLOC_GRANULE: usize = 4096;
+pub(super) const ALLOC_ALIGNMENT: usize = 4096;
+
+static ALLOCATIONS: Mutex<BTreeMap<usize, Layout>> = Mutex::new(BTreeMap::new());
+
+pub unsafe extern "C" fn hsa_region_get_info(
+    region: Region,
+    attribute: RegionInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    let flags = if region == FINE_GRAINED_REGION {
+        RegionGlobalFlag::KernArg as u32 | RegionGlobalFlag::FineGrained as u32
+    } else if region == COARSE_GRAINED_REGION {
+        RegionGlobalFlag::CoarseGrained as u32
+    } else {
+        return error(ErrorStatus::InvalidRegion);
+    };
+    match attribute {
+        RegionInfo::Segment => write_info(value, RegionSegment::Global),
+        RegionInfo::GlobalFlags => write_info(value, flags),
+        RegionInfo::Size | RegionInfo::AllocMaxSize => write_info(value, REGION_SIZE),
+        RegionInfo::AllocMaxPrivateWorkgroupSize => write_info(value, 0u32),
+        RegionInfo::RuntimeAllocAllowed => write_info(value, true),
+        RegionInfo::RuntimeAllocGranule => write_info(value, ALLOC_GRANULE),
+        RegionInfo::RuntimeAllocAlignment => write_info(value, ALLOC_ALIGNMENT),
+        RegionInfo::HostAccessible => write_info(value, region == FINE_GRAINED_REGION),
+    }
+}
+
+pub unsafe extern "C" fn hsa_agent_iterate_regions(
+    agent: Agent,
+    callback: extern "C" fn(Region, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    for region in &[FINE_GRAINED_REGION, COARSE_GRAINED_REGION] {
+        let r = callback(*region, data);
+        if r != SUCCESS {
+            return r;
+        }
+    }
+    SUCCESS
+}
+
+pub(super) fn allocation_size(ptr: *const c_void) -> Option<usize> {
+    ALLOCATIONS.lock().unwrap().get(&(ptr as usize)).map(|layout| layout.size())
+}
+
+// whether [ptr, ptr + size) lies inside one live allocation
+pub(super) fn contains(ptr: *const c_void, size: usize) -> bool {
+    let start = ptr as usize;
+    let allocations = ALLOCATIONS.lock().unwrap();
+    match allocations.range(..=start).next_back() {
+        Some((&base, layout)) => start.checked_add(size).is_some_and(|end| end <= base + layout.size()),
+        None => false,
+    }
+}
+
+pub unsafe extern "C" fn hsa_memory_allocate(
+    region: Region,
+    size: usize,
+    ptr: *mut *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if region != FINE_GRAINED_REGION && region != COARSE_GRAINED_REGION {
+        return error(ErrorStatus::InvalidRegion);
+    }
+    if size == 0 || ptr.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if size > REGION_SIZE {
+        return error(ErrorStatus::InvalidAllocation);
+    }
+    let layout = match Layout::from_size_align(round_up(size, ALLOC_GRANULE), ALLOC_ALIGNMENT) {
+        Ok(layout) => layout,
+        Err(_) => return error(ErrorStatus::InvalidAllocation),
+    };
+    let p = alloc_zeroed(layout);
+    if p.is_null() {
+        return error(ErrorStatus::OutOfResources);
+    }
+    ALLOCATIONS.lock().unwrap().insert(p as usize, layout);
+    *ptr = p as *mut c_void;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_memory_free(ptr: *mut c_void) -> HSAStatus {
+    if ptr.is_null() {
+        return SUCCESS;
+    }
+    match ALLOCATIONS.lock().unwrap().remove(&(ptr as usize)) {
+        Some(layout) => {
+            dealloc(ptr as *mut u8, layout);
+            SUCCESS
+        }
+        None => error(ErrorStatus::InvalidArgument),
+    }
+}
+
+pub unsafe extern "C" fn hsa_memory_copy(
+    dst: *mut c_void,
+    src: *const c_void,
+    size: usize,
+) -> HSAStatus {
+    if dst.is_null() || src.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    copy(src as *const u8, dst as *mut u8, size);
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_memory_assign_agent(
+    ptr: *mut c_void,
+    agent: Agent,
+    _access: AccessPermission,
+) -> HSAStatus {
+    if ptr.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_memory_register(ptr: *mut c_void, size: usize) -> HSAStatus {
+    if ptr.is_null() || size == 0 {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_memory_deregister(ptr: *mut c_void, _size: usize) -> HSAStatus {
+    if ptr.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    SUCCESS
+}
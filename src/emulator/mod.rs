@@ -0,0 +1,60 @@
+use std::os::raw::c_void;
+use std::ptr::write_unaligned;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use native::HSAStatus;
+use ErrorStatus;
+
+mod system;
+mod signal;
+mod queue;
+mod memory;
+mod executable;
+mod kernel;
+mod amd;
+
+pub use self::kernel::{register_kernel, WorkItem};
+
+pub(crate) mod runtime {
+    pub use super::system::*;
+    pub use super::signal::*;
+    pub use super::queue::*;
+    pub use super::memory::*;
+    pub use super::executable::*;
+    pub use super::amd::*;
+}
+
+const SUCCESS: HSAStatus = 0;
+
+static INIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn error(e: ErrorStatus) -> HSAStatus {
+    e as HSAStatus
+}
+
+fn initialized() -> bool {
+    INIT_COUNT.load(Ordering::SeqCst) > 0
+}
+
+unsafe fn write_info<T>(value: *mut c_void, x: T) -> HSAStatus {
+    if value.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    write_unaligned(value as *mut T, x);
+    SUCCESS
+}
+
+unsafe fn write_fixed_str(value: *mut c_void, s: &str, len: usize) -> HSAStatus {
+    if value.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let dst = value as *mut u8;
+    let n = s.len().min(len - 1);
+    std::ptr::copy_nonoverlapping(s.as_ptr(), dst, n);
+    std::ptr::write_bytes(dst.add(n), 0, len - n);
+    SUCCESS
+}
+
+fn round_up(x: usize, align: usize) -> usize {
+    x.div_ceil(align) * align
+}
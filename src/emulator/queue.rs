@@ -0,0 +1,325 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::mem::MaybeUninit;
+use std::os::raw::c_void;
+use std::ptr::{addr_of_mut, null, null_mut, read};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, SUCCESS};
+use super::kernel;
+use super::memory::{COARSE_GRAINED_REGION, FINE_GRAINED_REGION};
+use super::signal::{hsa_signal_create, hsa_signal_destroy, load, notify_all, subtract,
+                    wait_until};
+use super::system::{CPU_AGENT, QUEUE_MAX_SIZE, QUEUE_MIN_SIZE};
+
+const PACKET_SIZE: usize = 64;
+
+static QUEUE_ID: AtomicU64 = AtomicU64::new(0);
+
+#[repr(C)]
+struct QueueObject {
+    handle: QueueHandle,
+    write_index: AtomicU64,
+    read_index: AtomicU64,
+    ring_layout: Layout,
+    owns_doorbell: bool,
+    callback: Option<extern "C" fn(HSAStatus, *const QueueHandle, *mut c_void)>,
+    data: *mut c_void,
+    active: AtomicBool,
+    stop: AtomicBool,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct QueuePtr(*const QueueObject);
+
+unsafe impl Send for QueuePtr {}
+
+impl QueueObject {
+    fn slot(&self, index: u64) -> *mut u8 {
+        let i = (index % self.handle.size as u64) as usize;
+        unsafe { (self.handle.base_address as *mut u8).add(i * PACKET_SIZE) }
+    }
+}
+
+unsafe fn get<'a>(queue: *const QueueHandle) -> &'a QueueObject {
+    &*(queue as *const QueueObject)
+}
+
+unsafe fn header<'a>(slot: *mut u8) -> &'a AtomicU16 {
+    &*(slot as *const AtomicU16)
+}
+
+fn packet_type(header: u16) -> u16 {
+    (header >> (PacketHeader::Type as u16)) & 0xff
+}
+
+fn run(queue: QueuePtr) {
+    let queue = unsafe { &*queue.0 };
+    loop {
+        let index = queue.read_index.load(Acquire);
+        let slot = queue.slot(index);
+        wait_until(None, || {
+            queue.stop.load(Acquire) ||
+                (queue.active.load(Acquire) &&
+                     packet_type(unsafe { header(slot) }.load(Acquire)) !=
+                         PacketType::Invalid as u16)
+        });
+        if queue.stop.load(Acquire) {
+            break;
+        }
+        let completion_signal = match unsafe { process(queue, slot) } {
+            Ok(signal) => signal,
+            Err(status) => {
+                // the queue is left in an error state; nothing further is processed
+                if let Some(callback) = queue.callback {
+                    callback(status as HSAStatus, &queue.handle, queue.data);
+                }
+                break;
+            }
+        };
+        unsafe { header(slot) }.store(
+            (PacketType::Invalid as u16) << (PacketHeader::Type as u16),
+            Release,
+        );
+        queue.read_index.store(index + 1, Release);
+        if completion_signal.handle != 0 {
+            unsafe { subtract(completion_signal, 1) };
+        } else {
+            notify_all();
+        }
+    }
+}
+
+unsafe fn process(queue: &QueueObject, slot: *mut u8) -> Result<SignalHandle, ErrorStatus> {
+    let typ = packet_type(header(slot).load(Acquire));
+    let signal = if typ == PacketType::KernelDispatch as u16 {
+        let packet = read(slot as *const KernelDispatchPacket);
+        kernel::dispatch(&packet)?;
+        packet.completion_signal
+    } else if typ == PacketType::BarrierAnd as u16 {
+        let packet = read(slot as *const BarrierAndPacket);
+        wait_until(None, || {
+            queue.stop.load(Acquire) ||
+                packet.dep_signal.iter().all(|s| s.handle == 0 || load(*s) == 0)
+        });
+        packet.completion_signal
+    } else if typ == PacketType::BarrierOr as u16 {
+        let packet = read(slot as *const BarrierOrPacket);
+        wait_until(None, || {
+            queue.stop.load(Acquire) || packet.dep_signal.iter().all(|s| s.handle == 0) ||
+                packet.dep_signal.iter().any(|s| s.handle != 0 && load(*s) == 0)
+        });
+        packet.completion_signal
+    } else if typ == PacketType::AgentDispatch as u16 {
+        read(slot as *const AgentDispatchPacket).completion_signal
+    } else {
+        return Err(ErrorStatus::InvalidPacketFormat);
+    };
+    Ok(signal)
+}
+
+unsafe fn create(
+    size: u32,
+    typ: QueueType,
+    doorbell_signal: Option<SignalHandle>,
+    callback: Option<extern "C" fn(HSAStatus, *const QueueHandle, *mut c_void)>,
+    data: *mut c_void,
+    queue: *mut *const QueueHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if queue.is_null() || !size.is_power_of_two() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let ring_layout = match Layout::from_size_align(size as usize * PACKET_SIZE, PACKET_SIZE) {
+        Ok(layout) => layout,
+        Err(_) => return error(ErrorStatus::OutOfResources),
+    };
+    let ring = alloc_zeroed(ring_layout);
+    if ring.is_null() {
+        return error(ErrorStatus::OutOfResources);
+    }
+    for i in 0..size as usize {
+        *(ring.add(i * PACKET_SIZE) as *mut u16) = (PacketType::Invalid as u16) <<
+            (PacketHeader::Type as u16);
+    }
+    let (doorbell, owns_doorbell) = match doorbell_signal {
+        Some(s) => (s, false),
+        None => {
+            let mut s = SignalHandle { handle: 0 };
+            let r = hsa_signal_create(0, 0, null(), &mut s);
+            if r != SUCCESS {
+                dealloc(ring, ring_layout);
+                return r;
+            }
+            (s, true)
+        }
+    };
+    let mut handle: MaybeUninit<QueueHandle> = MaybeUninit::zeroed();
+    let p = handle.as_mut_ptr();
+    addr_of_mut!((*p).typ).write(typ as QueueType32);
+    addr_of_mut!((*p).features).write(QueueFeature::KernelDispatch as u32);
+    addr_of_mut!((*p).base_address).write(ring as *const c_void);
+    addr_of_mut!((*p).doorbell_signal).write(doorbell);
+    addr_of_mut!((*p).size).write(size);
+    addr_of_mut!((*p).id).write(QUEUE_ID.fetch_add(1, Relaxed));
+    let obj = Box::new(QueueObject {
+        handle: handle.assume_init(),
+        write_index: AtomicU64::new(0),
+        read_index: AtomicU64::new(0),
+        ring_layout,
+        owns_doorbell,
+        callback,
+        data,
+        active: AtomicBool::new(true),
+        stop: AtomicBool::new(false),
+        worker: Mutex::new(None),
+    });
+    let obj = Box::into_raw(obj);
+    if owns_doorbell {
+        let ptr = QueuePtr(obj);
+        *(*obj).worker.lock().unwrap() = Some(thread::spawn(move || run(ptr)));
+    }
+    *queue = obj as *const QueueHandle;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_queue_create(
+    agent: Agent,
+    size: u32,
+    typ: QueueType,
+    callback: Option<extern "C" fn(HSAStatus, *const QueueHandle, *mut c_void)>,
+    data: *mut c_void,
+    _private_segment_size: u32,
+    _group_segment_size: u32,
+    queue: *mut *const QueueHandle,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    if !(QUEUE_MIN_SIZE..=QUEUE_MAX_SIZE).contains(&size) {
+        return error(ErrorStatus::InvalidQueueCreation);
+    }
+    create(size, typ, None, callback, data, queue)
+}
+
+pub unsafe extern "C" fn hsa_soft_queue_create(
+    region: Region,
+    size: u32,
+    typ: QueueType,
+    _features: u32,
+    doorbell_signal: SignalHandle,
+    queue: *mut *const QueueHandle,
+) -> HSAStatus {
+    if region != FINE_GRAINED_REGION && region != COARSE_GRAINED_REGION {
+        return error(ErrorStatus::InvalidRegion);
+    }
+    if doorbell_signal.handle == 0 {
+        return error(ErrorStatus::InvalidSignal);
+    }
+    create(size, typ, Some(doorbell_signal), None, null_mut(), queue)
+}
+
+pub unsafe extern "C" fn hsa_queue_destroy(queue: *const QueueHandle) -> HSAStatus {
+    if queue.is_null() {
+        return error(ErrorStatus::InvalidQueue);
+    }
+    let obj = Box::from_raw(queue as *mut QueueObject);
+    obj.stop.store(true, Release);
+    notify_all();
+    if let Some(worker) = obj.worker.lock().unwrap().take() {
+        let _ = worker.join();
+    }
+    if obj.owns_doorbell {
+        hsa_signal_destroy(obj.handle.doorbell_signal);
+    }
+    dealloc(obj.handle.base_address as *mut u8, obj.ring_layout);
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_queue_inactivate(queue: *const QueueHandle) -> HSAStatus {
+    if queue.is_null() {
+        return error(ErrorStatus::InvalidQueue);
+    }
+    get(queue).active.store(false, Release);
+    notify_all();
+    SUCCESS
+}
+
+macro_rules! queue_load {
+    ($($name:ident => $index:ident, $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(queue: *const QueueHandle) -> u64 {
+            get(queue).$index.load($order)
+        }
+    )*}
+}
+
+macro_rules! queue_store {
+    ($($name:ident => $index:ident, $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(queue: *const QueueHandle, value: u64) {
+            get(queue).$index.store(value, $order);
+            notify_all();
+        }
+    )*}
+}
+
+macro_rules! queue_cas {
+    ($($name:ident => $order:expr, $failure:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(queue: *const QueueHandle, expected: u64, value: u64) -> u64 {
+            match get(queue).write_index.compare_exchange(expected, value, $order, $failure) {
+                Ok(x) | Err(x) => x,
+            }
+        }
+    )*}
+}
+
+macro_rules! queue_add {
+    ($($name:ident => $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(queue: *const QueueHandle, value: u64) -> u64 {
+            get(queue).write_index.fetch_add(value, $order)
+        }
+    )*}
+}
+
+queue_load! {
+    hsa_queue_load_read_index_scacquire => read_index, Acquire;
+    hsa_queue_load_read_index_relaxed => read_index, Relaxed;
+    hsa_queue_load_read_index_acquire => read_index, Acquire;
+    hsa_queue_load_write_index_scacquire => write_index, Acquire;
+    hsa_queue_load_write_index_relaxed => write_index, Relaxed;
+    hsa_queue_load_write_index_acquire => write_index, Acquire;
+}
+
+queue_store! {
+    hsa_queue_store_write_index_relaxed => write_index, Relaxed;
+    hsa_queue_store_write_index_screlease => write_index, Release;
+    hsa_queue_store_write_index_release => write_index, Release;
+    hsa_queue_store_read_index_relaxed => read_index, Relaxed;
+    hsa_queue_store_read_index_screlease => read_index, Release;
+    hsa_queue_store_read_index_release => read_index, Release;
+}
+
+queue_cas! {
+    hsa_queue_cas_write_index_scacq_screl => AcqRel, Acquire;
+    hsa_queue_cas_write_index_scacquire => Acquire, Acquire;
+    hsa_queue_cas_write_index_relaxed => Relaxed, Relaxed;
+    hsa_queue_cas_write_index_screlease => Release, Relaxed;
+    hsa_queue_cas_write_index_acq_rel => AcqRel, Acquire;
+    hsa_queue_cas_write_index_acquire => Acquire, Acquire;
+    hsa_queue_cas_write_index_release => Release, Relaxed;
+}
+
+queue_add! {
+    hsa_queue_add_write_index_scacq_screl => AcqRel;
+    hsa_queue_add_write_index_scacquire => Acquire;
+    hsa_queue_add_write_index_relaxed => Relaxed;
+    hsa_queue_add_write_index_screlease => Release;
+    hsa_queue_add_write_index_acq_rel => AcqRel;
+    hsa_queue_add_write_index_acquire => Acquire;
+    hsa_queue_add_write_index_release => Release;
+}
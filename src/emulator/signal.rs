@@ -0,0 +1,405 @@
+use std::slice;
+use std::sync::atomic::{AtomicI64, AtomicUsize};
+use std::sync::atomic::Ordering::{self, AcqRel, Acquire, Relaxed, Release};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, SUCCESS};
+use super::amd::release_ipc_signal;
+
+// IPC attachments share the object, so it is freed when the last handle is destroyed
+struct SignalObject {
+    value: AtomicI64,
+    refs: AtomicUsize,
+    ipc: bool,
+}
+
+struct SignalGroupObject {
+    signals: Vec<SignalHandle>,
+}
+
+struct Notifier {
+    generation: Mutex<u64>,
+    cond: Condvar,
+}
+
+static NOTIFIER: Notifier = Notifier {
+    generation: Mutex::new(0),
+    cond: Condvar::new(),
+};
+
+const POLL_INTERVAL_MS: u64 = 1;
+
+pub(super) fn notify_all() {
+    let mut generation = NOTIFIER.generation.lock().unwrap();
+    *generation = generation.wrapping_add(1);
+    NOTIFIER.cond.notify_all();
+}
+
+pub(super) fn wait_until<F>(timeout: Option<Duration>, mut f: F) -> bool
+where
+    F: FnMut() -> bool,
+{
+    let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+    let mut generation = NOTIFIER.generation.lock().unwrap();
+    loop {
+        if f() {
+            return true;
+        }
+        let mut interval = Duration::from_millis(POLL_INTERVAL_MS);
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            interval = interval.min(deadline - now);
+        }
+        generation = NOTIFIER.cond.wait_timeout(generation, interval).unwrap().0;
+    }
+}
+
+unsafe fn get<'a>(signal: SignalHandle) -> &'a SignalObject {
+    &*(signal.handle as *const SignalObject)
+}
+
+pub(super) unsafe fn load(signal: SignalHandle) -> SignalValue {
+    get(signal).value.load(Acquire) as SignalValue
+}
+
+pub(super) unsafe fn subtract(signal: SignalHandle, value: SignalValue) {
+    get(signal).value.fetch_sub(value, AcqRel);
+    notify_all();
+}
+
+pub(super) fn satisfied(condition: SignalCondition, value: SignalValue, compare: SignalValue) -> bool {
+    match condition {
+        SignalCondition::Eq => value == compare,
+        SignalCondition::Ne => value != compare,
+        SignalCondition::Lt => value < compare,
+        SignalCondition::Gte => value >= compare,
+    }
+}
+
+pub(super) unsafe fn is_ipc(signal: SignalHandle) -> bool {
+    get(signal).ipc
+}
+
+pub(super) unsafe fn retain(signal: SignalHandle) {
+    get(signal).refs.fetch_add(1, AcqRel);
+}
+
+pub(super) unsafe fn create(
+    initial_value: SignalValue,
+    ipc: bool,
+    signal: *mut SignalHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if signal.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let obj = Box::new(SignalObject {
+        value: AtomicI64::new(initial_value),
+        refs: AtomicUsize::new(1),
+        ipc,
+    });
+    *signal = SignalHandle { handle: Box::into_raw(obj) as u64 };
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_signal_create(
+    initial_value: SignalValue,
+    _num_consumers: u32,
+    _consumers: *const Agent,
+    signal: *mut SignalHandle,
+) -> HSAStatus {
+    create(initial_value, false, signal)
+}
+
+pub unsafe extern "C" fn hsa_signal_destroy(signal: SignalHandle) -> HSAStatus {
+    if signal.handle == 0 {
+        return error(ErrorStatus::InvalidSignal);
+    }
+    let obj = get(signal);
+    let last = if obj.ipc {
+        release_ipc_signal(signal, || obj.refs.fetch_sub(1, AcqRel) == 1)
+    } else {
+        obj.refs.fetch_sub(1, AcqRel) == 1
+    };
+    if last {
+        drop(Box::from_raw(signal.handle as *mut SignalObject));
+    }
+    SUCCESS
+}
+
+macro_rules! signal_load {
+    ($($name:ident => $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(signal: SignalHandle) -> SignalValue {
+            get(signal).value.load($order) as SignalValue
+        }
+    )*}
+}
+
+macro_rules! signal_store {
+    ($($name:ident => $order:expr, $notify:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(signal: SignalHandle, value: SignalValue) {
+            get(signal).value.store(value as i64, $order);
+            if $notify {
+                notify_all();
+            }
+        }
+    )*}
+}
+
+macro_rules! signal_exchange {
+    ($($name:ident => $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(signal: SignalHandle, value: SignalValue) -> SignalValue {
+            let old = get(signal).value.swap(value as i64, $order);
+            notify_all();
+            old as SignalValue
+        }
+    )*}
+}
+
+macro_rules! signal_cas {
+    ($($name:ident => $order:expr, $failure:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(
+            signal: SignalHandle,
+            expected: SignalValue,
+            value: SignalValue,
+        ) -> SignalValue {
+            let r = get(signal).value.compare_exchange(
+                expected as i64,
+                value as i64,
+                $order,
+                $failure,
+            );
+            notify_all();
+            match r {
+                Ok(x) | Err(x) => x as SignalValue,
+            }
+        }
+    )*}
+}
+
+macro_rules! signal_rmw {
+    ($($name:ident => $method:ident, $order:expr;)*) => {$(
+        pub unsafe extern "C" fn $name(signal: SignalHandle, value: SignalValue) {
+            get(signal).value.$method(value as i64, $order);
+            notify_all();
+        }
+    )*}
+}
+
+signal_load! {
+    hsa_signal_load_scacquire => Acquire;
+    hsa_signal_load_relaxed => Relaxed;
+    hsa_signal_load_acquire => Acquire;
+}
+
+signal_store! {
+    hsa_signal_store_relaxed => Relaxed, true;
+    hsa_signal_store_screlease => Release, true;
+    hsa_signal_store_release => Release, true;
+    hsa_signal_silent_store_relaxed => Relaxed, false;
+    hsa_signal_silent_store_screlease => Release, false;
+}
+
+signal_exchange! {
+    hsa_signal_exchange_scacq_screl => AcqRel;
+    hsa_signal_exchange_scacquire => Acquire;
+    hsa_signal_exchange_relaxed => Relaxed;
+    hsa_signal_exchange_screlease => Release;
+    hsa_signal_exchange_acq_rel => AcqRel;
+    hsa_signal_exchange_acquire => Acquire;
+    hsa_signal_exchange_release => Release;
+}
+
+signal_cas! {
+    hsa_signal_cas_scacq_screl => AcqRel, Acquire;
+    hsa_signal_cas_scacquire => Acquire, Acquire;
+    hsa_signal_cas_relaxed => Relaxed, Relaxed;
+    hsa_signal_cas_screlease => Release, Relaxed;
+    hsa_signal_cas_acq_rel => AcqRel, Acquire;
+    hsa_signal_cas_acquire => Acquire, Acquire;
+    hsa_signal_cas_release => Release, Relaxed;
+}
+
+signal_rmw! {
+    hsa_signal_add_scacq_screl => fetch_add, AcqRel;
+    hsa_signal_add_scacquire => fetch_add, Acquire;
+    hsa_signal_add_relaxed => fetch_add, Relaxed;
+    hsa_signal_add_screlease => fetch_add, Release;
+    hsa_signal_add_acq_rel => fetch_add, AcqRel;
+    hsa_signal_add_acquire => fetch_add, Acquire;
+    hsa_signal_add_release => fetch_add, Release;
+    hsa_signal_subtract_scacq_screl => fetch_sub, AcqRel;
+    hsa_signal_subtract_scacquire => fetch_sub, Acquire;
+    hsa_signal_subtract_relaxed => fetch_sub, Relaxed;
+    hsa_signal_subtract_screlease => fetch_sub, Release;
+    hsa_signal_subtract_acq_rel => fetch_sub, AcqRel;
+    hsa_signal_subtract_acquire => fetch_sub, Acquire;
+    hsa_signal_subtract_release => fetch_sub, Release;
+    hsa_signal_and_scacq_screl => fetch_and, AcqRel;
+    hsa_signal_and_scacquire => fetch_and, Acquire;
+    hsa_signal_and_relaxed => fetch_and, Relaxed;
+    hsa_signal_and_screlease => fetch_and, Release;
+    hsa_signal_and_acq_rel => fetch_and, AcqRel;
+    hsa_signal_and_acquire => fetch_and, Acquire;
+    hsa_signal_and_release => fetch_and, Release;
+    hsa_signal_or_scacq_screl => fetch_or, AcqRel;
+    hsa_signal_or_scacquire => fetch_or, Acquire;
+    hsa_signal_or_relaxed => fetch_or, Relaxed;
+    hsa_signal_or_screlease => fetch_or, Release;
+    hsa_signal_or_acq_rel => fetch_or, AcqRel;
+    hsa_signal_or_acquire => fetch_or, Acquire;
+    hsa_signal_or_release => fetch_or, Release;
+    hsa_signal_xor_scacq_screl => fetch_xor, AcqRel;
+    hsa_signal_xor_scacquire => fetch_xor, Acquire;
+    hsa_signal_xor_relaxed => fetch_xor, Relaxed;
+    hsa_signal_xor_screlease => fetch_xor, Release;
+    hsa_signal_xor_acq_rel => fetch_xor, AcqRel;
+    hsa_signal_xor_acquire => fetch_xor, Acquire;
+    hsa_signal_xor_release => fetch_xor, Release;
+}
+
+unsafe fn wait(
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    timeout_hint: u64,
+    order: Ordering,
+) -> SignalValue {
+    let obj = get(signal);
+    let timeout = if timeout_hint == u64::MAX {
+        None
+    } else {
+        Some(Duration::from_nanos(timeout_hint))
+    };
+    let mut value = 0;
+    wait_until(timeout, || {
+        value = obj.value.load(order) as SignalValue;
+        satisfied(condition, value, compare_value)
+    });
+    value
+}
+
+pub unsafe extern "C" fn hsa_signal_wait_scacquire(
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    timeout_hint: u64,
+    _wait_state_hint: WaitState,
+) -> SignalValue {
+    wait(signal, condition, compare_value, timeout_hint, Acquire)
+}
+
+pub unsafe extern "C" fn hsa_signal_wait_relaxed(
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    timeout_hint: u64,
+    _wait_state_hint: WaitState,
+) -> SignalValue {
+    wait(signal, condition, compare_value, timeout_hint, Relaxed)
+}
+
+pub unsafe extern "C" fn hsa_signal_wait_acquire(
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    timeout_hint: u64,
+    _wait_state_hint: WaitState,
+) -> SignalValue {
+    wait(signal, condition, compare_value, timeout_hint, Acquire)
+}
+
+pub unsafe extern "C" fn hsa_signal_group_create(
+    num_signals: u32,
+    signals: *const SignalHandle,
+    _num_consumers: u32,
+    _consumers: *const Agent,
+    signal_group: *mut SignalGroupHandle,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if num_signals == 0 || signals.is_null() || signal_group.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let signals = slice::from_raw_parts(signals, num_signals as usize);
+    if signals.iter().any(|s| s.handle == 0) {
+        return error(ErrorStatus::InvalidSignal);
+    }
+    let obj = Box::new(SignalGroupObject { signals: signals.to_vec() });
+    *signal_group = SignalGroupHandle { handle: Box::into_raw(obj) as u64 };
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_signal_group_destroy(signal_group: SignalGroupHandle) -> HSAStatus {
+    if signal_group.handle == 0 {
+        return error(ErrorStatus::InvalidSignalGroup);
+    }
+    drop(Box::from_raw(signal_group.handle as *mut SignalGroupObject));
+    SUCCESS
+}
+
+unsafe fn group_wait_any(
+    signal_group: SignalGroupHandle,
+    conditions: *const SignalCondition,
+    compare_values: *const SignalValue,
+    signal: *mut SignalHandle,
+    value: *mut SignalValue,
+    order: Ordering,
+) -> HSAStatus {
+    if signal_group.handle == 0 {
+        return error(ErrorStatus::InvalidSignalGroup);
+    }
+    if conditions.is_null() || compare_values.is_null() || signal.is_null() || value.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    let group = &*(signal_group.handle as *const SignalGroupObject);
+    let n = group.signals.len();
+    let conditions = slice::from_raw_parts(conditions, n);
+    let compare_values = slice::from_raw_parts(compare_values, n);
+    let mut found = None;
+    wait_until(None, || {
+        for (i, s) in group.signals.iter().enumerate() {
+            let v = get(*s).value.load(order) as SignalValue;
+            if satisfied(conditions[i], v, compare_values[i]) {
+                found = Some((*s, v));
+                return true;
+            }
+        }
+        false
+    });
+    let (s, v) = found.unwrap();
+    *signal = s;
+    *value = v;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_signal_group_wait_any_scacquire(
+    signal_group: SignalGroupHandle,
+    conditions: *const SignalCondition,
+    compare_values: *const SignalValue,
+    _wait_state_hint: WaitState,
+    signal: *mut SignalHandle,
+    value: *mut SignalValue,
+) -> HSAStatus {
+    group_wait_any(signal_group, conditions, compare_values, signal, value, Acquire)
+}
+
+pub unsafe extern "C" fn hsa_signal_group_wait_any_relaxed(
+    signal_group: SignalGroupHandle,
+    conditions: *const SignalCondition,
+    compare_values: *const SignalValue,
+    _wait_state_hint: WaitState,
+    signal: *mut SignalHandle,
+    value: *mut SignalValue,
+) -> HSAStatus {
+    group_wait_any(signal_group, conditions, compare_values, signal, value, Relaxed)
+}
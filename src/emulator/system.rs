@@ -0,0 +1,311 @@
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use native::*;
+use ErrorStatus;
+use super::{error, initialized, write_fixed_str, write_info, INIT_COUNT, SUCCESS};
+
+pub(super) const CPU_AGENT: Agent = Agent { handle: 1 };
+pub(super) const L1_CACHE: Cache = Cache { handle: 1 };
+pub(super) const QUEUE_MIN_SIZE: u32 = 64;
+pub(super) const QUEUE_MAX_SIZE: u32 = 4096;
+pub(super) const WORKGROUP_MAX_SIZE: u32 = 1024;
+
+static START: OnceLock<Instant> = OnceLock::new();
+
+pub unsafe extern "C" fn hsa_init() -> HSAStatus {
+    START.get_or_init(Instant::now);
+    INIT_COUNT.fetch_add(1, Ordering::SeqCst);
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_shut_down() -> HSAStatus {
+    let r = INIT_COUNT.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+    match r {
+        Ok(_) => SUCCESS,
+        Err(_) => error(ErrorStatus::NotInitialized),
+    }
+}
+
+pub unsafe extern "C" fn hsa_status_string(
+    status: HSAStatus,
+    status_string: *mut *const c_char,
+) -> HSAStatus {
+    let s: &'static [u8] = match status {
+        0x0 => b"HSA_STATUS_SUCCESS: The function has been executed successfully.\0",
+        0x1 => b"HSA_STATUS_INFO_BREAK: A traversal over a list of elements has been interrupted by the application before completing.\0",
+        0x28 => b"HSA_STATUS_ERROR_INVALID_MEMORY_POOL: The memory pool is invalid.\0",
+        0x1001 => b"HSA_STATUS_ERROR_INVALID_ARGUMENT: One of the actual arguments does not meet a precondition stated in the documentation of the corresponding formal argument.\0",
+        0x1002 => b"HSA_STATUS_ERROR_INVALID_QUEUE_CREATION: The requested queue creation is not valid.\0",
+        0x1003 => b"HSA_STATUS_ERROR_INVALID_ALLOCATION: The requested allocation is not valid.\0",
+        0x1004 => b"HSA_STATUS_ERROR_INVALID_AGENT: The agent is invalid.\0",
+        0x1005 => b"HSA_STATUS_ERROR_INVALID_REGION: The memory region is invalid.\0",
+        0x1006 => b"HSA_STATUS_ERROR_INVALID_SIGNAL: The signal is invalid.\0",
+        0x1007 => b"HSA_STATUS_ERROR_INVALID_QUEUE: The queue is invalid.\0",
+        0x1008 => b"HSA_STATUS_ERROR_OUT_OF_RESOURCES: The runtime failed to allocate the necessary resources.\0",
+        0x1009 => b"HSA_STATUS_ERROR_INVALID_PACKET_FORMAT: The AQL packet is malformed.\0",
+        0x100B => b"HSA_STATUS_ERROR_NOT_INITIALIZED: An API other than hsa_init has been invoked while the reference count of the HSA runtime is zero.\0",
+        0x1010 => b"HSA_STATUS_ERROR_INVALID_CODE_OBJECT: The code object is invalid.\0",
+        0x1011 => b"HSA_STATUS_ERROR_INVALID_EXECUTABLE: The executable is invalid.\0",
+        0x1012 => b"HSA_STATUS_ERROR_FROZEN_EXECUTABLE: The executable is frozen.\0",
+        0x1013 => b"HSA_STATUS_ERROR_INVALID_SYMBOL_NAME: There is no symbol with the given name.\0",
+        0x1016 => b"HSA_STATUS_ERROR_EXCEPTION: An HSAIL operation resulted in a hardware exception.\0",
+        0x1017 => b"HSA_STATUS_ERROR_INVALID_ISA_NAME: The ISA name is invalid.\0",
+        0x1019 => b"HSA_STATUS_ERROR_INVALID_EXECUTABLE_SYMBOL: The executable symbol is invalid.\0",
+        _ if status >= 0x1000 || (0x28..=0x2B).contains(&status) => b"HSA_STATUS_ERROR: A generic error has occurred.\0",
+        _ => return error(ErrorStatus::InvalidArgument),
+    };
+    if status_string.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    *status_string = s.as_ptr() as *const c_char;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_system_get_info(attribute: SystemInfo, value: *mut c_void) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    match attribute {
+        SystemInfo::VersionMajor => write_info(value, 1u16),
+        SystemInfo::VersionMinor => write_info(value, 1u16),
+        SystemInfo::Timestamp => write_info(value, timestamp()),
+        SystemInfo::TimestampFrequency => write_info(value, 1_000_000_000u64),
+        SystemInfo::SignalMaxWait => write_info(value, u64::MAX),
+        SystemInfo::Endianness => write_info(value, Endianness::Little),
+        SystemInfo::MachineModel => write_info(value, MachineModel::Large),
+        SystemInfo::Extensions => write_info(value, [0u8; 128]),
+    }
+}
+
+pub unsafe extern "C" fn hsa_extension_get_name(
+    extension: Extension,
+    name: *mut *const c_char,
+) -> HSAStatus {
+    let s: &'static [u8] = match extension {
+        Extension::Finalizer => b"HSA_EXTENSION_FINALIZER\0",
+        Extension::Images => b"HSA_EXTENSION_IMAGES\0",
+        Extension::PerformanceCounters => b"HSA_EXTENSION_PERFORMANCE_COUNTERS\0",
+        Extension::ProfilingEvents => b"HSA_EXTENSION_PROFILING_EVENTS\0",
+    };
+    if name.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    *name = s.as_ptr() as *const c_char;
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_system_extension_supported(
+    _extension: Extension,
+    _version_major: u16,
+    _version_minor: u16,
+    result: *mut bool,
+) -> HSAStatus {
+    write_info(result as *mut c_void, false)
+}
+
+pub unsafe extern "C" fn hsa_system_major_extension_supported(
+    _extension: Extension,
+    _version_major: u16,
+    version_minor: *mut u16,
+    result: *mut bool,
+) -> HSAStatus {
+    if version_minor.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    *version_minor = 0;
+    write_info(result as *mut c_void, false)
+}
+
+pub unsafe extern "C" fn hsa_system_get_major_extension_table(
+    _extension: Extension,
+    _version_major: u16,
+    _table_length: usize,
+    _table: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidArgument)
+}
+
+pub unsafe extern "C" fn hsa_agent_get_info(
+    agent: Agent,
+    attribute: AgentInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    match attribute {
+        AgentInfo::Name => write_fixed_str(value, "Emulated CPU", 64),
+        AgentInfo::VendorName => write_fixed_str(value, "rust-hsa", 64),
+        AgentInfo::Feature => write_info(value, AgentFeature::KernelDispatch as u32),
+        AgentInfo::MachineModel => write_info(value, MachineModel::Large),
+        AgentInfo::Profile => write_info(value, Profile::Full),
+        AgentInfo::DefaultFloatRoundingMode |
+        AgentInfo::BaseProfileDefaultFloatRoundingModes => {
+            write_info(value, DefaultFloatRoundingMode::Near)
+        }
+        AgentInfo::FastF16Operation => write_info(value, false),
+        AgentInfo::WavefrontSize => write_info(value, 1u32),
+        AgentInfo::WorkgroupMaxDim => write_info(value, [WORKGROUP_MAX_SIZE as u16; 3]),
+        AgentInfo::WorkgroupMaxSize => write_info(value, WORKGROUP_MAX_SIZE),
+        AgentInfo::GridMaxDim => {
+            write_info(
+                value,
+                Dim3 {
+                    x: u32::MAX,
+                    y: u32::MAX,
+                    z: u32::MAX,
+                },
+            )
+        }
+        AgentInfo::GridMaxSize => write_info(value, u32::MAX),
+        AgentInfo::FbarrierMaxSize => write_info(value, 32u32),
+        AgentInfo::QueuesMax => write_info(value, 128u32),
+        AgentInfo::QueueMinSize => write_info(value, QUEUE_MIN_SIZE),
+        AgentInfo::QueueMaxSize => write_info(value, QUEUE_MAX_SIZE),
+        AgentInfo::QueueType => write_info(value, QueueType::Multi),
+        AgentInfo::Node => write_info(value, 0u32),
+        AgentInfo::Device => write_info(value, DeviceType::CPU),
+        AgentInfo::CacheSize => write_info(value, [32 * 1024u32, 0, 0, 0]),
+        AgentInfo::ISA => write_info(value, ISA { handle: 0 }),
+        AgentInfo::Extensions => write_info(value, [0u8; 128]),
+        AgentInfo::VersionMajor => write_info(value, 1u16),
+        AgentInfo::VersionMinor => write_info(value, 1u16),
+    }
+}
+
+pub unsafe extern "C" fn hsa_iterate_agents(
+    callback: extern "C" fn(Agent, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if !initialized() {
+        return error(ErrorStatus::NotInitialized);
+    }
+    callback(CPU_AGENT, data)
+}
+
+pub unsafe extern "C" fn hsa_cache_get_info(
+    cache: Cache,
+    attribute: CacheInfo,
+    value: *mut c_void,
+) -> HSAStatus {
+    if cache != L1_CACHE {
+        return error(ErrorStatus::InvalidCache);
+    }
+    match attribute {
+        CacheInfo::Name => {
+            let name: &'static [u8] = b"Emulated CPU L1\0";
+            write_info(value, name.as_ptr() as *const c_char)
+        }
+        CacheInfo::Level => write_info(value, 1u8),
+        CacheInfo::Size => write_info(value, 32 * 1024u32),
+    }
+}
+
+pub unsafe extern "C" fn hsa_agent_iterate_caches(
+    agent: Agent,
+    callback: extern "C" fn(Cache, *mut c_void) -> HSAStatus,
+    data: *mut c_void,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    callback(L1_CACHE, data)
+}
+
+pub unsafe extern "C" fn hsa_agent_extension_supported(
+    _extension: Extension,
+    agent: Agent,
+    _version_major: u16,
+    _version_minor: u16,
+    result: *mut bool,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    write_info(result as *mut c_void, false)
+}
+
+pub unsafe extern "C" fn hsa_agent_major_extension_supported(
+    _extension: Extension,
+    agent: Agent,
+    _version_major: u16,
+    version_minor: *mut u16,
+    result: *mut bool,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    if version_minor.is_null() {
+        return error(ErrorStatus::InvalidArgument);
+    }
+    *version_minor = 0;
+    write_info(result as *mut c_void, false)
+}
+
+pub unsafe extern "C" fn hsa_agent_iterate_isas(
+    agent: Agent,
+    _callback: extern "C" fn(ISA, *mut c_void) -> HSAStatus,
+    _data: *mut c_void,
+) -> HSAStatus {
+    if agent != CPU_AGENT {
+        return error(ErrorStatus::InvalidAgent);
+    }
+    SUCCESS
+}
+
+pub unsafe extern "C" fn hsa_isa_from_name(_name: *const c_char, _isa: *mut ISA) -> HSAStatus {
+    error(ErrorStatus::InvalidISAName)
+}
+
+pub unsafe extern "C" fn hsa_isa_get_info_alt(
+    _isa: ISA,
+    _attribute: ISAInfo,
+    _value: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidISA)
+}
+
+pub unsafe extern "C" fn hsa_isa_get_exception_policies(
+    _isa: ISA,
+    _profile: Profile,
+    _mask: &mut u16,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidISA)
+}
+
+pub unsafe extern "C" fn hsa_isa_get_round_method(
+    _isa: ISA,
+    _fp_type: FpType,
+    _flush_mode: FlushMode,
+    _round_method: *mut RoundMethod,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidISA)
+}
+
+pub unsafe extern "C" fn hsa_wavefront_get_info(
+    _wavefront: Wavefront,
+    _attribute: WavefrontInfo,
+    _value: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidWavefront)
+}
+
+pub unsafe extern "C" fn hsa_isa_iterate_wavefronts(
+    _isa: ISA,
+    _callback: extern "C" fn(Wavefront, *mut c_void) -> HSAStatus,
+    _data: *mut c_void,
+) -> HSAStatus {
+    error(ErrorStatus::InvalidISA)
+}
+
+pub(super) fn timestamp() -> u64 {
+    let start = START.get_or_init(Instant::now);
+    let d = start.elapsed();
+    d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64
+}
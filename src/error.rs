@@ -0,0 +1,126 @@
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::ptr::null;
+
+use enum_primitive::FromPrimitive;
+use native::{hsa_status_string, HSAStatus};
+use super::ErrorStatus;
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    status: HSAStatus,
+    function: &'static str,
+    message: Option<String>,
+    context: Option<String>,
+}
+
+impl Error {
+    pub fn from_status(status: HSAStatus, function: &'static str) -> Error {
+        let message = if status >= ErrorStatus::LibraryNotFound as HSAStatus {
+            None
+        } else {
+            unsafe {
+                let mut p: *const c_char = null();
+                if hsa_status_string(status, &mut p) == 0 && !p.is_null() {
+                    Some(CStr::from_ptr(p).to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            }
+        };
+        Error {
+            status,
+            function,
+            message,
+            context: None,
+        }
+    }
+
+    pub fn status(&self) -> HSAStatus {
+        self.status
+    }
+
+    pub fn kind(&self) -> Option<ErrorStatus> {
+        ErrorStatus::from_i32(self.status)
+    }
+
+    pub fn function(&self) -> &'static str {
+        self.function
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    pub fn with_context<C: Into<String>>(mut self, context: C) -> Error {
+        self.context = Some(context.into());
+        self
+    }
+
+    #[cfg(feature = "dynamic")]
+    pub(crate) fn with_message<M: Into<String>>(mut self, message: M) -> Error {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref context) = self.context {
+            write!(f, "{}: ", context)?;
+        }
+        if !self.function.is_empty() {
+            write!(f, "{} failed: ", self.function)?;
+        }
+        match (self.message(), self.kind()) {
+            (Some(message), _) => write!(f, "{}", message)?,
+            (None, Some(kind)) => write!(f, "{:?}", kind)?,
+            (None, None) => write!(f, "unknown status")?,
+        }
+        write!(f, " ({:#x})", self.status)
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<ErrorStatus> for Error {
+    fn from(status: ErrorStatus) -> Error {
+        Error::from_status(status as HSAStatus, "")
+    }
+}
+
+impl PartialEq<ErrorStatus> for Error {
+    fn eq(&self, other: &ErrorStatus) -> bool {
+        self.status == *other as HSAStatus
+    }
+}
+
+impl fmt::Display for ErrorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} ({:#x})", self, *self as HSAStatus)
+    }
+}
+
+impl error::Error for ErrorStatus {}
+
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, Error>;
+
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T, Error> {
+        self.map_err(|e| e.into().with_context(context))
+    }
+
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, f: F) -> Result<T, Error> {
+        self.map_err(|e| e.into().with_context(f()))
+    }
+}
@@ -1,28 +1,37 @@
 use std::ffi::CString;
 use std::os::raw::c_void;
 use std::mem::zeroed;
+use std::marker::PhantomData;
 
 use native::*;
 use native::Executable as ExecutableHandle;
-use code_object::CodeObject;
-use super::{check, get_info, iter_callback_helper, ErrorStatus};
+use code_object::{CodeObject, CodeObjectReader};
+use runtime::Runtime;
+use super::{check, get_info, iter_callback_helper, Error};
 
-pub struct Executable {
+pub struct Executable<'rt> {
     handle: ExecutableHandle,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
-impl Executable {
+impl<'rt> Executable<'rt> {
     pub fn new<T: Into<Vec<u8>>>(
+        _runtime: &'rt Runtime,
         profile: Profile,
         default_float_rounding_mode: DefaultFloatRoundingMode,
         options: T,
-    ) -> Result<Executable, ErrorStatus> {
+    ) -> Result<Executable<'rt>, Error> {
         let mode = default_float_rounding_mode;
         unsafe {
             let opt = CString::from_vec_unchecked(options.into());
-            let handle: ExecutableHandle = zeroed();
-            check(hsa_executable_create_alt(profile, mode, opt.as_ptr(), &handle), ())
-                .map(|_| Executable { handle: handle })
+            let mut handle: ExecutableHandle = zeroed();
+            check(hsa_executable_create_alt(profile, mode, opt.as_ptr(), &mut handle), ())
+                .map(|_| {
+                    Executable {
+                        handle,
+                        runtime: PhantomData,
+                    }
+                })
         }
     }
 
@@ -32,7 +41,7 @@ impl Executable {
         agent: Agent,
         code_object: CodeObject,
         options: T,
-    ) -> Result<(), ErrorStatus> {
+    ) -> Result<(), Error> {
         unsafe {
             let opt = CString::from_vec_unchecked(options.into());
             check(
@@ -47,7 +56,50 @@ impl Executable {
         }
     }
 
-    pub fn freeze<T: Into<Vec<u8>>>(&self, options: T) -> Result<(), ErrorStatus> {
+    // the code object is copied while loading, so the reader may be dropped afterwards
+    pub fn load_agent_code_object<T: Into<Vec<u8>>>(
+        &self,
+        agent: Agent,
+        reader: &CodeObjectReader,
+        options: T,
+    ) -> Result<LoadedCodeObject, Error> {
+        unsafe {
+            let opt = CString::from_vec_unchecked(options.into());
+            let mut loaded: LoadedCodeObject = zeroed();
+            check(
+                hsa_executable_load_agent_code_object(
+                    self.handle,
+                    agent,
+                    reader.handle,
+                    opt.as_ptr(),
+                    &mut loaded,
+                ),
+                loaded,
+            )
+        }
+    }
+
+    pub fn load_program_code_object<T: Into<Vec<u8>>>(
+        &self,
+        reader: &CodeObjectReader,
+        options: T,
+    ) -> Result<LoadedCodeObject, Error> {
+        unsafe {
+            let opt = CString::from_vec_unchecked(options.into());
+            let mut loaded: LoadedCodeObject = zeroed();
+            check(
+                hsa_executable_load_program_code_object(
+                    self.handle,
+                    reader.handle,
+                    opt.as_ptr(),
+                    &mut loaded,
+                ),
+                loaded,
+            )
+        }
+    }
+
+    pub fn freeze<T: Into<Vec<u8>>>(&self, options: T) -> Result<(), Error> {
         unsafe {
             let opt = CString::from_vec_unchecked(options.into());
             check(hsa_executable_freeze(self.handle, opt.as_ptr()), ())
@@ -61,9 +113,9 @@ impl Executable {
         symbol_name: T,
         agent: Agent,
         call_convention: i32,
-    ) -> Result<ExecutableSymbol, ErrorStatus> {
+    ) -> Result<ExecutableSymbol, Error> {
         unsafe {
-            let symbol: ExecutableSymbol = zeroed();
+            let mut symbol: ExecutableSymbol = zeroed();
             check(
                 hsa_executable_get_symbol(
                     self.handle,
@@ -71,14 +123,14 @@ impl Executable {
                     CString::from_vec_unchecked(symbol_name.into()).as_ptr(),
                     agent,
                     call_convention,
-                    &symbol,
+                    &mut symbol,
                 ),
                 symbol,
             )
         }
     }
 
-    pub fn agent_symbols(&self, agent: Agent) -> Result<Vec<ExecutableSymbol>, ErrorStatus> {
+    pub fn agent_symbols(&self, agent: Agent) -> Result<Vec<ExecutableSymbol>, Error> {
         let mut v: Vec<ExecutableSymbol> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -89,7 +141,7 @@ impl Executable {
         )
     }
 
-    pub fn program_symbols(&self) -> Result<Vec<ExecutableSymbol>, ErrorStatus> {
+    pub fn program_symbols(&self) -> Result<Vec<ExecutableSymbol>, Error> {
         let mut v: Vec<ExecutableSymbol> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -99,7 +151,7 @@ impl Executable {
     }
 
     #[deprecated]
-    pub fn symbols(&self) -> Result<Vec<ExecutableSymbol>, ErrorStatus> {
+    pub fn symbols(&self) -> Result<Vec<ExecutableSymbol>, Error> {
         let mut v: Vec<ExecutableSymbol> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -108,15 +160,19 @@ impl Executable {
         )
     }
 
-    pub fn profile(&self) -> Result<Profile, ErrorStatus> {
+    pub(crate) fn handle(&self) -> ExecutableHandle {
+        self.handle
+    }
+
+    pub fn profile(&self) -> Result<Profile, Error> {
         get_info(|x| self.get_info(ExecutableInfo::Profile, x))
     }
 
-    pub fn state(&self) -> Result<ExecutableState, ErrorStatus> {
+    pub fn state(&self) -> Result<ExecutableState, Error> {
         get_info(|x| self.get_info(ExecutableInfo::State, x))
     }
 
-    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, ErrorStatus> {
+    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, Error> {
         get_info(|x| {
             self.get_info(ExecutableInfo::DefaultFloatRoundingMode, x)
         })
@@ -127,7 +183,7 @@ impl Executable {
     }
 }
 
-impl Drop for Executable {
+impl<'rt> Drop for Executable<'rt> {
     fn drop(&mut self) {
         if self.handle.handle != 0 {
             unsafe {
@@ -156,16 +212,15 @@ extern "C" fn symbol_list_callback2(
 }
 
 impl ExecutableSymbol {
-    pub fn kind(&self) -> Result<SymbolKind, ErrorStatus> {
+    pub fn kind(&self) -> Result<SymbolKind, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::Type, x))
     }
 
     #[deprecated]
-    pub fn name(&self) -> Result<String, ErrorStatus> {
+    pub fn name(&self) -> Result<String, Error> {
         let len: u32 = get_info(|x| self.get_info(ExecutableSymbolInfo::NameLength, x))?;
-        let mut buf: Vec<u8> = Vec::with_capacity(len as usize + 1);
+        let mut buf: Vec<u8> = vec![0; len as usize + 1];
         unsafe {
-            buf.set_len(len as usize);
             check(
                 hsa_executable_symbol_get_info(
                     *self,
@@ -173,16 +228,15 @@ impl ExecutableSymbol {
                     buf.as_mut_ptr() as *mut c_void,
                 ),
                 (),
-            ).map(|_| String::from_utf8_lossy(&buf).to_string())
+            ).map(|_| String::from_utf8_lossy(&buf[..len as usize]).to_string())
         }
     }
 
     #[deprecated]
-    pub fn module_name(&self) -> Result<String, ErrorStatus> {
+    pub fn module_name(&self) -> Result<String, Error> {
         let len: u32 = get_info(|x| self.get_info(ExecutableSymbolInfo::ModuleNameLength, x))?;
-        let mut buf: Vec<u8> = Vec::with_capacity(len as usize + 1);
+        let mut buf: Vec<u8> = vec![0; len as usize + 1];
         unsafe {
-            buf.set_len(len as usize);
             check(
                 hsa_executable_symbol_get_info(
                     *self,
@@ -190,15 +244,14 @@ impl ExecutableSymbol {
                     buf.as_mut_ptr() as *mut c_void,
                 ),
                 (),
-            ).map(|_| String::from_utf8_lossy(&buf).to_string())
+            ).map(|_| String::from_utf8_lossy(&buf[..len as usize]).to_string())
         }
     }
 
-    pub fn linker_name(&self) -> Result<String, ErrorStatus> {
+    pub fn linker_name(&self) -> Result<String, Error> {
         let len: u32 = get_info(|x| self.get_info(ExecutableSymbolInfo::LinkerNameLength, x))?;
-        let mut buf: Vec<u8> = Vec::with_capacity(len as usize + 1);
+        let mut buf: Vec<u8> = vec![0; len as usize + 1];
         unsafe {
-            buf.set_len(len as usize);
             check(
                 hsa_executable_symbol_get_info(
                     *self,
@@ -206,112 +259,112 @@ impl ExecutableSymbol {
                     buf.as_mut_ptr() as *mut c_void,
                 ),
                 (),
-            ).map(|_| String::from_utf8_lossy(&buf).to_string())
+            ).map(|_| String::from_utf8_lossy(&buf[..len as usize]).to_string())
         }
     }
 
-    pub fn agent(&self) -> Result<Agent, ErrorStatus> {
+    pub fn agent(&self) -> Result<Agent, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::Agent, x))
     }
 
-    pub fn variable_address(&self) -> Result<u64, ErrorStatus> {
+    pub fn variable_address(&self) -> Result<u64, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::VariableAddress, x))
     }
 
-    pub fn linkage(&self) -> Result<SymbolKindLinkage, ErrorStatus> {
+    pub fn linkage(&self) -> Result<SymbolKindLinkage, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::Linkage, x))
     }
 
-    pub fn is_definition(&self) -> Result<bool, ErrorStatus> {
+    pub fn is_definition(&self) -> Result<bool, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::IsDefinition, x))
     }
 
     #[deprecated]
-    pub fn variable_allocation(&self) -> Result<VariableAllocation, ErrorStatus> {
+    pub fn variable_allocation(&self) -> Result<VariableAllocation, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::VariableAllocation, x)
         })
     }
 
     #[deprecated]
-    pub fn variable_segment(&self) -> Result<VariableSegment, ErrorStatus> {
+    pub fn variable_segment(&self) -> Result<VariableSegment, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::VariableSegment, x))
     }
 
     #[deprecated]
-    pub fn variable_alignment(&self) -> Result<u32, ErrorStatus> {
+    pub fn variable_alignment(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::VariableAlignment, x)
         })
     }
 
     #[deprecated]
-    pub fn variable_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn variable_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::VariableSize, x))
     }
 
     #[deprecated]
-    pub fn variable_is_const(&self) -> Result<bool, ErrorStatus> {
+    pub fn variable_is_const(&self) -> Result<bool, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::VariableIsConst, x))
     }
 
-    pub fn kernel_object(&self) -> Result<u64, ErrorStatus> {
+    pub fn kernel_object(&self) -> Result<u64, Error> {
         get_info(|x| self.get_info(ExecutableSymbolInfo::KernelObject, x))
     }
 
-    pub fn kernel_kernarg_segment_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn kernel_kernarg_segment_size(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelKernArgSegmentSize, x)
         })
     }
 
-    pub fn kernel_kernarg_segment_alignment(&self) -> Result<u32, ErrorStatus> {
+    pub fn kernel_kernarg_segment_alignment(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelKernArgSegmentAlignment, x)
         })
     }
 
-    pub fn kernel_group_segment_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn kernel_group_segment_size(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelGroupSegmentSize, x)
         })
     }
 
-    pub fn kernel_private_segment_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn kernel_private_segment_size(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelPrivateSegmentSize, x)
         })
     }
 
-    pub fn kernel_dynamic_callstack(&self) -> Result<bool, ErrorStatus> {
+    pub fn kernel_dynamic_callstack(&self) -> Result<bool, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelDynamicCallstack, x)
         })
     }
 
     #[deprecated]
-    pub fn kernel_call_convention(&self) -> Result<u32, ErrorStatus> {
+    pub fn kernel_call_convention(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::KernelCallConvertion, x)
         })
     }
 
     #[cfg(target_pointer_width = "32")]
-    pub fn indirect_function_object(&self) -> Result<u32, ErrorStatus> {
+    pub fn indirect_function_object(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::IndirectFunctionObject, x)
         })
     }
 
     #[cfg(target_pointer_width = "64")]
-    pub fn indirect_function_object(&self) -> Result<u64, ErrorStatus> {
+    pub fn indirect_function_object(&self) -> Result<u64, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::IndirectFunctionObject, x)
         })
     }
 
     #[deprecated]
-    pub fn indirect_function_call_convention(&self) -> Result<u32, ErrorStatus> {
+    pub fn indirect_function_call_convention(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ExecutableSymbolInfo::IndirectFunctionCallConvertion, x)
         })
@@ -2,43 +2,52 @@ use std::ffi::CString;
 use std::os::raw::{c_void, c_char};
 use std::mem::zeroed;
 use std::ptr::null;
+use std::marker::PhantomData;
 
 use native::*;
 use native::CodeObject as CodeObjectHandle;
 use code_object::CodeObject;
-use super::{check, get_info, ErrorStatus};
+use runtime::Runtime;
+use super::{check, get_info, Error};
 
-pub struct ExtProgram {
+pub struct ExtProgram<'rt> {
     handle: ExtProgramHandle,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
-impl ExtProgram {
+impl<'rt> ExtProgram<'rt> {
     pub fn new(
+        _runtime: &'rt Runtime,
         machine_model: MachineModel,
         profile: Profile,
         default_float_rouding_mode: DefaultFloatRoundingMode,
         options: Option<&str>,
-    ) -> Result<ExtProgram, ErrorStatus> {
+    ) -> Result<ExtProgram<'rt>, Error> {
         unsafe {
             let opt = match options {
                 Some(x) => x.as_ptr(),
                 None => null(),
             } as *const c_char;
-            let prog: ExtProgramHandle = zeroed();
+            let mut prog: ExtProgramHandle = zeroed();
             check(
                 hsa_ext_program_create(
                     machine_model,
                     profile,
                     default_float_rouding_mode,
                     opt,
-                    &prog,
+                    &mut prog,
                 ),
                 (),
-            ).map(|_| ExtProgram { handle: prog })
+            ).map(|_| {
+                ExtProgram {
+                    handle: prog,
+                    runtime: PhantomData,
+                }
+            })
         }
     }
 
-    pub fn add_module(&self, module: &[u8]) -> Result<(), ErrorStatus> {
+    pub fn add_module(&self, module: &[u8]) -> Result<(), Error> {
         let ptr = module.as_ptr() as *const c_void;
         unsafe { check(hsa_ext_program_add_module(self.handle, ptr), ()) }
     }
@@ -49,10 +58,10 @@ impl ExtProgram {
         isa: ISA,
         options: T,
         code_object_type: CodeObjectType,
-    ) -> Result<CodeObject, ErrorStatus> {
+    ) -> Result<CodeObject, Error> {
         unsafe {
             let opt = CString::from_vec_unchecked(options.into());
-            let handle: CodeObjectHandle = zeroed();
+            let mut handle: CodeObjectHandle = zeroed();
             let directives: ExtControlDirectives = zeroed();
             check(
                 hsa_ext_program_finalize(
@@ -62,10 +71,10 @@ impl ExtProgram {
                     directives,
                     opt.as_ptr(),
                     code_object_type,
-                    &handle,
+                    &mut handle,
                 ),
                 (),
-            ).map(|_| CodeObject { handle: handle })
+            ).map(|_| CodeObject { handle })
         }
     }
 
@@ -74,7 +83,7 @@ impl ExtProgram {
         isa: ISA,
         options: Option<&str>,
         writer: &ExtCodeObjectWriter,
-    ) -> Result<(), ErrorStatus> {
+    ) -> Result<(), Error> {
         let opt = match options {
             Some(x) => x.as_ptr(),
             None => null(),
@@ -85,15 +94,15 @@ impl ExtProgram {
         }
     }
 
-    pub fn state(&self) -> Result<MachineModel, ErrorStatus> {
+    pub fn state(&self) -> Result<MachineModel, Error> {
         get_info(|x| self.get_info(ExtProgramInfo::MachineModel, x))
     }
 
-    pub fn profile(&self) -> Result<Profile, ErrorStatus> {
+    pub fn profile(&self) -> Result<Profile, Error> {
         get_info(|x| self.get_info(ExtProgramInfo::Profile, x))
     }
 
-    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, ErrorStatus> {
+    pub fn default_float_rounding_mode(&self) -> Result<DefaultFloatRoundingMode, Error> {
         get_info(|x| {
             self.get_info(ExtProgramInfo::DefaultFloatRoundingMode, x)
         })
@@ -104,7 +113,7 @@ impl ExtProgram {
     }
 }
 
-impl Drop for ExtProgram {
+impl<'rt> Drop for ExtProgram<'rt> {
     fn drop(&mut self) {
         if self.handle.handle != 0 {
             unsafe {
@@ -121,21 +130,21 @@ pub struct ExtCodeObjectWriter {
 }
 
 impl ExtCodeObjectWriter {
-    pub fn new() -> Result<ExtCodeObjectWriter, ErrorStatus> {
+    pub fn new() -> Result<ExtCodeObjectWriter, Error> {
         let mut buf: Vec<u8> = Vec::new();
         let p: *mut c_void = &mut buf as *mut _ as *mut c_void;
         unsafe {
-            let handle: ExtCodeObjectWriterHandle = zeroed();
+            let mut handle: ExtCodeObjectWriterHandle = zeroed();
             check(
                 hsa_ext_code_object_writer_create_from_memory(
                     object_writer_memory_alloc,
                     p,
-                    &handle,
+                    &mut handle,
                 ),
                 (),
             ).map(|_| {
                 ExtCodeObjectWriter {
-                    handle: handle,
+                    handle,
                     buffer: buf,
                 }
             })
@@ -0,0 +1,406 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use native::*;
+use elf::{self, u16_at, u32_at, ElfNote, ElfSection, ElfSymbol, SHT_NOTE};
+use code_object::CodeObjectReader;
+use executable::Executable;
+use super::{Error, ErrorStatus};
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFOSABI_AMDGPU_HSA: u8 = 64;
+const ET_DYN: u16 = 3;
+const EM_AMDGPU: u16 = 224;
+
+const EF_AMDGPU_MACH: u32 = 0xff;
+const EF_AMDGPU_FEATURE_XNACK_V3: u32 = 0x100;
+const EF_AMDGPU_FEATURE_SRAMECC_V3: u32 = 0x200;
+const EF_AMDGPU_FEATURE_XNACK_V4: u32 = 0x300;
+const EF_AMDGPU_FEATURE_SRAMECC_V4: u32 = 0xc00;
+
+const NT_AMD_HSA_ISA_VERSION: u32 = 3;
+
+const TRIPLE: &str = "amdgcn-amd-amdhsa";
+
+// EF_AMDGPU_MACH_AMDGCN_* from llvm/BinaryFormat/ELF.h
+fn processor(mach: u32) -> Option<&'static str> {
+    Some(match mach {
+        0x020 => "gfx600",
+        0x021 => "gfx601",
+        0x03a => "gfx602",
+        0x022 => "gfx700",
+        0x023 => "gfx701",
+        0x024 => "gfx702",
+        0x025 => "gfx703",
+        0x026 => "gfx704",
+        0x03b => "gfx705",
+        0x028 => "gfx801",
+        0x029 => "gfx802",
+        0x02a => "gfx803",
+        0x03c => "gfx805",
+        0x02b => "gfx810",
+        0x02c => "gfx900",
+        0x02d => "gfx902",
+        0x02e => "gfx904",
+        0x02f => "gfx906",
+        0x030 => "gfx908",
+        0x031 => "gfx909",
+        0x032 => "gfx90c",
+        0x03f => "gfx90a",
+        0x040 => "gfx940",
+        0x04b => "gfx941",
+        0x04c => "gfx942",
+        0x033 => "gfx1010",
+        0x034 => "gfx1011",
+        0x035 => "gfx1012",
+        0x042 => "gfx1013",
+        0x036 => "gfx1030",
+        0x037 => "gfx1031",
+        0x038 => "gfx1032",
+        0x039 => "gfx1033",
+        0x03e => "gfx1034",
+        0x03d => "gfx1035",
+        0x045 => "gfx1036",
+        0x041 => "gfx1100",
+        0x046 => "gfx1101",
+        0x047 => "gfx1102",
+        0x044 => "gfx1103",
+        0x043 => "gfx1150",
+        0x04a => "gfx1151",
+        0x048 => "gfx1200",
+        0x04e => "gfx1201",
+        _ => return None,
+    })
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TargetFeature {
+    Unsupported,
+    Any,
+    Off,
+    On,
+}
+
+impl TargetFeature {
+    fn v4(flags: u32, mask: u32) -> TargetFeature {
+        // the four settings are encoded in the two bits under the mask
+        let low = mask & mask.wrapping_neg();
+        match (flags & mask) / low {
+            0 => TargetFeature::Unsupported,
+            1 => TargetFeature::Any,
+            2 => TargetFeature::Off,
+            _ => TargetFeature::On,
+        }
+    }
+
+    // a code object built for Any runs with the feature either on or off
+    fn accepts(self, agent: TargetFeature) -> bool {
+        match self {
+            TargetFeature::Unsupported | TargetFeature::Any => true,
+            _ => self == agent,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TargetId {
+    processor: String,
+    sramecc: TargetFeature,
+    xnack: TargetFeature,
+}
+
+impl TargetId {
+    // accepts full target IDs such as "amdgcn-amd-amdhsa--gfx90a:sramecc+:xnack-" and
+    // bare processor names; a feature that is not mentioned may take either setting
+    pub fn parse(name: &str) -> Option<TargetId> {
+        let name = name.trim_end_matches('\0');
+        let target = match name.find("--") {
+            Some(i) if name[..i].starts_with(TRIPLE) => &name[i + 2..],
+            Some(_) => return None,
+            None => name,
+        };
+        let mut parts = target.split(':');
+        let processor = parts.next().unwrap_or("");
+        if !processor.starts_with("gfx") {
+            return None;
+        }
+        let mut id = TargetId {
+            processor: processor.to_string(),
+            sramecc: TargetFeature::Any,
+            xnack: TargetFeature::Any,
+        };
+        for part in parts {
+            let (feature, setting) = part.split_at(part.len().saturating_sub(1));
+            let setting = match setting {
+                "+" => TargetFeature::On,
+                "-" => TargetFeature::Off,
+                _ => return None,
+            };
+            match feature {
+                "sramecc" => id.sramecc = setting,
+                "xnack" => id.xnack = setting,
+                _ => return None,
+            }
+        }
+        Some(id)
+    }
+
+    pub fn processor(&self) -> &str {
+        &self.processor
+    }
+
+    pub fn sramecc(&self) -> TargetFeature {
+        self.sramecc
+    }
+
+    pub fn xnack(&self) -> TargetFeature {
+        self.xnack
+    }
+
+    // whether a code object built for self runs on an agent whose ISA is `agent`
+    pub fn is_compatible_with(&self, agent: &TargetId) -> bool {
+        self.processor == agent.processor && self.sramecc.accepts(agent.sramecc) &&
+            self.xnack.accepts(agent.xnack)
+    }
+}
+
+impl fmt::Display for TargetId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}--{}", TRIPLE, self.processor)?;
+        for &(name, setting) in &[("sramecc", self.sramecc), ("xnack", self.xnack)] {
+            match setting {
+                TargetFeature::On => write!(f, ":{}+", name)?,
+                TargetFeature::Off => write!(f, ":{}-", name)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn invalid<C: Into<String>>(context: C) -> Error {
+    Error::from(ErrorStatus::InvalidCodeObject).with_context(context)
+}
+
+fn all_notes<'b>(bytes: &'b [u8], sections: &[ElfSection]) -> Result<Vec<ElfNote<'b>>, Error> {
+    let mut notes = Vec::new();
+    for section in sections.iter().filter(|s| s.kind == SHT_NOTE) {
+        notes.extend(elf::notes(elf::section_data(bytes, section)?)?);
+    }
+    Ok(notes)
+}
+
+pub struct Hsaco<'a> {
+    bytes: Cow<'a, [u8]>,
+    version: u32,
+    target: TargetId,
+}
+
+impl<'a> Hsaco<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Hsaco<'a>, Error> {
+        Hsaco::from_cow(Cow::Borrowed(bytes))
+    }
+
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Hsaco<'static>, Error> {
+        Hsaco::from_cow(Cow::Owned(bytes))
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Hsaco<'static>, Error> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            Error::from(ErrorStatus::InvalidFile).with_context(format!("{}: {}", path.display(), e))
+        })?;
+        Hsaco::from_vec(bytes).map_err(|e| {
+            let context = format!("{}: {}", path.display(), e.context().unwrap_or(""));
+            e.with_context(context)
+        })
+    }
+
+    fn from_cow(bytes: Cow<'a, [u8]>) -> Result<Hsaco<'a>, Error> {
+        let (version, target) = {
+            let b: &[u8] = &bytes;
+            if b.len() < 64 || &b[..4] != b"\x7fELF" {
+                return Err(invalid("not an ELF file"));
+            }
+            if b[4] != ELFCLASS64 || b[5] != ELFDATA2LSB {
+                return Err(invalid("not a 64-bit little-endian ELF file"));
+            }
+            if b[7] != ELFOSABI_AMDGPU_HSA || u16_at(b, 18)? != EM_AMDGPU {
+                return Err(invalid("not an AMDGPU HSA code object"));
+            }
+            if u16_at(b, 16)? != ET_DYN {
+                return Err(invalid("not a shared object; link the code object first"));
+            }
+            let version = b[8] as u32 + 2;
+            let target = if version < 3 {
+                Hsaco::target_from_notes(b)?
+            } else {
+                Hsaco::target_from_flags(version, u32_at(b, 48)?)?
+            };
+            (version, target)
+        };
+        Ok(Hsaco {
+            bytes,
+            version,
+            target,
+        })
+    }
+
+    // v2 records gfx<major><minor><stepping> in an ISA version note and has no
+    // encoding for xnack or sramecc
+    fn target_from_notes(bytes: &[u8]) -> Result<TargetId, Error> {
+        let sections = elf::sections(bytes)?;
+        let notes = all_notes(bytes, &sections)?;
+        let desc = notes.iter()
+            .find(|n| n.name == "AMD" && n.kind == NT_AMD_HSA_ISA_VERSION)
+            .map(|n| n.desc)
+            .ok_or_else(|| invalid("code object v2 has no ISA version note"))?;
+        let (major, minor, stepping) = (u32_at(desc, 4)?, u32_at(desc, 8)?, u32_at(desc, 12)?);
+        Ok(TargetId {
+            processor: format!("gfx{}{}{:x}", major, minor, stepping),
+            sramecc: TargetFeature::Any,
+            xnack: TargetFeature::Any,
+        })
+    }
+
+    fn target_from_flags(version: u32, flags: u32) -> Result<TargetId, Error> {
+        let mach = flags & EF_AMDGPU_MACH;
+        let processor = processor(mach)
+            .ok_or_else(|| invalid(format!("unknown AMDGPU processor {:#x}", mach)))?;
+        let (sramecc, xnack) = if version == 3 {
+            let v3 = |bit| if flags & bit != 0 { TargetFeature::On } else { TargetFeature::Any };
+            (v3(EF_AMDGPU_FEATURE_SRAMECC_V3), v3(EF_AMDGPU_FEATURE_XNACK_V3))
+        } else {
+            (
+                TargetFeature::v4(flags, EF_AMDGPU_FEATURE_SRAMECC_V4),
+                TargetFeature::v4(flags, EF_AMDGPU_FEATURE_XNACK_V4),
+            )
+        };
+        Ok(TargetId {
+            processor: processor.to_string(),
+            sramecc,
+            xnack,
+        })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn target(&self) -> &TargetId {
+        &self.target
+    }
+
+    pub fn sections(&self) -> Result<Vec<ElfSection>, Error> {
+        elf::sections(&self.bytes)
+    }
+
+    pub fn section_data(&self, section: &ElfSection) -> Result<&[u8], Error> {
+        elf::section_data(&self.bytes, section)
+    }
+
+    pub fn symbols(&self) -> Result<Vec<ElfSymbol>, Error> {
+        elf::symbols(&self.bytes, &self.sections()?)
+    }
+
+    pub fn notes(&self) -> Result<Vec<ElfNote<'_>>, Error> {
+        all_notes(&self.bytes, &self.sections()?)
+    }
+
+    pub fn matching_isa(&self, agent: Agent) -> Result<ISA, Error> {
+        let mut names = Vec::new();
+        for isa in agent.isas()? {
+            let name = isa.name()?;
+            if let Some(target) = TargetId::parse(&name) {
+                if self.target.is_compatible_with(&target) {
+                    return Ok(isa);
+                }
+            }
+            names.push(name.trim_end_matches('\0').to_string());
+        }
+        Err(Error::from(ErrorStatus::IncompatibleArguments).with_context(format!(
+            "code object targets {} but the agent supports {:?}",
+            self.target,
+            names
+        )))
+    }
+}
+
+impl<'rt> Executable<'rt> {
+    pub fn load_hsaco<T: Into<Vec<u8>>>(
+        &self,
+        agent: Agent,
+        hsaco: &Hsaco,
+        options: T,
+    ) -> Result<LoadedCodeObject, Error> {
+        hsaco.matching_isa(agent)?;
+        let reader = CodeObjectReader::from_memory(hsaco.bytes())?;
+        self.load_agent_code_object(agent, &reader, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ErrorStatus;
+    use super::{Hsaco, TargetFeature, TargetId};
+
+    fn header(abi_version: u8, flags: u32) -> Vec<u8> {
+        let mut b = vec![0u8; 64];
+        b[..4].copy_from_slice(b"\x7fELF");
+        b[4] = 2;
+        b[5] = 1;
+        b[6] = 1;
+        b[7] = 64;
+        b[8] = abi_version;
+        b[16..18].copy_from_slice(&3u16.to_le_bytes());
+        b[18..20].copy_from_slice(&224u16.to_le_bytes());
+        b[48..52].copy_from_slice(&flags.to_le_bytes());
+        b
+    }
+
+    #[test]
+    fn header_and_target_are_validated() {
+        // v5, gfx90a, sramecc+ (0xc00), xnack any (0x100)
+        let bytes = header(3, 0x3f | 0xc00 | 0x100);
+        let hsaco = Hsaco::parse(&bytes).unwrap();
+        assert_eq!(hsaco.version(), 5);
+        assert_eq!(hsaco.target().to_string(), "amdgcn-amd-amdhsa--gfx90a:sramecc+");
+        assert_eq!(hsaco.target().xnack(), TargetFeature::Any);
+
+        let agent = TargetId::parse("amdgcn-amd-amdhsa--gfx90a:sramecc+:xnack-").unwrap();
+        assert!(hsaco.target().is_compatible_with(&agent));
+        let agent = TargetId::parse("amdgcn-amd-amdhsa--gfx90a:sramecc-:xnack-").unwrap();
+        assert!(!hsaco.target().is_compatible_with(&agent));
+        assert!(TargetId::parse("AMD:AMDGPU:9:0:0").is_none());
+
+        let mut elf32 = bytes.clone();
+        elf32[4] = 1;
+        for bad in &[&bytes[..63], &header(0, 0x3f)[..], &header(2, 0xfe)[..], &elf32[..]] {
+            assert_eq!(Hsaco::parse(bad).err().unwrap(), ErrorStatus::InvalidCodeObject);
+        }
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn loading_requires_a_matching_isa() {
+        use native::*;
+        use executable::Executable;
+
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "")
+                .unwrap();
+        let bytes = header(2, 0x3f);
+        let e = executable.load_hsaco(agent, &Hsaco::parse(&bytes).unwrap(), "").unwrap_err();
+        assert_eq!(e, ErrorStatus::IncompatibleArguments);
+        assert!(e.context().unwrap().contains("gfx90a"));
+    }
+}
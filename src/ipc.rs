@@ -0,0 +1,282 @@
+use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::mem::{size_of, zeroed};
+use std::ptr::null_mut;
+
+use native::*;
+use runtime::Runtime;
+use region::Memory;
+use buffer::DeviceBuffer;
+use kernarg::KernelArg;
+use memory_pool::PoolMemory;
+use signal::{Signal, SignalBase};
+use super::{check, Error, ErrorStatus};
+
+// wire format: a 4-byte tag, the eight little-endian words of the runtime handle and,
+// for memory, the exported length as a little-endian u64
+const MEMORY_TAG: &[u8; 4] = b"HSAM";
+const SIGNAL_TAG: &[u8; 4] = b"HSAS";
+const HANDLE_BYTES: usize = 32;
+
+fn write_handle(tag: &[u8; 4], handle: &IpcHandle, out: &mut Vec<u8>) {
+    out.extend_from_slice(tag);
+    for word in &handle.handle {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn read_handle(tag: &[u8; 4], bytes: &[u8]) -> Result<IpcHandle, Error> {
+    if bytes.len() < 4 + HANDLE_BYTES || &bytes[..4] != tag {
+        return Err(Error::from(ErrorStatus::InvalidArgument)
+            .with_context(format!("not a {} IPC handle", String::from_utf8_lossy(tag))));
+    }
+    let mut handle = IpcHandle { handle: [0; 8] };
+    for (i, word) in handle.handle.iter_mut().enumerate() {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(&bytes[4 + i * 4..8 + i * 4]);
+        *word = u32::from_le_bytes(b);
+    }
+    Ok(handle)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IpcMemoryHandle {
+    handle: IpcHandle,
+    len: usize,
+}
+
+impl IpcMemoryHandle {
+    pub const SIZE: usize = 4 + HANDLE_BYTES + 8;
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(IpcMemoryHandle::SIZE);
+        write_handle(MEMORY_TAG, &self.handle, &mut out);
+        out.extend_from_slice(&(self.len as u64).to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<IpcMemoryHandle, Error> {
+        if bytes.len() != IpcMemoryHandle::SIZE {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("IPC memory handle has the wrong length"));
+        }
+        let handle = read_handle(MEMORY_TAG, bytes)?;
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&bytes[4 + HANDLE_BYTES..]);
+        Ok(IpcMemoryHandle {
+            handle,
+            len: u64::from_le_bytes(len) as usize,
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IpcSignalHandle {
+    handle: IpcHandle,
+}
+
+impl IpcSignalHandle {
+    pub const SIZE: usize = 4 + HANDLE_BYTES;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(IpcSignalHandle::SIZE);
+        write_handle(SIGNAL_TAG, &self.handle, &mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<IpcSignalHandle, Error> {
+        if bytes.len() != IpcSignalHandle::SIZE {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("IPC signal handle has the wrong length"));
+        }
+        read_handle(SIGNAL_TAG, bytes).map(|handle| IpcSignalHandle { handle })
+    }
+}
+
+// borrows the exported object so that it outlives every handle given out while the
+// export exists; importers must detach before the export is dropped
+pub struct IpcExport<'a, H: Copy> {
+    handle: H,
+    source: PhantomData<&'a ()>,
+}
+
+impl<'a, H: Copy> IpcExport<'a, H> {
+    pub fn handle(&self) -> H {
+        self.handle
+    }
+}
+
+fn export_memory<'a>(ptr: *const c_void, len: usize) -> Result<IpcExport<'a, IpcMemoryHandle>, Error> {
+    if len == 0 {
+        return Err(ErrorStatus::InvalidArgument.into());
+    }
+    let mut handle = IpcHandle { handle: [0; 8] };
+    check(unsafe { hsa_amd_ipc_memory_create(ptr as *mut c_void, len, &mut handle) }, ())?;
+    Ok(IpcExport {
+        handle: IpcMemoryHandle {
+            handle,
+            len,
+        },
+        source: PhantomData,
+    })
+}
+
+impl<'rt, T> Memory<'rt, T> {
+    pub fn ipc_export(&self) -> Result<IpcExport<'_, IpcMemoryHandle>, Error> {
+        export_memory(self.as_ptr() as *const c_void, self.size())
+    }
+}
+
+impl<'rt, T: KernelArg> DeviceBuffer<'rt, T> {
+    pub fn ipc_export(&self) -> Result<IpcExport<'_, IpcMemoryHandle>, Error> {
+        export_memory(self.as_ptr() as *const c_void, self.len() * size_of::<T>())
+    }
+}
+
+impl<'rt> PoolMemory<'rt> {
+    pub fn ipc_export(&self) -> Result<IpcExport<'_, IpcMemoryHandle>, Error> {
+        export_memory(self.as_ptr() as *const c_void, self.len())
+    }
+}
+
+pub struct IpcMemory<'rt> {
+    ptr: *mut u8,
+    len: usize,
+    runtime: PhantomData<&'rt Runtime>,
+}
+
+impl<'rt> IpcMemory<'rt> {
+    pub fn attach(
+        _runtime: &'rt Runtime,
+        handle: &IpcMemoryHandle,
+        agents: &[Agent],
+    ) -> Result<IpcMemory<'rt>, Error> {
+        let mut ptr: *mut c_void = null_mut();
+        check(
+            unsafe {
+                hsa_amd_ipc_memory_attach(
+                    &handle.handle,
+                    handle.len,
+                    agents.len() as u32,
+                    agents.as_ptr(),
+                    &mut ptr,
+                )
+            },
+            (),
+        )?;
+        Ok(IpcMemory {
+            ptr: ptr as *mut u8,
+            len: handle.len,
+            runtime: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+}
+
+impl<'rt> Drop for IpcMemory<'rt> {
+    fn drop(&mut self) {
+        unsafe {
+            hsa_amd_ipc_memory_detach(self.ptr as *mut c_void);
+        }
+    }
+}
+
+impl<'rt> Signal<'rt> {
+    // only signals created this way can be exported
+    pub fn new_ipc(
+        _runtime: &'rt Runtime,
+        initial_value: SignalValue,
+    ) -> Result<Signal<'rt>, Error> {
+        let mut handle: SignalHandle = unsafe { zeroed() };
+        check(
+            unsafe {
+                hsa_amd_signal_create(
+                    initial_value,
+                    0,
+                    [].as_ptr(),
+                    AmdSignalAttribute::Ipc as u64,
+                    &mut handle,
+                )
+            },
+            (),
+        )?;
+        Ok(Signal::from_handle(handle))
+    }
+
+    pub fn ipc_export(&self) -> Result<IpcExport<'_, IpcSignalHandle>, Error> {
+        let mut handle = IpcHandle { handle: [0; 8] };
+        check(unsafe { hsa_amd_ipc_signal_create(self.handle(), &mut handle) }, ())?;
+        Ok(IpcExport {
+            handle: IpcSignalHandle { handle },
+            source: PhantomData,
+        })
+    }
+
+    // the attached signal is released by dropping it, like any other Signal
+    pub fn ipc_attach(_runtime: &'rt Runtime, handle: &IpcSignalHandle) -> Result<Signal<'rt>, Error> {
+        let mut signal: SignalHandle = unsafe { zeroed() };
+        check(unsafe { hsa_amd_ipc_signal_attach(&handle.handle, &mut signal) }, ())?;
+        Ok(Signal::from_handle(signal))
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use signal::{Signal, SignalBase};
+    use region::Memory;
+    use ErrorStatus;
+    use super::{IpcMemory, IpcMemoryHandle, IpcSignalHandle};
+
+    #[test]
+    fn handles_round_trip_through_bytes() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = agent.fine_grained_global_regions().unwrap()[0];
+        let memory = Memory::allocate(&runtime, region, 64).unwrap();
+        let export = memory.ipc_export().unwrap();
+        let bytes = export.handle().to_bytes();
+        assert_eq!((bytes.len(), &bytes[..4]), (IpcMemoryHandle::SIZE, &b"HSAM"[..]));
+        let handle = IpcMemoryHandle::from_bytes(&bytes).unwrap();
+        assert_eq!(handle, export.handle());
+        {
+            let imported = IpcMemory::attach(&runtime, &handle, &[agent]).unwrap();
+            unsafe { *imported.as_mut_ptr().add(5) = 42 };
+            assert_eq!(unsafe { *memory.as_ptr().add(5) }, 42);
+        }
+        assert_eq!(IpcSignalHandle::from_bytes(&bytes).unwrap_err(), ErrorStatus::InvalidArgument);
+        assert!(IpcMemoryHandle::from_bytes(&bytes[1..]).is_err());
+
+        let signal = Signal::new_ipc(&runtime, 1).unwrap();
+        let bytes = signal.ipc_export().unwrap().handle().to_bytes();
+        let handle = IpcSignalHandle::from_bytes(&bytes).unwrap();
+        let attached = Signal::ipc_attach(&runtime, &handle).unwrap();
+        attached.store_screlease(0);
+        drop(attached);
+        assert_eq!(signal.load_scacquire(), 0);
+        assert!(Signal::new(&runtime, 0, &[]).unwrap().ipc_export().is_err());
+    }
+}
@@ -1,54 +1,53 @@
 use std::os::raw::{c_void, c_char};
-use std::mem::zeroed;
+use std::mem::{zeroed, MaybeUninit};
 
 use native::*;
-use super::{bitflags, check, get_info, iter_callback_helper, ErrorStatus, Flags};
+use super::{bitflags, check, get_info, iter_callback_helper, Error, Flags};
 
 impl ISA {
-    pub fn new(name: &str) -> Result<ISA, ErrorStatus> {
+    pub fn new(name: &str) -> Result<ISA, Error> {
         unsafe {
-            let isa = zeroed();
-            check(hsa_isa_from_name(name.as_ptr() as *const c_char, &isa), isa)
+            let mut isa = zeroed();
+            check(hsa_isa_from_name(name.as_ptr() as *const c_char, &mut isa), isa)
         }
     }
 
-    pub fn name(&self) -> Result<String, ErrorStatus> {
+    pub fn name(&self) -> Result<String, Error> {
         let len: u32 = get_info(|x| self.get_info(ISAInfo::NameLength, x))?;
-        let mut buf: Vec<u8> = Vec::with_capacity(len as usize + 1);
+        let mut buf: Vec<u8> = vec![0; len as usize + 1];
         unsafe {
-            buf.set_len(len as usize);
             let ptr = buf.as_mut_ptr() as *mut c_void;
             check(hsa_isa_get_info_alt(*self, ISAInfo::Name, ptr), ())
-                .map(|_| String::from_utf8_lossy(&buf).to_string())
+                .map(|_| String::from_utf8_lossy(&buf[..len as usize]).to_string())
         }
     }
 
     #[deprecated]
-    pub fn call_convention_count(&self) -> Result<u32, ErrorStatus> {
+    pub fn call_convention_count(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(ISAInfo::CallConvertionCount, x))
     }
 
     #[deprecated]
-    pub fn call_convention_info_wavefront_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn call_convention_info_wavefront_size(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ISAInfo::CallConvertionInfoWavefrontSize, x)
         })
     }
 
     #[deprecated]
-    pub fn call_convention_info_wavefronts_per_compute_unit(&self) -> Result<u32, ErrorStatus> {
+    pub fn call_convention_info_wavefronts_per_compute_unit(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(ISAInfo::CallConvertionInfoWavefrontsPerComputeUnit, x)
         })
     }
 
-    pub fn machine_models(&self) -> Result<Flags<MachineModel>, ErrorStatus> {
+    pub fn machine_models(&self) -> Result<Flags<MachineModel>, Error> {
         get_info(|x| self.get_info(ISAInfo::MachineModels, x)).map(|x: [bool; 2]| {
             self.boolflags(&x, &[MachineModel::Small, MachineModel::Large])
         })
     }
 
-    pub fn profiles(&self) -> Result<Flags<Profile>, ErrorStatus> {
+    pub fn profiles(&self) -> Result<Flags<Profile>, Error> {
         get_info(|x| self.get_info(ISAInfo::Profiles, x)).map(|x: [bool; 2]| {
             self.boolflags(&x, &[Profile::Base, Profile::Full])
         })
@@ -56,7 +55,7 @@ impl ISA {
 
     pub fn default_float_rounding_modes(
         &self,
-    ) -> Result<Flags<DefaultFloatRoundingMode>, ErrorStatus> {
+    ) -> Result<Flags<DefaultFloatRoundingMode>, Error> {
         get_info(|x| self.get_info(ISAInfo::DefaultFloatRoundingModes, x)).map(|x: [bool; 3]| {
             self.boolflags(
                 &x,
@@ -71,7 +70,7 @@ impl ISA {
 
     pub fn base_profile_default_float_rounding_modes(
         &self,
-    ) -> Result<Flags<DefaultFloatRoundingMode>, ErrorStatus> {
+    ) -> Result<Flags<DefaultFloatRoundingMode>, Error> {
         get_info(|x| {
             self.get_info(ISAInfo::BaseProfileDefaultFloatRoundingModes, x)
         }).map(|x: [bool; 3]| {
@@ -86,34 +85,34 @@ impl ISA {
         })
     }
 
-    pub fn fast_f16_operation(&self) -> Result<bool, ErrorStatus> {
+    pub fn fast_f16_operation(&self) -> Result<bool, Error> {
         get_info(|x| self.get_info(ISAInfo::FastF16Operation, x))
     }
 
-    pub fn workgroup_max_dim(&self) -> Result<[u16; 3], ErrorStatus> {
+    pub fn workgroup_max_dim(&self) -> Result<[u16; 3], Error> {
         get_info(|x| self.get_info(ISAInfo::WorkgroupMaxDim, x))
     }
 
-    pub fn workgroup_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn workgroup_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(ISAInfo::WorkgroupMaxSize, x))
     }
 
-    pub fn grid_max_dim(&self) -> Result<Dim3, ErrorStatus> {
+    pub fn grid_max_dim(&self) -> Result<Dim3, Error> {
         get_info(|x| self.get_info(ISAInfo::GridMaxDim, x))
     }
 
-    pub fn grid_max_size(&self) -> Result<u64, ErrorStatus> {
+    pub fn grid_max_size(&self) -> Result<u64, Error> {
         get_info(|x| self.get_info(ISAInfo::GridMaxSize, x))
     }
 
-    pub fn fbarrier_max_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn fbarrier_max_size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(ISAInfo::FbarrierMaxSize, x))
     }
 
     pub fn exception_policies(
         &self,
         profile: Profile,
-    ) -> Result<Flags<ExceptionPolicy>, ErrorStatus> {
+    ) -> Result<Flags<ExceptionPolicy>, Error> {
         let mut mask: u16 = 0;
         check(unsafe { hsa_isa_get_exception_policies(*self, profile, &mut mask) }, ()).map(|_| {
             bitflags(mask as u32)
@@ -124,14 +123,15 @@ impl ISA {
         &self,
         fp_type: FpType,
         flush_mode: FlushMode,
-    ) -> Result<RoundMethod, ErrorStatus> {
+    ) -> Result<RoundMethod, Error> {
         unsafe {
-            let m: RoundMethod = zeroed();
-            check(hsa_isa_get_round_method(*self, fp_type, flush_mode, &m), m)
+            let mut m: MaybeUninit<RoundMethod> = MaybeUninit::uninit();
+            check(hsa_isa_get_round_method(*self, fp_type, flush_mode, m.as_mut_ptr()), ())
+                .map(|_| m.assume_init())
         }
     }
 
-    pub fn wavefronts(&self) -> Result<Vec<Wavefront>, ErrorStatus> {
+    pub fn wavefronts(&self) -> Result<Vec<Wavefront>, Error> {
         let mut v: Vec<Wavefront> = Vec::new();
         let p: *mut c_void = &mut v as *mut _ as *mut c_void;
         check(
@@ -159,18 +159,21 @@ impl ISA {
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<usize> for MachineModel {
     fn into(self) -> usize {
         self as usize
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<usize> for Profile {
     fn into(self) -> usize {
         self as usize
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<usize> for DefaultFloatRoundingMode {
     fn into(self) -> usize {
         self as usize
@@ -0,0 +1,727 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::mem::{align_of, forget, size_of};
+use std::pin::Pin;
+use std::ptr::{copy_nonoverlapping, null_mut, read_unaligned};
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use native::*;
+use access;
+use super::{check, Error, ErrorStatus};
+use queue::{CompletionFuture, Queue};
+use signal::{Signal, SignalBase};
+use waiter::SignalFuture;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernargField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub alignment: usize,
+    pub access: Option<AccessPermission>,
+}
+
+/// # Safety
+///
+/// The type's bytes are copied into the kernarg segment as they are, so its layout must be
+/// what the kernel expects for the argument. Device buffers of the type are read back from
+/// whatever the device wrote, so every bit pattern must be a valid value.
+pub unsafe trait KernelArg: Copy {
+    const ACCESS: Option<AccessPermission> = None;
+}
+
+macro_rules! impl_kernel_arg {
+    ($($t:ty)*) => {$(
+        unsafe impl KernelArg for $t {}
+    )*}
+}
+
+// usize, isize and raw pointers are left out: their width follows the host, and a
+// raw pointer may point anywhere; device pointers go through DevicePtr instead
+impl_kernel_arg!(u8 u16 u32 u64 i8 i16 i32 i64 f32 f64);
+
+unsafe impl<T: KernelArg, const N: usize> KernelArg for [T; N] {}
+
+pub struct DevicePtr<'a, T: 'a> {
+    ptr: *mut T,
+    marker: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T> DevicePtr<'a, T> {
+    pub(crate) fn new(ptr: *mut T) -> DevicePtr<'a, T> {
+        DevicePtr {
+            ptr,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+}
+
+impl<'a, T> Clone for DevicePtr<'a, T> {
+    fn clone(&self) -> DevicePtr<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T> Copy for DevicePtr<'a, T> {}
+
+unsafe impl<'a, T> KernelArg for DevicePtr<'a, T> {
+    const ACCESS: Option<AccessPermission> = Some(AccessPermission::RW);
+}
+
+pub struct DeviceConstPtr<'a, T: 'a> {
+    ptr: *const T,
+    marker: PhantomData<&'a [T]>,
+}
+
+impl<'a, T> DeviceConstPtr<'a, T> {
+    pub(crate) fn new(ptr: *const T) -> DeviceConstPtr<'a, T> {
+        DeviceConstPtr {
+            ptr,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+}
+
+impl<'a, T> Clone for DeviceConstPtr<'a, T> {
+    fn clone(&self) -> DeviceConstPtr<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T> Copy for DeviceConstPtr<'a, T> {}
+
+unsafe impl<'a, T> KernelArg for DeviceConstPtr<'a, T> {
+    const ACCESS: Option<AccessPermission> = Some(AccessPermission::RO);
+}
+
+/// # Safety
+///
+/// `FIELDS`, `SIZE` and `ALIGNMENT` must describe the type's `repr(C)` layout exactly.
+pub unsafe trait KernelArgs: Copy {
+    const FIELDS: &'static [KernargField];
+    const SIZE: usize;
+    const ALIGNMENT: usize;
+
+    fn validate(symbol: &ExecutableSymbol) -> Result<(), Error> {
+        let size = symbol.kernel_kernarg_segment_size()? as usize;
+        let alignment = symbol.kernel_kernarg_segment_alignment()? as usize;
+        if Self::SIZE != size {
+            return Err(Error::from(ErrorStatus::InvalidArgument).with_context(format!(
+                "kernel arguments are {} bytes but the kernel expects {}",
+                Self::SIZE,
+                size
+            )));
+        }
+        if Self::ALIGNMENT > alignment.max(16) {
+            return Err(Error::from(ErrorStatus::InvalidArgument).with_context(format!(
+                "kernel arguments need {}-byte alignment but the kernel provides {}",
+                Self::ALIGNMENT,
+                alignment
+            )));
+        }
+        Ok(())
+    }
+}
+
+// a single argument is a kernarg segment of its own
+unsafe impl<T: KernelArg> KernelArgs for T {
+    const FIELDS: &'static [KernargField] = &[KernargField {
+        name: "0",
+        offset: 0,
+        size: size_of::<T>(),
+        alignment: align_of::<T>(),
+        access: T::ACCESS,
+    }];
+    const SIZE: usize = size_of::<T>();
+    const ALIGNMENT: usize = align_of::<T>();
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EntryState {
+    Reserved,
+    Dispatched,
+    Abandoned,
+}
+
+struct Entry<'rt> {
+    id: u64,
+    start: usize,
+    state: EntryState,
+    signal: Signal<'rt>,
+}
+
+struct RingState<'rt> {
+    head: usize,
+    next_id: u64,
+    live: VecDeque<Entry<'rt>>,
+    signals: Vec<Signal<'rt>>,
+    // futures of dispatched slots, woken when their slot is reclaimed
+    wakers: Vec<(u64, Waker)>,
+}
+
+impl<'rt> RingState<'rt> {
+    fn reclaim(&mut self) {
+        while let Some(entry) = self.live.front() {
+            let done = match entry.state {
+                EntryState::Reserved => false,
+                EntryState::Dispatched => entry.signal.load_scacquire() == 0,
+                EntryState::Abandoned => true,
+            };
+            if !done {
+                break;
+            }
+            let entry = self.live.pop_front().unwrap();
+            self.signals.push(entry.signal);
+        }
+        if self.live.is_empty() {
+            self.head = 0;
+        }
+        let oldest = self.live.front().map(|e| e.id).unwrap_or(self.next_id);
+        for (_, waker) in self.wakers.extract_if(.., |&mut (id, _)| id < oldest) {
+            waker.wake();
+        }
+    }
+
+    fn is_reclaimed(&mut self, id: u64) -> bool {
+        self.reclaim();
+        self.live.front().is_none_or(|e| id < e.id)
+    }
+
+    fn fit(&self, size: usize, align: usize, capacity: usize) -> Option<usize> {
+        let start = align_up(self.head, align);
+        let tail = match self.live.front() {
+            Some(entry) => entry.start,
+            None => return if size <= capacity { Some(0) } else { None },
+        };
+        if self.head < tail {
+            return if start + size <= tail { Some(start) } else { None };
+        }
+        if self.head == tail {
+            None
+        } else if start + size <= capacity {
+            Some(start)
+        } else if size <= tail {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn entry(&mut self, id: u64) -> Option<&mut Entry<'rt>> {
+        self.live.iter_mut().find(|e| e.id == id)
+    }
+}
+
+// how long waits on dispatched slots go between checks of the queue state
+const POLL_HINT: u64 = 1_000_000;
+
+fn align_up(x: usize, align: usize) -> usize {
+    (x + align - 1) & !(align - 1)
+}
+
+pub struct KernargRing<'q, 'rt: 'q> {
+    queue: &'q Queue<'rt>,
+    base: *mut u8,
+    capacity: usize,
+    alignment: usize,
+    state: Mutex<RingState<'rt>>,
+}
+
+impl<'rt> Queue<'rt> {
+    pub fn kernarg_ring(
+        &self,
+        region: Region,
+        capacity: usize,
+    ) -> Result<KernargRing<'_, 'rt>, Error> {
+        if capacity == 0 {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        let alignment = region.runtime_alloc_alignment()?;
+        let mut ptr: *mut c_void = null_mut();
+        check(unsafe { hsa_memory_allocate(region, capacity, &mut ptr) }, ())?;
+        Ok(KernargRing {
+            queue: self,
+            base: ptr as *mut u8,
+            capacity,
+            alignment,
+            state: Mutex::new(RingState {
+                head: 0,
+                next_id: 0,
+                live: VecDeque::new(),
+                signals: Vec::new(),
+                wakers: Vec::new(),
+            }),
+        })
+    }
+}
+
+impl<'q, 'rt> KernargRing<'q, 'rt> {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn queue(&self) -> &'q Queue<'rt> {
+        self.queue
+    }
+
+    pub fn in_flight(&self) -> usize {
+        let mut state = self.state();
+        state.reclaim();
+        state.live.len()
+    }
+
+    pub fn allocate(&self, symbol: &ExecutableSymbol) -> Result<KernargSlot<'_, 'q, 'rt>, Error> {
+        let size = symbol.kernel_kernarg_segment_size()? as usize;
+        let align = symbol.kernel_kernarg_segment_alignment()? as usize;
+        let mut slot = self.allocate_raw(size, align.max(16))?;
+        slot.symbol = Some(*symbol);
+        Ok(slot)
+    }
+
+    pub fn allocate_raw(
+        &self,
+        size: usize,
+        align: usize,
+    ) -> Result<KernargSlot<'_, 'q, 'rt>, Error> {
+        if !align.is_power_of_two() || align > self.alignment {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("kernarg alignment exceeds the region's runtime_alloc_alignment"));
+        }
+        if size > self.capacity {
+            return Err(Error::from(ErrorStatus::OutOfResources)
+                .with_context("kernarg segment is larger than the ring"));
+        }
+        // a zero-sized kernarg segment still gets a distinct slot so it can be recycled
+        let size = size.max(1);
+        loop {
+            let mut state = self.state();
+            state.reclaim();
+            if let Some(start) = state.fit(size, align, self.capacity) {
+                let signal = match state.signals.pop() {
+                    Some(signal) => signal,
+                    None => {
+                        let signal = Signal::create(0, &[])?;
+                        self.queue.state().track(signal.handle());
+                        signal
+                    }
+                };
+                let id = state.next_id;
+                state.next_id += 1;
+                state.head = start + size;
+                state.live.push_back(Entry {
+                    id,
+                    start,
+                    state: EntryState::Reserved,
+                    signal,
+                });
+                return Ok(KernargSlot {
+                    ring: self,
+                    id,
+                    ptr: unsafe { self.base.add(start) },
+                    size,
+                    symbol: None,
+                    pointers: Vec::new(),
+                    dispatched: false,
+                });
+            }
+            if state.live.front().map(|e| e.state) == Some(EntryState::Reserved) {
+                return Err(Error::from(ErrorStatus::OutOfResources)
+                    .with_context("kernarg ring is full of undispatched slots"));
+            }
+            drop(state);
+            // a failed queue never completes the slots that fill the ring
+            self.queue.state().usable()?;
+            thread::yield_now();
+        }
+    }
+
+    pub fn dispatch(
+        &self,
+        mut slot: KernargSlot<'_, 'q, 'rt>,
+        mut packet: KernelDispatchPacket,
+    ) -> Result<KernargCompletion<'_, 'q, 'rt>, Error> {
+        if packet.completion_signal.handle != 0 {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        self.check(&slot)?;
+        slot.dispatched = true;
+        let signal = {
+            let mut state = self.state();
+            let entry = state.entry(slot.id).unwrap();
+            entry.state = EntryState::Dispatched;
+            entry.signal.store_relaxed(1);
+            entry.signal.handle()
+        };
+        self.queue.state().reset(signal);
+        packet.kernarg_address = slot.ptr as *const c_void;
+        packet.completion_signal = signal;
+        match self.queue.submit(&packet) {
+            Ok(index) => Ok(KernargCompletion {
+                ring: self,
+                id: slot.id,
+                signal,
+                index,
+            }),
+            Err(e) => {
+                signal.store_relaxed(0);
+                Err(e)
+            }
+        }
+    }
+
+    // everything dispatch checks before it touches the queue
+    pub(crate) fn check(&self, slot: &KernargSlot<'_, 'q, 'rt>) -> Result<(), Error> {
+        if !::std::ptr::eq(slot.ring, self) {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        if let Some(agent) = self.queue.state().agent() {
+            access::validate(agent, &slot.pointers)?;
+        }
+        // a failed queue keeps the failure records of the signals it was running
+        self.queue.state().usable()
+    }
+
+    fn state(&self) -> MutexGuard<'_, RingState<'rt>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_reclaimed(&self, id: u64) -> bool {
+        self.state().is_reclaimed(id)
+    }
+
+    // false once the queue has failed with the signal still pending
+    fn retire<S: SignalBase>(&self, signal: &S) -> bool {
+        while signal.wait_scacquire(SignalCondition::Eq, 0, POLL_HINT, WaitState::Blocked) != 0 {
+            if self.queue.state().usable().is_err() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<'q, 'rt> Drop for KernargRing<'q, 'rt> {
+    fn drop(&mut self) {
+        let mut state = self.state();
+        let entries: Vec<Entry> = state.live.drain(..).collect();
+        // like a dropped Completion, a slot that a failed queue never retires leaks its
+        // signal, and the ring memory with it
+        let mut leaked = false;
+        let mut signals = Vec::new();
+        for entry in entries {
+            if entry.state == EntryState::Dispatched && !self.retire(&entry.signal) {
+                self.queue.state().untrack(entry.signal.handle());
+                forget(entry.signal);
+                leaked = true;
+            } else {
+                signals.push(entry.signal);
+            }
+        }
+        for signal in signals.into_iter().chain(state.signals.drain(..)) {
+            self.queue.state().untrack(signal.handle());
+        }
+        if !leaked {
+            unsafe {
+                hsa_memory_free(self.base as *mut c_void);
+            }
+        }
+    }
+}
+
+pub struct KernargSlot<'r, 'q: 'r, 'rt: 'q> {
+    ring: &'r KernargRing<'q, 'rt>,
+    id: u64,
+    ptr: *mut u8,
+    size: usize,
+    symbol: Option<ExecutableSymbol>,
+    pointers: Vec<(usize, AccessPermission)>,
+    dispatched: bool,
+}
+
+impl<'r, 'q, 'rt> KernargSlot<'r, 'q, 'rt> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    pub fn write<T: Copy>(&mut self, args: &T) -> Result<(), Error> {
+        if size_of::<T>() > self.size || !(self.ptr as usize).is_multiple_of(align_of::<T>()) {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("kernel arguments do not fit the kernarg slot"));
+        }
+        unsafe { copy_nonoverlapping(args as *const T, self.ptr as *mut T, 1) };
+        Ok(())
+    }
+
+    pub fn write_args<A: KernelArgs>(&mut self, args: &A) -> Result<(), Error> {
+        if let Some(ref symbol) = self.symbol {
+            A::validate(symbol)?;
+        }
+        if A::SIZE > self.size || !(self.ptr as usize).is_multiple_of(A::ALIGNMENT) {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("kernel arguments do not fit the kernarg slot"));
+        }
+        // only the kernarg segment itself is copied, not the struct's trailing padding
+        unsafe { copy_nonoverlapping(args as *const A as *const u8, self.ptr, A::SIZE) };
+        self.pointers.clear();
+        if cfg!(debug_assertions) {
+            for field in A::FIELDS {
+                if let Some(access) = field.access {
+                    let p = unsafe { (args as *const A as *const u8).add(field.offset) };
+                    let ptr = unsafe { read_unaligned(p as *const usize) };
+                    self.pointers.push((ptr, access));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.size {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("kernel arguments do not fit the kernarg slot"));
+        }
+        unsafe { copy_nonoverlapping(bytes.as_ptr(), self.ptr, bytes.len()) };
+        Ok(())
+    }
+}
+
+impl<'r, 'q, 'rt> Drop for KernargSlot<'r, 'q, 'rt> {
+    fn drop(&mut self) {
+        if !self.dispatched {
+            if let Some(entry) = self.ring.state().entry(self.id) {
+                entry.state = EntryState::Abandoned;
+            }
+        }
+    }
+}
+
+pub struct KernargCompletion<'r, 'q: 'r, 'rt: 'q> {
+    ring: &'r KernargRing<'q, 'rt>,
+    id: u64,
+    signal: SignalHandle,
+    index: u64,
+}
+
+impl<'r, 'q, 'rt> KernargCompletion<'r, 'q, 'rt> {
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn signal(&self) -> SignalHandle {
+        self.signal
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.ring.is_reclaimed(self.id) || self.signal.load_scacquire() == 0
+    }
+
+    pub fn wait_async(&self) -> KernargFuture<'_, 'q, 'rt> {
+        let future = SignalFuture::new(self.signal, SignalCondition::Eq, 0);
+        KernargFuture {
+            ring: self.ring,
+            id: self.id,
+            signal: self.signal,
+            future: CompletionFuture::new(future, self.ring.queue.state(), self.signal),
+        }
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        while !self.ring.is_reclaimed(self.id) &&
+            self.signal.wait_scacquire(SignalCondition::Eq, 0, POLL_HINT, WaitState::Blocked) != 0
+        {
+            self.ring.queue.state().usable()?;
+        }
+        self.ring.queue.state().result(self.signal)
+    }
+}
+
+// once the slot is reclaimed its signal may already run a later dispatch, so the ring is
+// asked first; signals are only reused after a reclaim, which wakes the futures of the
+// slots it frees
+pub struct KernargFuture<'r, 'q: 'r, 'rt: 'q> {
+    ring: &'r KernargRing<'q, 'rt>,
+    id: u64,
+    signal: SignalHandle,
+    future: CompletionFuture<'r>,
+}
+
+impl<'r, 'q, 'rt> Future for KernargFuture<'r, 'q, 'rt> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        {
+            let mut state = this.ring.state();
+            if !state.is_reclaimed(this.id) {
+                if !state.wakers.iter().any(|&(id, ref w)| id == this.id && w.will_wake(cx.waker())) {
+                    state.wakers.push((this.id, cx.waker().clone()));
+                }
+                drop(state);
+                return Pin::new(&mut this.future).poll(cx);
+            }
+        }
+        Poll::Ready(this.ring.queue.state().result(this.signal))
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use native::*;
+    use emulator::{register_kernel, WorkItem};
+    use executable::Executable;
+    use queue::Queue;
+    use super::KernelArg;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Args {
+        value: u64,
+        pad: u64,
+    }
+
+    unsafe impl KernelArg for Args {}
+
+    static SUM: AtomicU64 = AtomicU64::new(0);
+
+    #[test]
+    fn slots_are_recycled_after_completion() {
+        let runtime = ::init().unwrap();
+        register_kernel("&__kernarg_ring_test", |_: &WorkItem, args: &Args| {
+            SUM.fetch_add(args.value, Ordering::SeqCst);
+        });
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = agent.kernarg_global_regions().unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "").unwrap();
+        executable.freeze("").unwrap();
+        let symbol = executable.get_symbol("", "&__kernarg_ring_test", agent, 0).unwrap();
+        let kernel_object = symbol.kernel_object().unwrap();
+
+        let ring = queue.kernarg_ring(region, 8 * 16).unwrap();
+        let mut last = None;
+        for i in 1..=100 {
+            let mut slot = ring.allocate(&symbol).unwrap();
+            assert_eq!(slot.len(), 16);
+            slot.write(&Args { value: i, pad: 0 }).unwrap();
+            let packet = KernelDispatchPacket::builder(kernel_object)
+                .grid_size(&[1])
+                .workgroup_size(&[1])
+                .build()
+                .unwrap();
+            last = Some(ring.dispatch(slot, packet).unwrap());
+            assert!(ring.in_flight() <= 8);
+        }
+        last.unwrap().wait().unwrap();
+        assert_eq!(SUM.load(Ordering::SeqCst), 5050);
+        assert_eq!(ring.in_flight(), 0);
+
+        drop(ring.allocate_raw(16, 16).unwrap());
+        assert_eq!(ring.in_flight(), 0);
+        assert!(ring.allocate_raw(ring.capacity() + 1, 16).is_err());
+    }
+
+    #[test]
+    fn futures_resolve_once_their_slot_is_reclaimed() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+        use signal::{Signal, SignalBase};
+
+        let runtime = ::init().unwrap();
+        register_kernel("&__kernarg_future_test", |_: &WorkItem, _: &Args| {});
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = agent.kernarg_global_regions().unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "").unwrap();
+        executable.freeze("").unwrap();
+        let symbol = executable.get_symbol("", "&__kernarg_future_test", agent, 0).unwrap();
+        let packet = || {
+            KernelDispatchPacket::builder(symbol.kernel_object().unwrap())
+                .grid_size(&[1])
+                .workgroup_size(&[1])
+                .build()
+                .unwrap()
+        };
+
+        let ring = queue.kernarg_ring(region, 16).unwrap();
+        let first = ring.dispatch(ring.allocate(&symbol).unwrap(), packet()).unwrap();
+        first.wait().unwrap();
+
+        // the second dispatch reuses the first one's signal and waits behind the gate
+        let gate = Signal::create(1, &[]).unwrap();
+        queue.submit(&BarrierAndPacket::builder().dep_signal(&gate).build().unwrap()).unwrap();
+        let second = ring.dispatch(ring.allocate(&symbol).unwrap(), packet()).unwrap();
+        assert_eq!(second.signal(), first.signal());
+        assert!(!second.is_complete());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut future = first.wait_async();
+        assert!(matches!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Ok(()))));
+        let mut future = second.wait_async();
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        gate.store_screlease(0);
+        second.wait().unwrap();
+    }
+
+    #[test]
+    fn a_failed_queue_does_not_hang_the_ring() {
+        use signal::{Signal, SignalBase};
+        use ErrorStatus;
+
+        let runtime = ::init().unwrap();
+        register_kernel("&__kernarg_fault_test", |_: &WorkItem, _: &Args| {});
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = agent.kernarg_global_regions().unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "").unwrap();
+        executable.freeze("").unwrap();
+        let symbol = executable.get_symbol("", "&__kernarg_fault_test", agent, 0).unwrap();
+        let packet = |kernel_object| {
+            KernelDispatchPacket::builder(kernel_object)
+                .grid_size(&[1])
+                .workgroup_size(&[1])
+                .build()
+                .unwrap()
+        };
+
+        // both slots are dispatched behind the gate, and the first one faults the queue
+        let ring = queue.kernarg_ring(region, 2 * 16).unwrap();
+        let gate = Signal::create(1, &[]).unwrap();
+        queue.submit(&BarrierAndPacket::builder().dep_signal(&gate).build().unwrap()).unwrap();
+        let failing = ring.dispatch(ring.allocate(&symbol).unwrap(), packet(0xdead)).unwrap();
+        let stuck = ring
+            .dispatch(ring.allocate(&symbol).unwrap(), packet(symbol.kernel_object().unwrap()))
+            .unwrap();
+        gate.store_screlease(0);
+
+        assert_eq!(failing.wait().unwrap_err(), ErrorStatus::Exception);
+        assert_eq!(stuck.wait().unwrap_err(), ErrorStatus::Exception);
+        assert_eq!(ring.allocate_raw(16, 16).err().unwrap(), ErrorStatus::Exception);
+        drop(ring);
+    }
+}
@@ -0,0 +1,209 @@
+use std::mem::size_of;
+
+use native::*;
+use kernarg::{KernargCompletion, KernargRing, KernargSlot, KernelArg, KernelArgs};
+use signal::SignalBase;
+use symbol::Kernel;
+use super::{Error, ErrorStatus};
+
+type WriteArgs<'a> = Box<dyn FnOnce(&mut KernargSlot) -> Result<(), Error> + 'a>;
+
+pub struct Launch<'a> {
+    kernel: Kernel<'a>,
+    grid_size: Vec<u32>,
+    workgroup_size: Vec<u16>,
+    dynamic_group_segment_size: u32,
+    args: Option<WriteArgs<'a>>,
+    completion_signal: Option<SignalHandle>,
+    dep_signals: Vec<SignalHandle>,
+}
+
+impl<'e> Kernel<'e> {
+    pub fn launch(&self) -> Launch<'e> {
+        Launch {
+            kernel: *self,
+            grid_size: Vec::new(),
+            workgroup_size: Vec::new(),
+            dynamic_group_segment_size: 0,
+            args: None,
+            completion_signal: None,
+            dep_signals: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Launch<'a> {
+    pub fn grid_size(mut self, grid_size: &[u32]) -> Self {
+        self.grid_size = grid_size.to_vec();
+        self
+    }
+
+    pub fn workgroup_size(mut self, workgroup_size: &[u16]) -> Self {
+        self.workgroup_size = workgroup_size.to_vec();
+        self
+    }
+
+    // added to the kernel's fixed group segment size
+    pub fn dynamic_group_segment_size(mut self, size: u32) -> Self {
+        self.dynamic_group_segment_size = size;
+        self
+    }
+
+    // may be shorter than the kernarg segment: the hidden arguments that code object
+    // v5 kernels expect after the explicit ones are left zeroed
+    pub fn args<T: KernelArg + 'a>(mut self, args: &T) -> Self {
+        let args = *args;
+        self.args = Some(Box::new(move |slot: &mut KernargSlot| {
+            if size_of::<T>() > slot.len() {
+                return Err(Error::from(ErrorStatus::InvalidArgument)
+                    .with_context("kernel arguments exceed the kernarg segment"));
+            }
+            slot.write_bytes(&vec![0; slot.len()])?;
+            slot.write_args(&args)
+        }));
+        self
+    }
+
+    // checked against the kernel's kernarg segment like KernargSlot::write_args
+    pub fn kernel_args<A: KernelArgs + 'a>(mut self, args: &A) -> Self {
+        let args = *args;
+        self.args = Some(Box::new(move |slot: &mut KernargSlot| slot.write_args(&args)));
+        self
+    }
+
+    // decremented once the kernel has finished, in addition to the completion the
+    // ring tracks for the kernarg slot
+    pub fn completion_signal<S: SignalBase>(mut self, signal: &S) -> Self {
+        self.completion_signal = Some(signal.handle());
+        self
+    }
+
+    // may be repeated; the kernel starts once every dependency has reached zero
+    pub fn dep_signal<S: SignalBase>(mut self, signal: &S) -> Self {
+        self.dep_signals.push(signal.handle());
+        self
+    }
+
+    pub fn dispatch<'r, 'q, 'rt>(
+        self,
+        ring: &'r KernargRing<'q, 'rt>,
+    ) -> Result<KernargCompletion<'r, 'q, 'rt>, Error> {
+        let kernel = self.kernel;
+        let group_segment_size = kernel.group_segment_size()
+            .checked_add(self.dynamic_group_segment_size)
+            .ok_or(ErrorStatus::InvalidArgument)?;
+        let packet = KernelDispatchPacket::builder(kernel.object())
+            .grid_size(&self.grid_size)
+            .workgroup_size(&self.workgroup_size)
+            .group_segment_size(group_segment_size)
+            .private_segment_size(kernel.private_segment_size())
+            .build()?;
+        let mut barriers = Vec::new();
+        for deps in self.dep_signals.chunks(5) {
+            let builder = deps.iter().fold(BarrierAndPacket::builder(), |b, d| b.dep_signal(d));
+            barriers.push(builder.build()?);
+        }
+
+        let mut slot = ring.allocate_raw(
+            kernel.kernarg_segment_size() as usize,
+            (kernel.kernarg_segment_alignment() as usize).max(16),
+        )?;
+        if let Some(write) = self.args {
+            write(&mut slot)?;
+        } else if kernel.kernarg_segment_size() != 0 {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("the kernel takes arguments but none were given"));
+        }
+        // the barrier bit makes this wait for the kernel before it completes
+        let completion_barrier = match self.completion_signal {
+            Some(signal) => Some(BarrierAndPacket::builder()
+                .barrier(true)
+                .completion_signal(&signal)
+                .build()?),
+            None => None,
+        };
+        // nothing is submitted unless the dispatch would be accepted; only a queue
+        // error racing the submissions below can leave the dependency barriers
+        // queued without the kernel, and those only wait on their signals
+        ring.check(&slot)?;
+        // an AQL queue launches in order, so the barriers hold back the dispatch
+        for barrier in &barriers {
+            ring.queue().submit(barrier)?;
+        }
+        let completion = ring.dispatch(slot, packet)?;
+        if let Some(barrier) = completion_barrier {
+            ring.queue().submit(&barrier)?;
+        }
+        Ok(completion)
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use native::*;
+    use emulator::{register_kernel, WorkItem};
+    use executable::Executable;
+    use queue::Queue;
+    use signal::{Signal, SignalBase};
+
+    static HITS: AtomicU64 = AtomicU64::new(0);
+    static LAST: AtomicU64 = AtomicU64::new(0);
+
+    #[test]
+    fn launches_wait_for_dependencies() {
+        let runtime = ::init().unwrap();
+        register_kernel("&__launch_test", |_: &WorkItem, args: &[u64; 2]| {
+            HITS.fetch_add(args[0] * args[1], Ordering::SeqCst);
+            LAST.store(args[1], Ordering::SeqCst);
+        });
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let region = agent.kernarg_global_regions().unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "").unwrap();
+        executable.freeze("").unwrap();
+        let kernel = executable.kernel("&__launch_test", agent).unwrap();
+        assert_eq!((kernel.kernarg_segment_size(), kernel.dynamic_callstack()), (16, false));
+
+        let ring = queue.kernarg_ring(region, 256).unwrap();
+        let dep = Signal::new(&runtime, 1, &[]).unwrap();
+        let done = Signal::new(&runtime, 1, &[]).unwrap();
+        let completion = kernel.launch()
+            .grid_size(&[4])
+            .workgroup_size(&[2])
+            .dynamic_group_segment_size(64)
+            .args(&[3u64, 5])
+            .dep_signal(&dep)
+            .completion_signal(&done)
+            .dispatch(&ring)
+            .unwrap();
+        assert!(!completion.is_complete());
+        assert_eq!(HITS.load(Ordering::SeqCst), 0);
+        dep.store_screlease(0);
+        completion.wait().unwrap();
+        while done.wait_scacquire(SignalCondition::Eq, 0, u64::MAX, WaitState::Blocked) != 0 {}
+        assert_eq!(HITS.load(Ordering::SeqCst), 60);
+
+        // shorter arguments leave the rest of the segment zeroed
+        kernel.launch().args(&7u64).grid_size(&[1]).workgroup_size(&[1])
+            .dispatch(&ring)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!((HITS.load(Ordering::SeqCst), LAST.load(Ordering::SeqCst)), (60, 0));
+
+        assert!(kernel.launch().grid_size(&[1]).workgroup_size(&[1]).dispatch(&ring).is_err());
+        assert!(kernel.launch().args(&[0u64; 3]).grid_size(&[1]).workgroup_size(&[1])
+            .dispatch(&ring)
+            .is_err());
+        // a rejected launch leaves its dependency barriers unsubmitted
+        let index = queue.load_write_index_scacquire();
+        assert!(kernel.launch().args(&[0u64; 3]).grid_size(&[1]).workgroup_size(&[1])
+            .dep_signal(&dep)
+            .dispatch(&ring)
+            .is_err());
+        assert_eq!(queue.load_write_index_scacquire(), index);
+    }
+}
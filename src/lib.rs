@@ -1,43 +1,89 @@
-#![feature(core_intrinsics)]
 #![allow(deprecated)]
 
 #[macro_use]
 extern crate enum_primitive;
 use enum_primitive::FromPrimitive;
+#[cfg(feature = "dynamic")]
+extern crate libloading;
+#[cfg(feature = "derive")]
+extern crate hsa_derive;
 
-use std::mem::zeroed;
+use std::mem::MaybeUninit;
 use std::os::raw::{c_char, c_void};
 
-mod native;
+pub mod native;
+mod error;
+mod runtime;
 mod system;
 mod agent;
 mod cache;
 mod signal;
 mod queue;
 mod region;
+mod buffer;
+mod pool;
+mod memory_pool;
+mod ipc;
+mod select;
+mod kernarg;
+mod access;
+mod copy;
+mod packet;
+mod waiter;
 mod isa;
 mod wavefront;
 mod executable;
+mod symbol;
+mod launch;
 mod code_object;
+mod hsaco;
+mod elf;
+mod msgpack;
+mod yaml;
+mod metadata;
 mod ext_finalize;
+#[cfg(feature = "emulator")]
+pub mod emulator;
 
-use native::HSAStatus;
+pub use native::{Api, HSAStatus, set_api, with_api};
 pub use native::{Agent, Cache, CodeObjectType, DefaultFloatRoundingMode, DeviceType, Extension,
                  FenceScope, KernelDispatchPacket, KernelDispatchPacketSetup, MachineModel,
+                 AgentDispatchPacket, BarrierAndPacket, BarrierOrPacket,
                  PacketType, PacketHeader, Profile, QueueType, RegionSegment, SignalCondition,
-                 SignalValue, WaitState};
+                 SignalValue, WaitState, AgentFeature, QueueFeature, AccessPermission,
+                 MemoryPool, MemoryPoolSegment, MemoryPoolGlobalFlag, MemoryPoolLocation,
+                 MemoryPoolAccess, LoadedCodeObject};
+pub use error::*;
+pub use runtime::*;
 pub use signal::*;
 pub use queue::*;
+pub use packet::*;
+pub use waiter::SignalFuture;
 pub use region::*;
+pub use buffer::*;
+pub use pool::*;
+pub use memory_pool::*;
+pub use ipc::*;
+pub use select::*;
+pub use kernarg::*;
+pub use access::AccessSet;
+pub use copy::*;
+#[cfg(feature = "derive")]
+pub use hsa_derive::KernelArgs;
 pub use executable::*;
+pub use symbol::*;
+pub use launch::*;
 pub use code_object::*;
+pub use hsaco::*;
+pub use elf::{ElfNote, ElfSection, ElfSymbol};
+pub use metadata::*;
 pub use ext_finalize::*;
 pub use system::*;
 pub use std::collections::BTreeSet as Flags;
 
 enum_from_primitive! {
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SuccessStatus {
     Success = 0x0,
     InfoBreak,
@@ -45,7 +91,7 @@ pub enum SuccessStatus {
 
 enum_from_primitive! {
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ErrorStatus {
     // 2.2.1.1
     InvalidArgument = 0x1001,
@@ -80,6 +126,12 @@ pub enum ErrorStatus {
     InvalidSignalGroup,
     InvalidRuntimeState,
 
+    // hsa_ext_amd.h
+    InvalidMemoryPool = 0x28,
+    MemoryApertureViolation,
+    IllegalInstruction,
+    MemoryFault,
+
     // 3.2.1.1
     InvalidProgram = 0x2000,
     InvalidModule,
@@ -107,58 +159,40 @@ pub enum ErrorStatus {
     OutOfEvents,
     EventNotRegistered,
     CannotUseProducers,
+
+    // dynamic loading of the runtime library
+    LibraryNotFound = 0x10000,
+    SymbolNotFound,
 }}
 
-fn check<T>(ret: HSAStatus, ok: T) -> Result<T, ErrorStatus> {
-    match ret {
-        0 => Ok(ok),
-        _ => {
-            match ErrorStatus::from_i32(ret) {
-                Some(e) => Err(e),
-                _ => Err(ErrorStatus::Exception),
-            }
-        }
-    }
+fn check<T>(ret: HSAStatus, ok: T) -> Result<T, Error> {
+    check_with_success_status(ret).map(|_| ok)
 }
 
-/*
-fn check_with_success_status(ret: HSAStatus) -> Result<SuccessStatus, ErrorStatus> {
+fn check_with_success_status(ret: HSAStatus) -> Result<SuccessStatus, Error> {
     match SuccessStatus::from_i32(ret) {
         Some(s) => Ok(s),
-        _ => match ErrorStatus::from_i32(ret) {
-            Some(e) => Err(e),
-            _ => Err(ErrorStatus::Exception),
-        }
+        _ => Err(Error::from_status(ret, native::last_call())),
     }
 }
-*/
-
-pub fn init() -> Result<(), ErrorStatus> {
-    check(unsafe { native::hsa_init() }, ())
-}
 
-pub fn shutdown() -> Result<(), ErrorStatus> {
-    check(unsafe { native::hsa_shut_down() }, ())
-}
-
-pub fn status_string(status: ErrorStatus) -> Result<String, ErrorStatus> {
+pub fn status_string(status: ErrorStatus) -> Result<String, Error> {
     get_str(native::hsa_status_string, status as HSAStatus)
 }
 
 fn get_str<T>(
-    f: unsafe extern "C" fn(T, &*const c_char) -> HSAStatus,
+    f: unsafe fn(T, *mut *const c_char) -> HSAStatus,
     v: T,
-) -> Result<String, ErrorStatus> {
+) -> Result<String, Error> {
     unsafe {
-        let p: *const c_char = std::ptr::null();
-        check(
-            f(v, &p),
-            std::ffi::CStr::from_ptr(p).to_string_lossy().to_string(),
-        )
+        let mut p: *const c_char = std::ptr::null();
+        check(f(v, &mut p), ()).map(|_| {
+            std::ffi::CStr::from_ptr(p).to_string_lossy().to_string()
+        })
     }
 }
 
-fn get_fixed_str<F>(f: F, sz: usize) -> Result<String, ErrorStatus>
+fn get_fixed_str<F>(f: F, sz: usize) -> Result<String, Error>
 where
     F: Fn(*mut c_void) -> HSAStatus,
 {
@@ -167,19 +201,19 @@ where
         t.push('\0');
     }
     check(f(t.as_ptr() as *mut c_void), ()).map(|_| {
-        let pos = t.find('\0').unwrap_or_else(|| t.len());
+        let pos = t.find('\0').unwrap_or(t.len());
         t.truncate(pos);
         t
     })
 }
 
-fn get_info<F, R>(f: F) -> Result<R, ErrorStatus>
+fn get_info<F, R>(f: F) -> Result<R, Error>
 where
     F: Fn(*mut c_void) -> HSAStatus,
 {
-    let mut x: R = unsafe { zeroed() };
-    let p: *mut c_void = &mut x as *mut _ as *mut c_void;
-    check(f(p), x)
+    let mut x: MaybeUninit<R> = MaybeUninit::zeroed();
+    let p: *mut c_void = x.as_mut_ptr() as *mut c_void;
+    check(f(p), ()).map(|_| unsafe { x.assume_init() })
 }
 
 fn iter_callback_helper<T>(x: T, data: *mut c_void) -> HSAStatus {
@@ -205,3 +239,81 @@ where
     }
     flags
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::os::raw::c_void;
+
+    use native::{with_api, Api, HSAStatus, SystemInfo};
+    use super::{check, check_with_success_status, version_major, Error, ErrorStatus,
+                SuccessStatus};
+
+    thread_local!(static STATUS: Cell<HSAStatus> = const { Cell::new(0) });
+
+    unsafe extern "C" fn failing_system_get_info(_: SystemInfo, _: *mut c_void) -> HSAStatus {
+        STATUS.with(|s| s.get())
+    }
+
+    fn version_major_with_status(status: HSAStatus) -> Result<u16, Error> {
+        let mut api = Api::current();
+        api.hsa_system_get_info = failing_system_get_info;
+        STATUS.with(|s| s.set(status));
+        with_api(&api, version_major)
+    }
+
+    #[test]
+    fn check_maps_every_error_status() {
+        let ranges = [
+            (0x28, 0x2B),
+            (0x1001, 0x101F),
+            (0x2000, 0x2007),
+            (0x3000, 0x3003),
+            (0x4000, 0x4002),
+            (0x5000, 0x5004),
+            (0x10000, 0x10001),
+        ];
+        for &(first, last) in &ranges {
+            for status in first..last + 1 {
+                match version_major_with_status(status) {
+                    Err(e) => {
+                        assert_eq!(e.status(), status);
+                        assert_eq!(e.kind().map(|k| k as HSAStatus), Some(status));
+                        assert_eq!(e.function(), "hsa_system_get_info");
+                    }
+                    Ok(_) => panic!("status {:#x} was not reported as an error", status),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_keeps_unknown_status() {
+        match version_major_with_status(0x1FFF) {
+            Err(e) => {
+                assert_eq!(e.status(), 0x1FFF);
+                assert!(e.kind().is_none());
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn check_accepts_info_break() {
+        assert_eq!(check_with_success_status(1).unwrap(), SuccessStatus::InfoBreak);
+        assert!(check(1, ()).is_ok());
+    }
+
+    #[test]
+    fn error_display_includes_function_and_context() {
+        use error::Context;
+        let e = version_major_with_status(ErrorStatus::InvalidArgument as HSAStatus)
+            .context("reading the version")
+            .unwrap_err();
+        assert!(e == ErrorStatus::InvalidArgument);
+        assert_eq!(e.context(), Some("reading the version"));
+        let text = e.to_string();
+        assert!(text.starts_with("reading the version: hsa_system_get_info failed: "));
+        assert!(text.ends_with("(0x1001)"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::os::raw::c_void;
+use std::ptr::{null, null_mut};
+use std::marker::PhantomData;
+
+use native::*;
+use runtime::Runtime;
+use access::AccessSet;
+use super::{bitflags, check, get_info, Error, ErrorStatus, Flags};
+
+impl MemoryPool {
+    pub fn segment(&self) -> Result<MemoryPoolSegment, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::Segment, x))
+    }
+
+    pub fn global_flags(&self) -> Result<Flags<MemoryPoolGlobalFlag>, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::GlobalFlags, x))
+            .map(|flags: u32| bitflags(flags))
+    }
+
+    pub fn size(&self) -> Result<usize, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::Size, x))
+    }
+
+    pub fn alloc_max_size(&self) -> Result<usize, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::AllocMaxSize, x))
+    }
+
+    pub fn runtime_alloc_allowed(&self) -> Result<bool, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::RuntimeAllocAllowed, x))
+    }
+
+    pub fn runtime_alloc_granule(&self) -> Result<usize, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::RuntimeAllocGranule, x))
+    }
+
+    pub fn runtime_alloc_rec_granule(&self) -> Result<usize, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::RuntimeAllocRecGranule, x))
+    }
+
+    pub fn runtime_alloc_alignment(&self) -> Result<usize, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::RuntimeAllocAlignment, x))
+    }
+
+    pub fn accessible_by_all(&self) -> Result<bool, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::AccessibleByAll, x))
+    }
+
+    pub fn location(&self) -> Result<MemoryPoolLocation, Error> {
+        get_info(|x| self.get_info(MemoryPoolInfo::Location, x))
+    }
+
+    pub fn access(&self, agent: Agent) -> Result<MemoryPoolAccess, Error> {
+        get_info(|x| self.get_agent_info(agent, AgentMemoryPoolInfo::Access, x))
+    }
+
+    pub fn num_link_hops(&self, agent: Agent) -> Result<u32, Error> {
+        get_info(|x| self.get_agent_info(agent, AgentMemoryPoolInfo::NumLinkHops, x))
+    }
+
+    pub fn can_migrate_to(&self, dst: MemoryPool) -> Result<bool, Error> {
+        get_info(|x| unsafe { hsa_amd_memory_pool_can_migrate(*self, dst, x as *mut bool) })
+    }
+
+    fn get_info(&self, attr: MemoryPoolInfo, v: *mut c_void) -> HSAStatus {
+        unsafe { hsa_amd_memory_pool_get_info(*self, attr, v) }
+    }
+
+    fn get_agent_info(&self, agent: Agent, attr: AgentMemoryPoolInfo, v: *mut c_void) -> HSAStatus {
+        unsafe { hsa_amd_agent_memory_pool_get_info(agent, *self, attr, v) }
+    }
+}
+
+pub struct PoolMemory<'rt> {
+    ptr: *mut u8,
+    size: usize,
+    access: AccessSet,
+    runtime: PhantomData<&'rt Runtime>,
+}
+
+impl<'rt> PoolMemory<'rt> {
+    pub fn allocate(
+        _runtime: &'rt Runtime,
+        pool: MemoryPool,
+        size: usize,
+    ) -> Result<PoolMemory<'rt>, Error> {
+        let mut ptr: *mut c_void = null_mut();
+        check(unsafe { hsa_amd_memory_pool_allocate(pool, size, 0, &mut ptr) }, ())?;
+        Ok(PoolMemory {
+            ptr: ptr as *mut u8,
+            size,
+            access: AccessSet::new(ptr, size, pool.accessible_by_all().unwrap_or(false)),
+            runtime: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    // pools that are DisallowedByDefault for an agent need this before the agent
+    // touches the allocation
+    pub fn agents_allow_access(&self, agents: &[Agent]) -> Result<(), Error> {
+        if agents.is_empty() {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        check(
+            unsafe {
+                hsa_amd_agents_allow_access(
+                    agents.len() as u32,
+                    agents.as_ptr(),
+                    null(),
+                    self.ptr as *const c_void,
+                )
+            },
+            (),
+        )?;
+        self.access.record(agents, AccessPermission::RW);
+        Ok(())
+    }
+
+    pub fn migrate(&self, pool: MemoryPool) -> Result<(), Error> {
+        check(unsafe { hsa_amd_memory_migrate(self.ptr as *const c_void, pool, 0) }, ())
+    }
+
+    pub fn access(&self) -> &AccessSet {
+        &self.access
+    }
+}
+
+impl<'rt> Drop for PoolMemory<'rt> {
+    fn drop(&mut self) {
+        unsafe {
+            hsa_amd_memory_pool_free(self.ptr as *mut c_void);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use super::PoolMemory;
+
+    #[test]
+    fn pools_mirror_regions() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let pools = agent.memory_pools().unwrap();
+        assert_eq!(pools.len(), agent.regions().unwrap().len());
+        let coarse = *pools.iter()
+            .find(|p| p.global_flags().unwrap().contains(&MemoryPoolGlobalFlag::CoarseGrained))
+            .unwrap();
+        assert_eq!(coarse.access(agent).unwrap(), MemoryPoolAccess::DisallowedByDefault);
+
+        let memory = PoolMemory::allocate(&runtime, coarse, 256).unwrap();
+        assert_eq!(memory.access().get(agent), None);
+        memory.agents_allow_access(&[agent]).unwrap();
+        assert_eq!(memory.access().get(agent), Some(AccessPermission::RW));
+        assert!(memory.migrate(pools[0]).is_ok());
+        assert!(PoolMemory::allocate(&runtime, MemoryPool { handle: 9 }, 256).is_err());
+    }
+}
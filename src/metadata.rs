@@ -0,0 +1,649 @@
+use native::*;
+use elf::{self, u16_at, u32_at, u64_at, STT_OBJECT};
+use hsaco::{invalid, Hsaco, TargetId};
+use super::{msgpack, yaml, Error};
+
+const NT_AMD_HSA_METADATA: u32 = 10;
+const NT_AMDGPU_METADATA: u32 = 32;
+
+// the decoded metadata document; v2 YAML scalars all arrive as strings, so the
+// accessors convert where the v3+ msgpack encoding would already be typed
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<MetadataValue>),
+    Map(Vec<(MetadataValue, MetadataValue)>),
+}
+
+impl MetadataValue {
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        match *self {
+            MetadataValue::Map(ref entries) => {
+                entries.iter().find(|e| e.0.as_str() == Some(key)).map(|e| &e.1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            MetadataValue::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            MetadataValue::UInt(v) => Some(v),
+            MetadataValue::Int(v) if v >= 0 => Some(v as u64),
+            MetadataValue::Str(ref s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            MetadataValue::Bool(v) => Some(v),
+            MetadataValue::Str(ref s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[MetadataValue]> {
+        match *self {
+            MetadataValue::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+// amd_kernel_descriptor_t, the 64-byte object behind each "<kernel>.kd" symbol
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KernelDescriptor {
+    pub group_segment_fixed_size: u32,
+    pub private_segment_fixed_size: u32,
+    pub kernarg_size: u32,
+    pub kernel_code_entry_byte_offset: i64,
+    pub compute_pgm_rsrc3: u32,
+    pub compute_pgm_rsrc1: u32,
+    pub compute_pgm_rsrc2: u32,
+    pub kernel_code_properties: u16,
+}
+
+const KERNEL_CODE_PROPERTY_ENABLE_WAVEFRONT_SIZE32: u16 = 1 << 10;
+
+impl KernelDescriptor {
+    pub const SIZE: usize = 64;
+
+    pub fn parse(bytes: &[u8]) -> Result<KernelDescriptor, Error> {
+        if bytes.len() < KernelDescriptor::SIZE {
+            return Err(invalid("kernel descriptor is shorter than 64 bytes"));
+        }
+        Ok(KernelDescriptor {
+            group_segment_fixed_size: u32_at(bytes, 0)?,
+            private_segment_fixed_size: u32_at(bytes, 4)?,
+            kernarg_size: u32_at(bytes, 8)?,
+            kernel_code_entry_byte_offset: u64_at(bytes, 16)? as i64,
+            compute_pgm_rsrc3: u32_at(bytes, 44)?,
+            compute_pgm_rsrc1: u32_at(bytes, 48)?,
+            compute_pgm_rsrc2: u32_at(bytes, 52)?,
+            kernel_code_properties: u16_at(bytes, 56)?,
+        })
+    }
+
+    pub fn wavefront_size(&self) -> u32 {
+        if self.kernel_code_properties & KERNEL_CODE_PROPERTY_ENABLE_WAVEFRONT_SIZE32 != 0 {
+            32
+        } else {
+            64
+        }
+    }
+}
+
+// value kinds and address spaces are kept as spelled in the note: snake_case for
+// v3 and later ("global_buffer"), CamelCase for v2 ("GlobalBuffer")
+#[derive(Clone, Debug, PartialEq)]
+pub struct KernelArgMetadata {
+    pub name: Option<String>,
+    pub type_name: Option<String>,
+    pub offset: u32,
+    pub size: u32,
+    pub value_kind: String,
+    pub address_space: Option<String>,
+    pub access: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KernelMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub kernarg_segment_size: u32,
+    pub kernarg_segment_align: u32,
+    pub group_segment_fixed_size: u32,
+    pub private_segment_fixed_size: u32,
+    pub wavefront_size: u32,
+    pub sgpr_count: u32,
+    pub vgpr_count: u32,
+    pub max_flat_workgroup_size: u32,
+    pub args: Vec<KernelArgMetadata>,
+    // v3 and later only; v2 kernels carry an amd_kernel_code_t instead
+    pub descriptor: Option<KernelDescriptor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KernelMismatch {
+    pub field: &'static str,
+    pub metadata: u32,
+    pub runtime: u32,
+}
+
+impl KernelMetadata {
+    // differences between the metadata and what the runtime reports for the loaded
+    // symbol; the runtime may raise the kernarg alignment, so only a lower one counts
+    pub fn compare(&self, symbol: ExecutableSymbol) -> Result<Vec<KernelMismatch>, Error> {
+        let checks = [
+            ("kernarg_segment_size", self.kernarg_segment_size,
+             symbol.kernel_kernarg_segment_size()?, false),
+            ("kernarg_segment_align", self.kernarg_segment_align,
+             symbol.kernel_kernarg_segment_alignment()?, true),
+            ("group_segment_fixed_size", self.group_segment_fixed_size,
+             symbol.kernel_group_segment_size()?, false),
+            ("private_segment_fixed_size", self.private_segment_fixed_size,
+             symbol.kernel_private_segment_size()?, false),
+        ];
+        Ok(checks.iter()
+            .filter(|&&(_, metadata, runtime, at_least)| {
+                if at_least { runtime < metadata } else { runtime != metadata }
+            })
+            .map(|&(field, metadata, runtime, _)| KernelMismatch {
+                field,
+                metadata,
+                runtime,
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeObjectMetadata {
+    pub version: Vec<u32>,
+    pub target: TargetId,
+    pub kernels: Vec<KernelMetadata>,
+    pub raw: MetadataValue,
+}
+
+impl CodeObjectMetadata {
+    // by source name or by descriptor symbol name
+    pub fn kernel(&self, name: &str) -> Option<&KernelMetadata> {
+        self.kernels.iter().find(|k| k.name == name || k.symbol == name)
+    }
+}
+
+struct Keys {
+    version: &'static str,
+    kernels: &'static str,
+    // v2 nests the sizes and register counts in a "CodeProps" map
+    props: Option<&'static str>,
+    name: &'static str,
+    symbol: &'static str,
+    kernarg_segment_size: &'static str,
+    kernarg_segment_align: &'static str,
+    group_segment_fixed_size: &'static str,
+    private_segment_fixed_size: &'static str,
+    wavefront_size: &'static str,
+    sgpr_count: &'static str,
+    vgpr_count: &'static str,
+    max_flat_workgroup_size: &'static str,
+    args: &'static str,
+    arg_type_name: &'static str,
+    // v2 arguments have no offset; it follows from the sizes and alignments
+    arg_offset: Option<&'static str>,
+    arg_size: &'static str,
+    arg_align: &'static str,
+    arg_value_kind: &'static str,
+    arg_address_space: &'static str,
+    arg_access: &'static str,
+}
+
+const V2_KEYS: Keys = Keys {
+    version: "Version",
+    kernels: "Kernels",
+    props: Some("CodeProps"),
+    name: "Name",
+    symbol: "SymbolName",
+    kernarg_segment_size: "KernargSegmentSize",
+    kernarg_segment_align: "KernargSegmentAlign",
+    group_segment_fixed_size: "GroupSegmentFixedSize",
+    private_segment_fixed_size: "PrivateSegmentFixedSize",
+    wavefront_size: "WavefrontSize",
+    sgpr_count: "NumSGPRs",
+    vgpr_count: "NumVGPRs",
+    max_flat_workgroup_size: "MaxFlatWorkGroupSize",
+    args: "Args",
+    arg_type_name: "TypeName",
+    arg_offset: None,
+    arg_size: "Size",
+    arg_align: "Align",
+    arg_value_kind: "ValueKind",
+    arg_address_space: "AddressSpace",
+    arg_access: "AccQual",
+};
+
+const V3_KEYS: Keys = Keys {
+    version: "amdhsa.version",
+    kernels: "amdhsa.kernels",
+    props: None,
+    name: ".name",
+    symbol: ".symbol",
+    kernarg_segment_size: ".kernarg_segment_size",
+    kernarg_segment_align: ".kernarg_segment_align",
+    group_segment_fixed_size: ".group_segment_fixed_size",
+    private_segment_fixed_size: ".private_segment_fixed_size",
+    wavefront_size: ".wavefront_size",
+    sgpr_count: ".sgpr_count",
+    vgpr_count: ".vgpr_count",
+    max_flat_workgroup_size: ".max_flat_workgroup_size",
+    args: ".args",
+    arg_type_name: ".type_name",
+    arg_offset: Some(".offset"),
+    arg_size: ".size",
+    arg_align: ".align",
+    arg_value_kind: ".value_kind",
+    arg_address_space: ".address_space",
+    arg_access: ".access",
+};
+
+fn string(map: &MetadataValue, key: &str) -> Option<String> {
+    map.get(key).and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+fn number(map: &MetadataValue, key: &str) -> u32 {
+    map.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+fn args(kernel: &MetadataValue, keys: &Keys) -> Result<Vec<KernelArgMetadata>, Error> {
+    let overflow = || invalid("kernel argument offsets overflow");
+    let mut next = 0u32;
+    let mut args = Vec::new();
+    for arg in kernel.get(keys.args).and_then(|a| a.as_array()).unwrap_or(&[]) {
+        let size = number(arg, keys.arg_size);
+        let offset = match keys.arg_offset {
+            Some(key) => number(arg, key),
+            None => {
+                let align = number(arg, keys.arg_align).max(1);
+                next.div_ceil(align).checked_mul(align).ok_or_else(overflow)?
+            }
+        };
+        next = offset.checked_add(size).ok_or_else(overflow)?;
+        args.push(KernelArgMetadata {
+            name: string(arg, keys.name),
+            type_name: string(arg, keys.arg_type_name),
+            offset,
+            size,
+            value_kind: string(arg, keys.arg_value_kind).unwrap_or_default(),
+            address_space: string(arg, keys.arg_address_space),
+            access: string(arg, keys.arg_access),
+        });
+    }
+    Ok(args)
+}
+
+impl<'a> Hsaco<'a> {
+    pub fn metadata(&self) -> Result<CodeObjectMetadata, Error> {
+        let v2 = self.version() < 3;
+        let (keys, owner, kind) = if v2 {
+            (&V2_KEYS, "AMD", NT_AMD_HSA_METADATA)
+        } else {
+            (&V3_KEYS, "AMDGPU", NT_AMDGPU_METADATA)
+        };
+        let raw = {
+            let notes = self.notes()?;
+            let note = notes.iter()
+                .find(|n| n.name == owner && n.kind == kind)
+                .ok_or_else(|| invalid("code object has no metadata note"))?;
+            if v2 {
+                yaml::parse(&String::from_utf8_lossy(note.desc))?
+            } else {
+                msgpack::decode(note.desc)?
+            }
+        };
+        let sections = self.sections()?;
+        let symbols = self.symbols()?;
+
+        let mut kernels = Vec::new();
+        for kernel in raw.get(keys.kernels).and_then(|k| k.as_array()).unwrap_or(&[]) {
+            let props = keys.props.and_then(|p| kernel.get(p)).unwrap_or(kernel);
+            let name = string(kernel, keys.name)
+                .ok_or_else(|| invalid("kernel metadata has no name"))?;
+            let symbol = string(kernel, keys.symbol).unwrap_or_else(|| format!("{}.kd", name));
+            let descriptor = match symbols.iter().find(|s| s.name == symbol) {
+                Some(s) if !v2 && s.kind == STT_OBJECT => Some(KernelDescriptor::parse(
+                    elf::symbol_data(self.bytes(), &sections, s)?,
+                )?),
+                _ => None,
+            };
+            let wavefront_size = match number(props, keys.wavefront_size) {
+                0 => descriptor.map(|d| d.wavefront_size()).unwrap_or(64),
+                n => n,
+            };
+            kernels.push(KernelMetadata {
+                args: args(kernel, keys)?,
+                name,
+                symbol,
+                kernarg_segment_size: number(props, keys.kernarg_segment_size),
+                kernarg_segment_align: number(props, keys.kernarg_segment_align),
+                group_segment_fixed_size: number(props, keys.group_segment_fixed_size),
+                private_segment_fixed_size: number(props, keys.private_segment_fixed_size),
+                wavefront_size,
+                sgpr_count: number(props, keys.sgpr_count),
+                vgpr_count: number(props, keys.vgpr_count),
+                max_flat_workgroup_size: number(props, keys.max_flat_workgroup_size),
+                descriptor,
+            });
+        }
+
+        let version = raw.get(keys.version)
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|n| n.as_u64()).map(|n| n as u32).collect())
+            .unwrap_or_default();
+        // the target string in the note is authoritative when present (v4 and later)
+        let target = raw.get("amdhsa.target")
+            .and_then(|t| t.as_str())
+            .and_then(TargetId::parse)
+            .unwrap_or_else(|| self.target().clone());
+        Ok(CodeObjectMetadata {
+            version,
+            target,
+            kernels,
+            raw,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetadataValue::{self, Array, Map, Str, UInt};
+    use hsaco::{Hsaco, TargetFeature};
+
+    fn s(v: &str) -> MetadataValue {
+        Str(v.to_string())
+    }
+
+    fn pack(value: &MetadataValue, out: &mut Vec<u8>) {
+        match *value {
+            Str(ref v) if v.len() < 32 => {
+                out.push(0xa0 | v.len() as u8);
+                out.extend_from_slice(v.as_bytes());
+            }
+            Str(ref v) => {
+                out.extend_from_slice(&[0xd9, v.len() as u8]);
+                out.extend_from_slice(v.as_bytes());
+            }
+            UInt(v) if v < 128 => out.push(v as u8),
+            UInt(v) => {
+                out.push(0xce);
+                out.extend_from_slice(&(v as u32).to_be_bytes());
+            }
+            Array(ref items) => {
+                out.push(0x90 | items.len() as u8);
+                items.iter().for_each(|i| pack(i, out));
+            }
+            Map(ref entries) => {
+                out.push(0xde);
+                out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+                for (k, v) in entries {
+                    pack(k, out);
+                    pack(v, out);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn note(name: &str, kind: u32, desc: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+        out.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize((out.len() + 3) & !3, 0);
+        out.extend_from_slice(desc);
+        out.resize((out.len() + 3) & !3, 0);
+        out
+    }
+
+    // sections: null, .note, .rodata holding the symbols, .symtab, .strtab, .shstrtab
+    fn elf(abi_version: u8, flags: u32, notes: &[u8], symbols: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut b = vec![0u8; 64];
+        b[..4].copy_from_slice(b"\x7fELF");
+        b[4..9].copy_from_slice(&[2, 1, 1, 64, abi_version]);
+        b[16..18].copy_from_slice(&3u16.to_le_bytes());
+        b[18..20].copy_from_slice(&224u16.to_le_bytes());
+        b[48..52].copy_from_slice(&flags.to_le_bytes());
+
+        let mut headers = vec![[0u64; 8]];
+        let mut section = |b: &mut Vec<u8>, name: u64, kind: u64, data: &[u8], link: u64| {
+            let offset = b.len() as u64;
+            b.extend_from_slice(data);
+            let entry_size = if kind == 2 { 24 } else { 0 };
+            headers.push([name | kind << 32, 0, offset, offset, data.len() as u64, link, 0,
+                          entry_size]);
+        };
+        let (mut rodata, mut symtab, mut strtab) = (Vec::new(), vec![0u8; 24], vec![0u8]);
+        for &(name, data) in symbols {
+            let value = b.len() + notes.len() + rodata.len();
+            symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+            symtab.extend_from_slice(&[0x11, 0, 2, 0]);
+            symtab.extend_from_slice(&(value as u64).to_le_bytes());
+            symtab.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+            rodata.extend_from_slice(data);
+        }
+        let shstrtab = b"\0.note\0.rodata\0.symtab\0.strtab\0.shstrtab\0";
+        section(&mut b, 1, 7, notes, 0);
+        section(&mut b, 7, 1, &rodata, 0);
+        section(&mut b, 15, 2, &symtab, 4);
+        section(&mut b, 23, 3, &strtab, 0);
+        section(&mut b, 31, 3, shstrtab, 0);
+
+        let shoff = b.len() as u64;
+        // Elf64_Shdr as eight words, sh_name/sh_type and sh_link/sh_info sharing one each
+        for word in headers.iter().flat_map(|h| h.iter()) {
+            b.extend_from_slice(&word.to_le_bytes());
+        }
+        b[40..48].copy_from_slice(&shoff.to_le_bytes());
+        b[58..60].copy_from_slice(&64u16.to_le_bytes());
+        b[60..62].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        b[62..64].copy_from_slice(&5u16.to_le_bytes());
+        b
+    }
+
+    #[test]
+    fn v5_metadata_and_descriptors_are_read() {
+        let arg = |name: &str, offset: u64| Map(vec![
+            (s(".name"), s(name)),
+            (s(".offset"), UInt(offset)),
+            (s(".size"), UInt(8)),
+            (s(".value_kind"), s("global_buffer")),
+            (s(".address_space"), s("global")),
+        ]);
+        let kernel = Map(vec![
+            (s(".name"), s("copy")),
+            (s(".symbol"), s("copy.kd")),
+            (s(".kernarg_segment_size"), UInt(16)),
+            (s(".kernarg_segment_align"), UInt(8)),
+            (s(".group_segment_fixed_size"), UInt(512)),
+            (s(".private_segment_fixed_size"), UInt(0)),
+            (s(".sgpr_count"), UInt(12)),
+            (s(".max_flat_workgroup_size"), UInt(1024)),
+            (s(".args"), Array(vec![arg("src", 0), arg("dst", 8)])),
+        ]);
+        let document = Map(vec![
+            (s("amdhsa.version"), Array(vec![UInt(1), UInt(2)])),
+            (s("amdhsa.target"), s("amdgcn-amd-amdhsa--gfx1100:xnack-")),
+            (s("amdhsa.kernels"), Array(vec![kernel])),
+        ]);
+        let mut desc = Vec::new();
+        pack(&document, &mut desc);
+        let mut kd = [0u8; 64];
+        kd[0..4].copy_from_slice(&512u32.to_le_bytes());
+        kd[8..12].copy_from_slice(&16u32.to_le_bytes());
+        kd[56..58].copy_from_slice(&(1u16 << 10).to_le_bytes());
+        let bytes = elf(3, 0x41, &note("AMDGPU", 32, &desc), &[("copy.kd", &kd)]);
+
+        let hsaco = Hsaco::parse(&bytes).unwrap();
+        let names: Vec<_> = hsaco.sections().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["", ".note", ".rodata", ".symtab", ".strtab", ".shstrtab"]);
+        assert_eq!(hsaco.symbols().unwrap()[0].name, "copy.kd");
+
+        let metadata = hsaco.metadata().unwrap();
+        assert_eq!(metadata.version, [1, 2]);
+        assert_eq!(metadata.target.processor(), "gfx1100");
+        assert_eq!(metadata.target.xnack(), TargetFeature::Off);
+        let kernel = metadata.kernel("copy.kd").unwrap();
+        assert_eq!((kernel.kernarg_segment_size, kernel.group_segment_fixed_size), (16, 512));
+        assert_eq!((kernel.sgpr_count, kernel.max_flat_workgroup_size), (12, 1024));
+        assert_eq!(kernel.args[1].name.as_ref().unwrap(), "dst");
+        assert_eq!((kernel.args[1].offset, &kernel.args[1].value_kind[..]), (8, "global_buffer"));
+        let descriptor = kernel.descriptor.unwrap();
+        assert_eq!(descriptor.kernarg_size, 16);
+        // no .wavefront_size in the note, so the descriptor decides
+        assert_eq!((descriptor.wavefront_size(), kernel.wavefront_size), (32, 32));
+
+        let bytes = elf(3, 0x41, &[], &[]);
+        assert!(Hsaco::parse(&bytes).unwrap().metadata().is_err());
+    }
+
+    // code object v2 notes: the gfx90a ISA version followed by the YAML metadata
+    fn v2_notes(yaml: &str) -> Vec<u8> {
+        let mut isa = Vec::new();
+        isa.extend_from_slice(&4u16.to_le_bytes());
+        isa.extend_from_slice(&7u16.to_le_bytes());
+        for v in &[9u32, 0, 10] {
+            isa.extend_from_slice(&v.to_le_bytes());
+        }
+        isa.extend_from_slice(b"AMD\0AMDGPU\0");
+        let mut notes = note("AMD", 3, &isa);
+        notes.extend(note("AMD", 10, yaml.as_bytes()));
+        notes
+    }
+
+    #[test]
+    fn v2_yaml_metadata_is_read() {
+        let yaml = "---
+Version:         [ 1, 0 ]
+Kernels:
+  - Name:            scale
+    SymbolName:      'scale@kd'
+    Language:        OpenCL C
+    Args:
+      - Name:            factor
+        TypeName:        'char'
+        Size:            1
+        Align:           1
+        ValueKind:       ByValue
+      - Name:            data
+        TypeName:        'float*'
+        Size:            8
+        Align:           8
+        ValueKind:       GlobalBuffer
+        AddressSpace:    Global
+        AccQual:         Default
+    CodeProps:
+      KernargSegmentSize: 16
+      GroupSegmentFixedSize: 0
+      PrivateSegmentFixedSize: 32
+      KernargSegmentAlign: 8
+      WavefrontSize:   64
+      NumVGPRs:        6
+...
+";
+        let bytes = elf(0, 0, &v2_notes(yaml), &[]);
+
+        let hsaco = Hsaco::parse(&bytes).unwrap();
+        assert_eq!(hsaco.version(), 2);
+        assert_eq!(hsaco.target().to_string(), "amdgcn-amd-amdhsa--gfx90a");
+        let metadata = hsaco.metadata().unwrap();
+        assert_eq!(metadata.version, [1, 0]);
+        let kernel = metadata.kernel("scale").unwrap();
+        assert_eq!(kernel.symbol, "scale@kd");
+        assert_eq!((kernel.private_segment_fixed_size, kernel.vgpr_count), (32, 6));
+        let offsets: Vec<_> = kernel.args.iter().map(|a| (a.offset, a.size)).collect();
+        assert_eq!(offsets, [(0, 1), (8, 8)]);
+        assert_eq!(kernel.args[1].access.as_ref().unwrap(), "Default");
+        assert_eq!(kernel.descriptor, None);
+    }
+
+    #[test]
+    fn malformed_code_objects_are_rejected() {
+        use ErrorStatus;
+
+        let mut bytes = elf(3, 0x41, &[], &[("copy.kd", &[0u8; 64])]);
+        let shoff = ::elf::u64_at(&bytes, 40).unwrap() as usize;
+        // .symtab is section 3, sh_entsize the last word of its header
+        bytes[shoff + 3 * 64 + 56..shoff + 4 * 64].copy_from_slice(&16u64.to_le_bytes());
+        let err = Hsaco::parse(&bytes).unwrap().symbols().unwrap_err();
+        assert_eq!(err, ErrorStatus::InvalidCodeObject);
+        assert_eq!(err.context(), Some("ELF symbol entries are smaller than Elf64_Sym"));
+
+        let args = "---
+Kernels:
+  - Name:            huge
+    Args:
+      - Size:            4294967295
+        Align:           1
+      - Size:            8
+        Align:           8
+";
+        let bytes = elf(0, 0, &v2_notes(args), &[]);
+        let err = Hsaco::parse(&bytes).unwrap().metadata().unwrap_err();
+        assert_eq!(err.context(), Some("kernel argument offsets overflow"));
+
+        let mut nested = String::from("---\nKernels:\n");
+        for depth in 0..64 {
+            nested.push_str(&format!("{:1$}- Args:\n", "", 2 + depth * 4));
+        }
+        let bytes = elf(0, 0, &v2_notes(&nested), &[]);
+        let err = Hsaco::parse(&bytes).unwrap().metadata().unwrap_err();
+        assert_eq!(err.context(), Some("metadata is nested too deeply"));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn metadata_compares_with_symbols() {
+        use native::ExecutableSymbol;
+        use emulator::{register_kernel, WorkItem};
+        use super::{KernelMetadata, KernelMismatch};
+
+        let _runtime = ::init().unwrap();
+        let handle = register_kernel("&__metadata_compare_test", |_: &WorkItem, _: &[u64; 2]| {});
+        let kernel = KernelMetadata {
+            name: "compare".to_string(),
+            symbol: "compare.kd".to_string(),
+            kernarg_segment_size: 16,
+            kernarg_segment_align: 4,
+            group_segment_fixed_size: 64,
+            private_segment_fixed_size: 0,
+            wavefront_size: 64,
+            sgpr_count: 0,
+            vgpr_count: 0,
+            max_flat_workgroup_size: 256,
+            args: Vec::new(),
+            descriptor: None,
+        };
+        let mismatches = kernel.compare(ExecutableSymbol { handle }).unwrap();
+        assert_eq!(mismatches, [KernelMismatch {
+            field: "group_segment_fixed_size",
+            metadata: 64,
+            runtime: 0,
+        }]);
+    }
+}
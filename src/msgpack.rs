@@ -0,0 +1,114 @@
+use elf::truncated;
+use metadata::MetadataValue;
+use super::{Error, ErrorStatus};
+
+// nesting in real metadata is a handful of levels; the limit only guards the stack
+const MAX_DEPTH: usize = 32;
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let end = self.offset.checked_add(n).ok_or_else(truncated)?;
+        if end > self.bytes.len() {
+            return Err(truncated());
+        }
+        let b = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(b)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64, Error> {
+        Ok(self.take(n)?.iter().fold(0u64, |acc, &b| acc << 8 | b as u64))
+    }
+
+    fn int(&mut self, n: usize) -> Result<i64, Error> {
+        let shift = 64 - 8 * n as u32;
+        Ok(((self.uint(n)? << shift) as i64) >> shift)
+    }
+
+    fn string(&mut self, n: usize) -> Result<MetadataValue, Error> {
+        let b = self.take(n)?;
+        Ok(MetadataValue::Str(String::from_utf8_lossy(b).into_owned()))
+    }
+
+    fn array(&mut self, n: usize, depth: usize) -> Result<MetadataValue, Error> {
+        let mut items = Vec::with_capacity(n.min(1024));
+        for _ in 0..n {
+            items.push(self.value(depth + 1)?);
+        }
+        Ok(MetadataValue::Array(items))
+    }
+
+    fn map(&mut self, n: usize, depth: usize) -> Result<MetadataValue, Error> {
+        let mut entries = Vec::with_capacity(n.min(1024));
+        for _ in 0..n {
+            let key = self.value(depth + 1)?;
+            let value = self.value(depth + 1)?;
+            entries.push((key, value));
+        }
+        Ok(MetadataValue::Map(entries))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<MetadataValue, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::from(ErrorStatus::InvalidCodeObject)
+                .with_context("metadata is nested too deeply"));
+        }
+        let tag = self.take(1)?[0];
+        Ok(match tag {
+            0x00..=0x7f => MetadataValue::UInt(tag as u64),
+            0xe0..=0xff => MetadataValue::Int(tag as i8 as i64),
+            0x80..=0x8f => return self.map((tag & 0xf) as usize, depth),
+            0x90..=0x9f => return self.array((tag & 0xf) as usize, depth),
+            0xa0..=0xbf => return self.string((tag & 0x1f) as usize),
+            0xc0 => MetadataValue::Nil,
+            0xc2 => MetadataValue::Bool(false),
+            0xc3 => MetadataValue::Bool(true),
+            0xc4..=0xc6 => {
+                let n = self.uint(1 << (tag - 0xc4))? as usize;
+                MetadataValue::Bin(self.take(n)?.to_vec())
+            }
+            // extension types are kept as their payload
+            0xc7..=0xc9 => {
+                let n = self.uint(1 << (tag - 0xc7))? as usize;
+                self.take(1)?;
+                MetadataValue::Bin(self.take(n)?.to_vec())
+            }
+            0xd4..=0xd8 => {
+                self.take(1)?;
+                MetadataValue::Bin(self.take(1 << (tag - 0xd4))?.to_vec())
+            }
+            0xca => MetadataValue::Float(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => MetadataValue::Float(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => MetadataValue::UInt(self.uint(1 << (tag - 0xcc))?),
+            0xd0..=0xd3 => MetadataValue::Int(self.int(1 << (tag - 0xd0))?),
+            0xd9..=0xdb => {
+                let n = self.uint(1 << (tag - 0xd9))? as usize;
+                return self.string(n);
+            }
+            0xdc | 0xdd => {
+                let n = self.uint(2 << (tag - 0xdc))? as usize;
+                return self.array(n, depth);
+            }
+            0xde | 0xdf => {
+                let n = self.uint(2 << (tag - 0xde))? as usize;
+                return self.map(n, depth);
+            }
+            0xc1 => {
+                return Err(Error::from(ErrorStatus::InvalidCodeObject)
+                    .with_context("invalid msgpack metadata"))
+            }
+        })
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<MetadataValue, Error> {
+    Decoder {
+        bytes,
+        offset: 0,
+    }.value(0)
+}
@@ -1,57 +1,324 @@
+use std::cell::Cell;
 use std::os::raw::{c_char, c_int, c_void};
+use std::ptr::{null, null_mut};
+use std::sync::atomic::{AtomicPtr, Ordering};
 
 pub type HSAStatus = c_int;
 
-#[link(name = "hsa-runtime64")]
-extern "C" {
+// entries in `optional` are vendor extensions that a runtime may not export; their
+// shims fail with SymbolNotFound when the current Api does not provide them
+macro_rules! hsa_api {
+    ($(fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*
+     optional {
+         $(fn $oname:ident($($oarg:ident: $oty:ty),* $(,)?) -> HSAStatus;)*
+     }) => {
+        #[derive(Copy, Clone)]
+        pub struct Api {
+            $(pub $name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+            $(pub $oname: Option<unsafe extern "C" fn($($oty),*) -> HSAStatus>,)*
+        }
+
+        #[cfg(not(any(feature = "emulator", feature = "dynamic")))]
+        mod linked {
+            use super::*;
+
+            #[link(name = "hsa-runtime64")]
+            extern "C" {
+                $(pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+            }
+        }
+
+        // optional entries are looked up in the loaded runtime rather than linked, so
+        // that runtimes without them still link and their entries stay None
+        #[cfg(not(any(feature = "emulator", feature = "dynamic")))]
+        impl Api {
+            pub fn linked() -> &'static Api {
+                static LINKED: ::std::sync::OnceLock<Api> = ::std::sync::OnceLock::new();
+                LINKED.get_or_init(|| unsafe {
+                    Api {
+                        $($name: linked::$name,)*
+                        $($oname: ::std::mem::transmute::<
+                            *mut c_void,
+                            Option<unsafe extern "C" fn($($oty),*) -> HSAStatus>,
+                        >(lookup_symbol(concat!(stringify!($oname), "\0"))),)*
+                    }
+                })
+            }
+        }
+
+        #[cfg(feature = "dynamic")]
+        impl Api {
+            /// # Safety
+            ///
+            /// Every symbol the library exports under these names must have the HSA runtime's
+            /// C signature.
+            pub unsafe fn from_library(lib: &::libloading::Library) -> Result<Api, String> {
+                Ok(Api {
+                    $($name: *lib
+                        .get::<unsafe extern "C" fn($($ty),*) $(-> $ret)?>(
+                            concat!(stringify!($name), "\0").as_bytes(),
+                        )
+                        .map_err(|_| stringify!($name).to_string())?,)*
+                    $($oname: lib
+                        .get::<unsafe extern "C" fn($($oty),*) -> HSAStatus>(
+                            concat!(stringify!($oname), "\0").as_bytes(),
+                        )
+                        .ok()
+                        .map(|f| *f),)*
+                })
+            }
+        }
+
+        // stands in until a dynamically loaded runtime is set, so calls fail with
+        // NotInitialized instead of reaching a missing library
+        #[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+        mod unloaded {
+            use super::*;
+
+            $(
+                pub unsafe extern "C" fn $name($(_: $ty),*) $(-> $ret)? {
+                    $(<$ret as Unloaded>::unloaded())?
+                }
+            )*
+            $(
+                pub unsafe extern "C" fn $oname($(_: $oty),*) -> HSAStatus {
+                    <HSAStatus as Unloaded>::unloaded()
+                }
+            )*
+        }
+
+        #[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+        impl Api {
+            fn unloaded() -> &'static Api {
+                static UNLOADED: Api = Api {
+                    $($name: unloaded::$name,)*
+                    $($oname: Some(unloaded::$oname),)*
+                };
+                &UNLOADED
+            }
+        }
+
+        #[cfg(feature = "emulator")]
+        impl Api {
+            pub fn emulated() -> &'static Api {
+                static EMULATED: Api = Api {
+                    $($name: ::emulator::runtime::$name,)*
+                    $($oname: Some(::emulator::runtime::$oname),)*
+                };
+                &EMULATED
+            }
+        }
+
+        // the shims mirror the C API one to one; its safety rules are the runtime's
+        $(
+            #[inline]
+            #[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                let _ = LAST_CALL.try_with(|c| c.set(stringify!($name)));
+                (current().$name)($($arg),*)
+            }
+        )*
+
+        $(
+            #[inline]
+            #[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+            pub unsafe fn $oname($($oarg: $oty),*) -> HSAStatus {
+                let _ = LAST_CALL.try_with(|c| c.set(stringify!($oname)));
+                match current().$oname {
+                    Some(f) => f($($oarg),*),
+                    None => ::ErrorStatus::SymbolNotFound as HSAStatus,
+                }
+            }
+        )*
+    }
+}
+
+#[cfg(all(unix, not(any(feature = "emulator", feature = "dynamic"))))]
+unsafe fn lookup_symbol(name: &str) -> *mut c_void {
+    extern "C" {
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+    // RTLD_DEFAULT searches every object loaded into the process
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let default = -2isize as *mut c_void;
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let default = null_mut();
+    dlsym(default, name.as_ptr() as *const c_char)
+}
+
+#[cfg(all(not(unix), not(any(feature = "emulator", feature = "dynamic"))))]
+unsafe fn lookup_symbol(_name: &str) -> *mut c_void {
+    null_mut()
+}
+
+// what the unloaded Api's entries return; value-returning entries only take handles
+// that an unloaded runtime never hands out
+#[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+trait Unloaded {
+    fn unloaded() -> Self;
+}
+
+#[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+impl Unloaded for HSAStatus {
+    fn unloaded() -> HSAStatus {
+        ::ErrorStatus::NotInitialized as HSAStatus
+    }
+}
+
+#[cfg(all(feature = "dynamic", not(feature = "emulator"), target_pointer_width = "64"))]
+impl Unloaded for SignalValue {
+    fn unloaded() -> SignalValue {
+        0
+    }
+}
+
+#[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+impl Unloaded for u64 {
+    fn unloaded() -> u64 {
+        0
+    }
+}
+
+static GLOBAL_API: AtomicPtr<Api> = AtomicPtr::new(null_mut());
+
+thread_local!(static SCOPED_API: Cell<*const Api> = const { Cell::new(null()) });
+
+thread_local!(static LAST_CALL: Cell<&'static str> = const { Cell::new("") });
+
+impl Api {
+    pub fn current() -> Api {
+        unsafe { *current() }
+    }
+
+    #[cfg(not(any(feature = "emulator", feature = "dynamic")))]
+    fn default_api() -> &'static Api {
+        Api::linked()
+    }
+
+    #[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+    fn default_api() -> &'static Api {
+        Api::unloaded()
+    }
+
+    #[cfg(feature = "emulator")]
+    fn default_api() -> &'static Api {
+        Api::emulated()
+    }
+}
+
+unsafe fn current<'a>() -> &'a Api {
+    let scoped = SCOPED_API.try_with(|s| s.get()).unwrap_or(null());
+    if !scoped.is_null() {
+        return &*scoped;
+    }
+    let global = GLOBAL_API.load(Ordering::Acquire);
+    if global.is_null() {
+        Api::default_api()
+    } else {
+        &*global
+    }
+}
+
+pub fn set_api(api: &'static Api) {
+    GLOBAL_API.store(api as *const Api as *mut Api, Ordering::Release);
+}
+
+pub fn last_call() -> &'static str {
+    LAST_CALL.try_with(|c| c.get()).unwrap_or("")
+}
+
+pub fn api_is_set() -> bool {
+    !GLOBAL_API.load(Ordering::Acquire).is_null()
+}
+
+#[cfg(feature = "dynamic")]
+pub const DEFAULT_LIBRARY_NAMES: &[&str] = &["libhsa-runtime64.so.1", "libhsa-runtime64.so"];
+
+#[cfg(feature = "dynamic")]
+#[derive(Debug)]
+pub enum LoadError {
+    LibraryNotFound(String),
+    SymbolNotFound(String),
+}
+
+#[cfg(feature = "dynamic")]
+pub fn load_library<P: AsRef<::std::ffi::OsStr>>(path: P) -> Result<&'static Api, LoadError> {
+    let lib = match unsafe { ::libloading::Library::new(path) } {
+        Ok(lib) => lib,
+        Err(e) => return Err(LoadError::LibraryNotFound(e.to_string())),
+    };
+    let api = match unsafe { Api::from_library(&lib) } {
+        Ok(api) => api,
+        Err(name) => return Err(LoadError::SymbolNotFound(name)),
+    };
+    // the resolved entry points must stay valid for the rest of the process
+    std::mem::forget(lib);
+    Ok(Box::leak(Box::new(api)))
+}
+
+pub fn with_api<F, R>(api: &Api, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Restore(*const Api);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_API.with(|s| s.set(self.0));
+        }
+    }
+    let _restore = Restore(SCOPED_API.with(|s| s.replace(api)));
+    f()
+}
+
+hsa_api! {
     // 2.1 Initialization and shut down
-    pub fn hsa_init() -> HSAStatus;
-    pub fn hsa_shut_down() -> HSAStatus;
+    fn hsa_init() -> HSAStatus;
+    fn hsa_shut_down() -> HSAStatus;
 
     // 2.2 Runtime notifications
-    pub fn hsa_status_string(status: HSAStatus, status_string: &*const c_char) -> HSAStatus;
+    fn hsa_status_string(status: HSAStatus, status_string: *mut *const c_char) -> HSAStatus;
 
     // 2.3 System and agent information
-    pub fn hsa_system_get_info(attribute: SystemInfo, value: *mut c_void) -> HSAStatus;
-    pub fn hsa_extension_get_name(extension: Extension, name: &*const c_char) -> HSAStatus;
-    pub fn hsa_system_extension_supported(
+    fn hsa_system_get_info(attribute: SystemInfo, value: *mut c_void) -> HSAStatus;
+    fn hsa_extension_get_name(extension: Extension, name: *mut *const c_char) -> HSAStatus;
+    fn hsa_system_extension_supported(
         extension: Extension,
         version_major: u16,
         version_minor: u16,
         result: *mut bool,
     ) -> HSAStatus;
-    pub fn hsa_system_major_extension_supported(
+    fn hsa_system_major_extension_supported(
         extension: Extension,
         version_major: u16,
         version_minor: *mut u16,
         result: *mut bool,
     ) -> HSAStatus;
-    pub fn hsa_system_get_major_extension_table(
+    fn hsa_system_get_major_extension_table(
         extension: Extension,
         version_major: u16,
         table_length: usize,
         table: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_agent_get_info(agent: Agent, attribute: AgentInfo, value: *mut c_void) -> HSAStatus;
-    pub fn hsa_iterate_agents(
+    fn hsa_agent_get_info(agent: Agent, attribute: AgentInfo, value: *mut c_void) -> HSAStatus;
+    fn hsa_iterate_agents(
         callback: extern "C" fn(Agent, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_cache_get_info(cache: Cache, attribute: CacheInfo, value: *mut c_void) -> HSAStatus;
-    pub fn hsa_agent_iterate_caches(
+    fn hsa_cache_get_info(cache: Cache, attribute: CacheInfo, value: *mut c_void) -> HSAStatus;
+    fn hsa_agent_iterate_caches(
         agent: Agent,
         callback: extern "C" fn(Cache, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_agent_extension_supported(
+    fn hsa_agent_extension_supported(
         extension: Extension,
         agent: Agent,
         version_major: u16,
         version_minor: u16,
         result: *mut bool,
     ) -> HSAStatus;
-    pub fn hsa_agent_major_extension_supported(
+    fn hsa_agent_major_extension_supported(
         extension: Extension,
         agent: Agent,
         version_major: u16,
@@ -60,130 +327,130 @@ extern "C" {
     ) -> HSAStatus;
 
     // 2.4 Signals
-    pub fn hsa_signal_create(
+    fn hsa_signal_create(
         initial_value: SignalValue,
         num_consumers: u32,
         consumers: *const Agent,
-        signal: &SignalHandle,
+        signal: *mut SignalHandle,
     ) -> HSAStatus;
-    pub fn hsa_signal_destroy(signal: SignalHandle) -> HSAStatus;
-    pub fn hsa_signal_load_scacquire(signal: SignalHandle) -> SignalValue;
-    pub fn hsa_signal_load_relaxed(signal: SignalHandle) -> SignalValue;
+    fn hsa_signal_destroy(signal: SignalHandle) -> HSAStatus;
+    fn hsa_signal_load_scacquire(signal: SignalHandle) -> SignalValue;
+    fn hsa_signal_load_relaxed(signal: SignalHandle) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_load_acquire(signal: SignalHandle) -> SignalValue;
-    pub fn hsa_signal_store_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_store_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_load_acquire(signal: SignalHandle) -> SignalValue;
+    fn hsa_signal_store_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_store_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_store_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_silent_store_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_silent_store_screlease(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_exchange_scacq_screl(signal: SignalHandle, value: SignalValue)
+    fn hsa_signal_store_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_silent_store_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_silent_store_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_exchange_scacq_screl(signal: SignalHandle, value: SignalValue)
         -> SignalValue;
-    pub fn hsa_signal_exchange_scacquire(signal: SignalHandle, value: SignalValue) -> SignalValue;
-    pub fn hsa_signal_exchange_relaxed(signal: SignalHandle, value: SignalValue) -> SignalValue;
-    pub fn hsa_signal_exchange_screlease(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_exchange_scacquire(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_exchange_relaxed(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_exchange_screlease(signal: SignalHandle, value: SignalValue) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_exchange_acq_rel(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_exchange_acq_rel(signal: SignalHandle, value: SignalValue) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_exchange_acquire(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_exchange_acquire(signal: SignalHandle, value: SignalValue) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_exchange_release(signal: SignalHandle, value: SignalValue) -> SignalValue;
-    pub fn hsa_signal_cas_scacq_screl(
+    fn hsa_signal_exchange_release(signal: SignalHandle, value: SignalValue) -> SignalValue;
+    fn hsa_signal_cas_scacq_screl(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
-    pub fn hsa_signal_cas_scacquire(
+    fn hsa_signal_cas_scacquire(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
-    pub fn hsa_signal_cas_relaxed(
+    fn hsa_signal_cas_relaxed(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
-    pub fn hsa_signal_cas_screlease(
+    fn hsa_signal_cas_screlease(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_cas_acq_rel(
+    fn hsa_signal_cas_acq_rel(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_cas_acquire(
+    fn hsa_signal_cas_acquire(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_cas_release(
+    fn hsa_signal_cas_release(
         signal: SignalHandle,
         expected: SignalValue,
         value: SignalValue,
     ) -> SignalValue;
-    pub fn hsa_signal_add_scacq_screl(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_add_scacquire(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_add_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_add_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_scacq_screl(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_scacquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_add_acq_rel(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_acq_rel(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_add_acquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_acquire(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_add_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_subtract_scacq_screl(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_subtract_scacquire(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_subtract_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_subtract_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_add_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_scacq_screl(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_scacquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_subtract_acq_rel(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_acq_rel(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_subtract_acquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_acquire(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_subtract_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_and_scacq_screl(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_and_scacquire(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_and_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_and_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_subtract_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_scacq_screl(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_scacquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_and_acq_rel(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_acq_rel(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_and_acquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_acquire(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_and_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_or_scacq_screl(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_or_scacquire(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_or_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_or_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_and_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_scacq_screl(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_scacquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_or_acq_rel(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_acq_rel(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_or_acquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_acquire(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_or_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_xor_scacq_screl(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_xor_scacquire(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_xor_relaxed(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_xor_screlease(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_or_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_scacq_screl(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_scacquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_relaxed(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_screlease(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_xor_acq_rel(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_acq_rel(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_xor_acquire(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_xor_acquire(signal: SignalHandle, value: SignalValue);
     //#[deprecated]
-    pub fn hsa_signal_xor_release(signal: SignalHandle, value: SignalValue);
-    pub fn hsa_signal_wait_scacquire(
+    fn hsa_signal_xor_release(signal: SignalHandle, value: SignalValue);
+    fn hsa_signal_wait_scacquire(
         signal: SignalHandle,
         condition: SignalCondition,
         compare_value: SignalValue,
         timeout_hint: u64,
         wait_state_hint: WaitState,
     ) -> SignalValue;
-    pub fn hsa_signal_wait_relaxed(
+    fn hsa_signal_wait_relaxed(
         signal: SignalHandle,
         condition: SignalCondition,
         compare_value: SignalValue,
@@ -191,219 +458,219 @@ extern "C" {
         wait_state_hint: WaitState,
     ) -> SignalValue;
     //#[deprecated]
-    pub fn hsa_signal_wait_acquire(
+    fn hsa_signal_wait_acquire(
         signal: SignalHandle,
         condition: SignalCondition,
         compare_value: SignalValue,
         timeout_hint: u64,
         wait_state_hint: WaitState,
     ) -> SignalValue;
-    pub fn hsa_signal_group_create(
+    fn hsa_signal_group_create(
         num_signals: u32,
         signals: *const SignalHandle,
         num_consumers: u32,
         consumers: *const Agent,
-        signal_group: &SignalGroupHandle,
+        signal_group: *mut SignalGroupHandle,
     ) -> HSAStatus;
-    pub fn hsa_signal_group_destroy(signal_group: SignalGroupHandle) -> HSAStatus;
-    pub fn hsa_signal_group_wait_any_scacquire(
+    fn hsa_signal_group_destroy(signal_group: SignalGroupHandle) -> HSAStatus;
+    fn hsa_signal_group_wait_any_scacquire(
         signal_group: SignalGroupHandle,
         conditions: *const SignalCondition,
         compare_values: *const SignalValue,
         wait_state_hint: WaitState,
-        signal: &SignalHandle,
-        value: &SignalValue,
+        signal: *mut SignalHandle,
+        value: *mut SignalValue,
     ) -> HSAStatus;
-    pub fn hsa_signal_group_wait_any_relaxed(
+    fn hsa_signal_group_wait_any_relaxed(
         signal_group: SignalGroupHandle,
         conditions: *const SignalCondition,
         compare_values: *const SignalValue,
         wait_state_hint: WaitState,
-        signal: &SignalHandle,
-        value: &SignalValue,
+        signal: *mut SignalHandle,
+        value: *mut SignalValue,
     ) -> HSAStatus;
 
     // 2.5 Queues
-    pub fn hsa_queue_create(
+    fn hsa_queue_create(
         agent: Agent,
         size: u32,
         typ: QueueType,
-        callback: *const c_void, //extern "C" fn(HSAStatus, *const QueueHandle, *const c_void),
-        data: *const c_void,
+        callback: Option<extern "C" fn(HSAStatus, *const QueueHandle, *mut c_void)>,
+        data: *mut c_void,
         private_segment_size: u32,
         group_segment_size: u32,
-        queue: &*const QueueHandle,
+        queue: *mut *const QueueHandle,
     ) -> HSAStatus;
-    pub fn hsa_soft_queue_create(
+    fn hsa_soft_queue_create(
         region: Region,
         size: u32,
         typ: QueueType,
         features: u32,
         doorbell_signal: SignalHandle,
-        queue: &*const QueueHandle,
+        queue: *mut *const QueueHandle,
     ) -> HSAStatus;
-    pub fn hsa_queue_destroy(queue: *const QueueHandle) -> HSAStatus;
-    pub fn hsa_queue_inactivate(queue: *const QueueHandle) -> HSAStatus;
-    pub fn hsa_queue_load_read_index_scacquire(queue: *const QueueHandle) -> u64;
-    pub fn hsa_queue_load_read_index_relaxed(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_destroy(queue: *const QueueHandle) -> HSAStatus;
+    fn hsa_queue_inactivate(queue: *const QueueHandle) -> HSAStatus;
+    fn hsa_queue_load_read_index_scacquire(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_load_read_index_relaxed(queue: *const QueueHandle) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_load_read_index_acquire(queue: *const QueueHandle) -> u64;
-    pub fn hsa_queue_load_write_index_scacquire(queue: *const QueueHandle) -> u64;
-    pub fn hsa_queue_load_write_index_relaxed(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_load_read_index_acquire(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_load_write_index_scacquire(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_load_write_index_relaxed(queue: *const QueueHandle) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_load_write_index_acquire(queue: *const QueueHandle) -> u64;
-    pub fn hsa_queue_store_write_index_relaxed(queue: *const QueueHandle, value: u64);
-    pub fn hsa_queue_store_write_index_screlease(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_load_write_index_acquire(queue: *const QueueHandle) -> u64;
+    fn hsa_queue_store_write_index_relaxed(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_store_write_index_screlease(queue: *const QueueHandle, value: u64);
     //#[deprecated]
-    pub fn hsa_queue_store_write_index_release(queue: *const QueueHandle, value: u64);
-    pub fn hsa_queue_cas_write_index_scacq_screl(
+    fn hsa_queue_store_write_index_release(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_cas_write_index_scacq_screl(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
-    pub fn hsa_queue_cas_write_index_scacquire(
+    fn hsa_queue_cas_write_index_scacquire(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
-    pub fn hsa_queue_cas_write_index_relaxed(
+    fn hsa_queue_cas_write_index_relaxed(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
-    pub fn hsa_queue_cas_write_index_screlease(
+    fn hsa_queue_cas_write_index_screlease(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_cas_write_index_acq_rel(
+    fn hsa_queue_cas_write_index_acq_rel(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_cas_write_index_acquire(
+    fn hsa_queue_cas_write_index_acquire(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_cas_write_index_release(
+    fn hsa_queue_cas_write_index_release(
         queue: *const QueueHandle,
         expected: u64,
         value: u64,
     ) -> u64;
-    pub fn hsa_queue_add_write_index_scacq_screl(queue: *const QueueHandle, value: u64) -> u64;
-    pub fn hsa_queue_add_write_index_scacquire(queue: *const QueueHandle, value: u64) -> u64;
-    pub fn hsa_queue_add_write_index_relaxed(queue: *const QueueHandle, value: u64) -> u64;
-    pub fn hsa_queue_add_write_index_screlease(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_scacq_screl(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_scacquire(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_relaxed(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_screlease(queue: *const QueueHandle, value: u64) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_add_write_index_acq_rel(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_acq_rel(queue: *const QueueHandle, value: u64) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_add_write_index_acquire(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_add_write_index_acquire(queue: *const QueueHandle, value: u64) -> u64;
     //#[deprecated]
-    pub fn hsa_queue_add_write_index_release(queue: *const QueueHandle, value: u64) -> u64;
-    pub fn hsa_queue_store_read_index_relaxed(queue: *const QueueHandle, value: u64);
-    pub fn hsa_queue_store_read_index_screlease(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_add_write_index_release(queue: *const QueueHandle, value: u64) -> u64;
+    fn hsa_queue_store_read_index_relaxed(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_store_read_index_screlease(queue: *const QueueHandle, value: u64);
     //#[deprecated]
-    pub fn hsa_queue_store_read_index_release(queue: *const QueueHandle, value: u64);
+    fn hsa_queue_store_read_index_release(queue: *const QueueHandle, value: u64);
 
     // 2.7 Memory
-    pub fn hsa_region_get_info(
+    fn hsa_region_get_info(
         region: Region,
         attribute: RegionInfo,
         value: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_agent_iterate_regions(
+    fn hsa_agent_iterate_regions(
         agent: Agent,
         callback: extern "C" fn(Region, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_memory_allocate(region: Region, size: usize, ptr: &*mut c_void) -> HSAStatus;
-    pub fn hsa_memory_free(ptr: *mut c_void) -> HSAStatus;
-    pub fn hsa_memory_copy(dst: *mut c_void, src: *const c_void, size: usize) -> HSAStatus;
-    pub fn hsa_memory_assign_agent(
+    fn hsa_memory_allocate(region: Region, size: usize, ptr: *mut *mut c_void) -> HSAStatus;
+    fn hsa_memory_free(ptr: *mut c_void) -> HSAStatus;
+    fn hsa_memory_copy(dst: *mut c_void, src: *const c_void, size: usize) -> HSAStatus;
+    fn hsa_memory_assign_agent(
         ptr: *mut c_void,
         agent: Agent,
         access: AccessPermission,
     ) -> HSAStatus;
-    pub fn hsa_memory_register(ptr: *mut c_void, size: usize) -> HSAStatus;
-    pub fn hsa_memory_deregister(ptr: *mut c_void, size: usize) -> HSAStatus;
+    fn hsa_memory_register(ptr: *mut c_void, size: usize) -> HSAStatus;
+    fn hsa_memory_deregister(ptr: *mut c_void, size: usize) -> HSAStatus;
 
     // 2.8 Code object loading
-    pub fn hsa_isa_from_name(name: *const c_char, isa: &ISA) -> HSAStatus;
-    pub fn hsa_agent_iterate_isas(
+    fn hsa_isa_from_name(name: *const c_char, isa: *mut ISA) -> HSAStatus;
+    fn hsa_agent_iterate_isas(
         agent: Agent,
         callback: extern "C" fn(ISA, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
     /*#[deprecated]
-    pub fn hsa_isa_get_info(
+    fn hsa_isa_get_info(
         isa: ISA,
         attribute: ISAInfo,
         index: u32,
         value: *mut c_void,
     ) -> HSAStatus;*/
-    pub fn hsa_isa_get_info_alt(isa: ISA, attribute: ISAInfo, value: *mut c_void) -> HSAStatus;
-    pub fn hsa_isa_get_exception_policies(isa: ISA, profile: Profile, mask: &mut u16) -> HSAStatus;
-    pub fn hsa_isa_get_round_method(
+    fn hsa_isa_get_info_alt(isa: ISA, attribute: ISAInfo, value: *mut c_void) -> HSAStatus;
+    fn hsa_isa_get_exception_policies(isa: ISA, profile: Profile, mask: &mut u16) -> HSAStatus;
+    fn hsa_isa_get_round_method(
         isa: ISA,
         fp_type: FpType,
         flush_mode: FlushMode,
-        round_method: &RoundMethod,
+        round_method: *mut RoundMethod,
     ) -> HSAStatus;
-    pub fn hsa_wavefront_get_info(
+    fn hsa_wavefront_get_info(
         wavefront: Wavefront,
         attribute: WavefrontInfo,
         value: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_isa_iterate_wavefronts(
+    fn hsa_isa_iterate_wavefronts(
         isa: ISA,
         callback: extern "C" fn(Wavefront, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
     /*#[deprecated]
-    pub fn hsa_isa_compatible(code_object_isa: ISA, agent_isa: ISA, result: &bool) -> HSAStatus;*/
-    /*pub fn hsa_code_object_reader_create_from_file(
+    fn hsa_isa_compatible(code_object_isa: ISA, agent_isa: ISA, result: &bool) -> HSAStatus;*/
+    fn hsa_code_object_reader_create_from_file(
         file: HSAFile,
-        code_object_reader: &CodeObjectReader,
+        code_object_reader: *mut CodeObjectReader,
     ) -> HSAStatus;
-    pub fn hsa_code_object_reader_create_from_memory(
+    fn hsa_code_object_reader_create_from_memory(
         code_object: *const c_void,
         size: usize,
-        code_object_reader: &CodeObjectReader,
+        code_object_reader: *mut CodeObjectReader,
     ) -> HSAStatus;
-    pub fn hsa_code_object_reader_destroy(code_object_reader: CodeObjectReader) -> HSAStatus;
-    #[deprecated]
-    pub fn hsa_executable_create(
+    fn hsa_code_object_reader_destroy(code_object_reader: CodeObjectReader) -> HSAStatus;
+    /*#[deprecated]
+    fn hsa_executable_create(
         profile: Profile,
         executable_state: ExecutableState,
         options: *const c_char,
         executable: &Executable,
     ) -> HSAStatus;*/
-    pub fn hsa_executable_create_alt(
+    fn hsa_executable_create_alt(
         profile: Profile,
         default_float_rouding_mode: DefaultFloatRoundingMode,
         options: *const c_char,
-        executable: &Executable,
+        executable: *mut Executable,
     ) -> HSAStatus;
-    pub fn hsa_executable_destroy(executable: Executable) -> HSAStatus;
-    /*pub fn hsa_executable_load_program_code_object(
+    fn hsa_executable_destroy(executable: Executable) -> HSAStatus;
+    fn hsa_executable_load_program_code_object(
         executable: Executable,
         code_object_reader: CodeObjectReader,
         options: *const c_char,
-        loaded_code_object: &LoadedCodeObject,
+        loaded_code_object: *mut LoadedCodeObject,
     ) -> HSAStatus;
-    pub fn hsa_executable_load_agent_code_object(
+    fn hsa_executable_load_agent_code_object(
         executable: Executable,
         agent: Agent,
         code_object_reader: CodeObjectReader,
-        options: *const c_void,
-        loaded_code_object: &LoadedCodeObject,
-    ) -> HSAStatus;*/
-    pub fn hsa_executable_freeze(executable: Executable, options: *const c_char) -> HSAStatus;
-    pub fn hsa_executable_get_info(
+        options: *const c_char,
+        loaded_code_object: *mut LoadedCodeObject,
+    ) -> HSAStatus;
+    fn hsa_executable_freeze(executable: Executable, options: *const c_char) -> HSAStatus;
+    fn hsa_executable_get_info(
         executable: Executable,
         attribute: ExecutableInfo,
         value: *mut c_void,
@@ -413,70 +680,57 @@ extern "C" {
         variable_name: *const c_char,
         address: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_executable_agent_global_variable_define(
+    fn hsa_executable_agent_global_variable_define(
         executable: Executable,
         agent: Agent,
         variable_name: *const c_char,
         address: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_executable_readonly_variable_define(
+    fn hsa_executable_readonly_variable_define(
         executable: Executable,
         agent: Agent,
         variable_name: *const c_char,
         address: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_executable_validate(executable: Executable, result: *mut u32) -> HSAStatus;
-    pub fn hsa_executable_validate_alt(
+    fn hsa_executable_validate(executable: Executable, result: *mut u32) -> HSAStatus;
+    fn hsa_executable_validate_alt(
         executable: Executable,
         options: *const c_char,
         result: *mut u32,
     ) -> HSAStatus;*/
     //#[deprecated]
-    pub fn hsa_executable_get_symbol(
+    fn hsa_executable_get_symbol(
         executable: Executable,
         module_name: *const c_char,
         symbol_name: *const c_char,
         agent: Agent,
         call_convention: i32,
-        symbol: &ExecutableSymbol,
-    ) -> HSAStatus;
-    /*#[deprecated]
-    pub fn hsa_executable_get_symbol_by_name(
-        executable: Executable,
-        symbol_name: *const c_char,
-        agent: &Agent,
-        symbol: &ExecutableSymbol,
+        symbol: *mut ExecutableSymbol,
     ) -> HSAStatus;
-    pub fn hsa_executable_get_symbol_by_linker_name(
-        executable: Executable,
-        linker_name: *const c_void,
-        agent: &Agent,
-        symbol: &ExecutableSymbol,
-    ) -> HSAStatus;*/
-    pub fn hsa_executable_symbol_get_info(
+    fn hsa_executable_symbol_get_info(
         executable_symbol: ExecutableSymbol,
         attribute: ExecutableSymbolInfo,
         value: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_executable_iterate_agent_symbols(
+    fn hsa_executable_iterate_agent_symbols(
         executable: Executable,
         agent: Agent,
         callback: extern "C" fn(Executable, Agent, ExecutableSymbol, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_executable_iterate_program_symbols(
+    fn hsa_executable_iterate_program_symbols(
         executable: Executable,
         callback: extern "C" fn(Executable, ExecutableSymbol, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_executable_iterate_symbols(
+    fn hsa_executable_iterate_symbols(
         executable: Executable,
         callback: extern "C" fn(Executable, ExecutableSymbol, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
     /*#[deprecated]
-    pub fn hsa_code_object_serialize(
+    fn hsa_code_object_serialize(
         code_object_reader: CodeObject,
         alloc_callback: extern "C" fn(size: usize, data: CallbackData, address: *mut *mut c_void)
                                       -> HSAStatus,
@@ -486,22 +740,22 @@ extern "C" {
         serialized_code_object_size: *mut usize,
     ) -> HSAStatus;
     #[deprecated]
-    pub fn hsa_code_object_deserialize(
+    fn hsa_code_object_deserialize(
         serialized_code_object: *mut c_void,
         serialized_code_object_size: usize,
         options: *const c_char,
         code_object: CodeObject,
     ) -> HSAStatus;*/
     //#[deprecated]
-    pub fn hsa_code_object_destroy(code_object: CodeObject) -> HSAStatus;
+    fn hsa_code_object_destroy(code_object: CodeObject) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_code_object_get_info(
+    fn hsa_code_object_get_info(
         code_object: CodeObject,
         attribute: CodeObjectInfo,
         value: *mut c_void,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_executable_load_code_object(
+    fn hsa_executable_load_code_object(
         executable: Executable,
         agent: Agent,
         code_object: CodeObject,
@@ -514,20 +768,20 @@ extern "C" {
         symbol: &CodeSymbol,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_code_object_get_symbol_from_name(
+    fn hsa_code_object_get_symbol_from_name(
         code_object: CodeObject,
         module_name: *const c_char,
         symbol_name: *const c_char,
         symbol: &CodeSymbol,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_code_symbol_get_info(
+    fn hsa_code_symbol_get_info(
         code_symbol: CodeSymbol,
         attribute: CodeSymbolInfo,
         value: *mut c_void,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_code_object_iterate_symbols(
+    fn hsa_code_object_iterate_symbols(
         code_object: CodeObject,
         callback: extern "C" fn(CodeObject, CodeSymbol, *mut c_void) -> HSAStatus,
         data: *mut c_void,
@@ -538,41 +792,41 @@ extern "C" {
         callback: extern "C" fn(ISA, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_ext_isa_from_name(name: *const c_char, isa: &mut ISA) -> HSAStatus;
+    fn hsa_ext_isa_from_name(name: *const c_char, isa: &mut ISA) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_ext_isa_get_info(
+    fn hsa_ext_isa_get_info(
         isa: ISA,
         attribute: ISAInfo,
         index: u32,
         value: *mut c_void,
     ) -> HSAStatus;
-    pub fn hsa_ext_code_object_writer_create_from_file(
+    fn hsa_ext_code_object_writer_create_from_file(
         file: HSAFile,
         code_object_writer: &ExtCodeObjectWriterHandle,
     ) -> HSAStatus;*/
-    pub fn hsa_ext_code_object_writer_create_from_memory(
+    fn hsa_ext_code_object_writer_create_from_memory(
         memory_allocate: extern "C" fn(usize, usize, *mut *mut c_void, *mut c_void) -> HSAStatus,
         data: *mut c_void,
-        code_object_writer: &ExtCodeObjectWriterHandle,
+        code_object_writer: *mut ExtCodeObjectWriterHandle,
     ) -> HSAStatus;
-    pub fn hsa_ext_code_object_writer_destroy(
+    fn hsa_ext_code_object_writer_destroy(
         code_object_writer: ExtCodeObjectWriterHandle,
     ) -> HSAStatus;
-    pub fn hsa_ext_program_create(
+    fn hsa_ext_program_create(
         machine_model: MachineModel,
         profile: Profile,
         default_float_rouding_mode: DefaultFloatRoundingMode,
         options: *const c_char,
-        program: &ExtProgramHandle,
+        program: *mut ExtProgramHandle,
     ) -> HSAStatus;
-    pub fn hsa_ext_program_destroy(program: ExtProgramHandle) -> HSAStatus;
-    pub fn hsa_ext_program_add_module(program: ExtProgramHandle, module: ExtModule) -> HSAStatus;
+    fn hsa_ext_program_destroy(program: ExtProgramHandle) -> HSAStatus;
+    fn hsa_ext_program_add_module(program: ExtProgramHandle, module: ExtModule) -> HSAStatus;
     /*pub fn hsa_ext_program_iterate_modules(
         program: ExtProgramHandle,
         callback: extern "C" fn(ExtProgramHandle, ExtModule, *mut c_void) -> HSAStatus,
         data: *mut c_void,
     ) -> HSAStatus;*/
-    pub fn hsa_ext_program_get_info(
+    fn hsa_ext_program_get_info(
         program: ExtProgramHandle,
         attribute: ExtProgramInfo,
         value: *mut c_void,
@@ -582,22 +836,115 @@ extern "C" {
         options: *const c_char,
         code_object_writer: &ExtCodeObjectWriterHandle,
     ) -> HSAStatus;*/
-    pub fn hsa_ext_agent_code_object_finalize(
+    fn hsa_ext_agent_code_object_finalize(
         program: ExtProgramHandle,
         isa: ISA,
         options: *const c_char,
         code_object_writer: &ExtCodeObjectWriterHandle,
     ) -> HSAStatus;
     //#[deprecated]
-    pub fn hsa_ext_program_finalize(
+    fn hsa_ext_program_finalize(
         program: ExtProgramHandle,
         isa: ISA,
         call_convention: i32,
         control_directives: ExtControlDirectives,
         options: *const c_char,
         code_object_type: CodeObjectType,
-        code_object: &CodeObject,
+        code_object: *mut CodeObject,
     ) -> HSAStatus;
+
+    optional {
+        // AMD extensions (hsa_ext_amd.h)
+        fn hsa_amd_memory_async_copy(
+            dst: *mut c_void,
+            dst_agent: Agent,
+            src: *const c_void,
+            src_agent: Agent,
+            size: usize,
+            num_dep_signals: u32,
+            dep_signals: *const SignalHandle,
+            completion_signal: SignalHandle,
+        ) -> HSAStatus;
+        fn hsa_amd_memory_pool_get_info(
+            memory_pool: MemoryPool,
+            attribute: MemoryPoolInfo,
+            value: *mut c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_agent_iterate_memory_pools(
+            agent: Agent,
+            callback: extern "C" fn(MemoryPool, *mut c_void) -> HSAStatus,
+            data: *mut c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_memory_pool_allocate(
+            memory_pool: MemoryPool,
+            size: usize,
+            flags: u32,
+            ptr: *mut *mut c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_memory_pool_free(ptr: *mut c_void) -> HSAStatus;
+        fn hsa_amd_agents_allow_access(
+            num_agents: u32,
+            agents: *const Agent,
+            flags: *const u32,
+            ptr: *const c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_memory_pool_can_migrate(
+            src_memory_pool: MemoryPool,
+            dst_memory_pool: MemoryPool,
+            result: *mut bool,
+        ) -> HSAStatus;
+        fn hsa_amd_memory_migrate(
+            ptr: *const c_void,
+            memory_pool: MemoryPool,
+            flags: u32,
+        ) -> HSAStatus;
+        fn hsa_amd_agent_memory_pool_get_info(
+            agent: Agent,
+            memory_pool: MemoryPool,
+            attribute: AgentMemoryPoolInfo,
+            value: *mut c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_signal_create(
+            initial_value: SignalValue,
+            num_consumers: u32,
+            consumers: *const Agent,
+            attributes: u64,
+            signal: *mut SignalHandle,
+        ) -> HSAStatus;
+        fn hsa_amd_ipc_memory_create(
+            ptr: *mut c_void,
+            len: usize,
+            handle: *mut IpcHandle,
+        ) -> HSAStatus;
+        fn hsa_amd_ipc_memory_attach(
+            handle: *const IpcHandle,
+            len: usize,
+            num_agents: u32,
+            mapping_agents: *const Agent,
+            mapped_ptr: *mut *mut c_void,
+        ) -> HSAStatus;
+        fn hsa_amd_ipc_memory_detach(mapped_ptr: *mut c_void) -> HSAStatus;
+        fn hsa_amd_ipc_signal_create(signal: SignalHandle, handle: *mut IpcHandle) -> HSAStatus;
+        fn hsa_amd_ipc_signal_attach(
+            handle: *const IpcHandle,
+            signal: *mut SignalHandle,
+        ) -> HSAStatus;
+
+        // 2.8 Code object loading: HSA 1.2 renamed get_symbol_by_name and a runtime may
+        // export either name, so neither is required
+        fn hsa_executable_get_symbol_by_name(
+            executable: Executable,
+            symbol_name: *const c_char,
+            agent: *const Agent,
+            symbol: *mut ExecutableSymbol,
+        ) -> HSAStatus;
+        fn hsa_executable_get_symbol_by_linker_name(
+            executable: Executable,
+            linker_name: *const c_char,
+            agent: *const Agent,
+            symbol: *mut ExecutableSymbol,
+        ) -> HSAStatus;
+    }
 }
 
 // 2.3 System and agent information
@@ -649,15 +996,17 @@ pub enum Extension {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct Agent {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+enum_from_primitive! {
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Debug)]
 #[repr(C)]
 pub enum AgentFeature {
     KernelDispatch = 1,
     AgentDispatch = 2,
 }
+}
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
@@ -716,7 +1065,7 @@ pub enum ExceptionPolicy {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct Cache {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -778,8 +1127,8 @@ pub type QueueType32 = u32;
 #[derive(Clone, Debug)]
 #[repr(C)]
 pub struct QueueHandle {
-    typ: QueueType32,
-    features: QueueFeature,
+    pub(crate) typ: QueueType32,
+    pub(crate) features: u32,
     pub base_address: *const c_void,
 
     #[cfg(target_pointer_width = "32")]
@@ -788,16 +1137,17 @@ pub struct QueueHandle {
     pub doorbell_signal: SignalHandle,
     pub size: u32,
     reserved1: u32,
-    id: u64,
+    pub(crate) id: u64,
 }
 
-#[allow(dead_code)]
-#[derive(Copy, Clone, PartialEq, Debug)]
+enum_from_primitive! {
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Debug)]
 #[repr(u32)]
 pub enum QueueFeature {
     KernelDispatch = 1,
     AgentDispatch = 2,
 }
+}
 
 // 2.6 Architected Queuing Language packets
 
@@ -891,37 +1241,37 @@ pub struct KernelDispatchPacket {
 #[derive(Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct AgentDispatchPacket {
-    header: u16,
-    typ: u16,
+    pub(crate) header: u16,
+    pub(crate) typ: u16,
     reserved0: u32,
-    return_address: *const c_void,
+    pub(crate) return_address: *const c_void,
     #[cfg(target_pointer_width = "32")]
     reserved1: u32,
-    args: [u64; 4],
+    pub(crate) args: [u64; 4],
     reserved2: u64,
-    completion_signal: SignalHandle,
+    pub(crate) completion_signal: SignalHandle,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct BarrierAndPacket {
-    header: u16,
+    pub(crate) header: u16,
     reserved0: u16,
     reserved1: u32,
-    dep_signal: [SignalHandle; 5],
+    pub(crate) dep_signal: [SignalHandle; 5],
     reserved2: u64,
-    completion_signal: SignalHandle,
+    pub(crate) completion_signal: SignalHandle,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct BarrierOrPacket {
-    header: u16,
+    pub(crate) header: u16,
     reserved0: u16,
     reserved1: u32,
-    dep_signal: [SignalHandle; 5],
+    pub(crate) dep_signal: [SignalHandle; 5],
     reserved2: u64,
-    completion_signal: SignalHandle,
+    pub(crate) completion_signal: SignalHandle,
 }
 
 // 2.7 Memory
@@ -962,6 +1312,88 @@ pub enum RegionInfo {
     RuntimeAllocAllowed = 5,
     RuntimeAllocGranule = 6,
     RuntimeAllocAlignment = 7,
+    // HSA_AMD_REGION_INFO_HOST_ACCESSIBLE
+    HostAccessible = 0xA000,
+}
+
+// AMD memory pools (hsa_ext_amd.h)
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[repr(C)]
+pub struct MemoryPool {
+    pub handle: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub enum MemoryPoolSegment {
+    Global = 0,
+    ReadOnly = 1,
+    Private = 2,
+    Group = 3,
+}
+
+enum_from_primitive! {
+#[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Debug)]
+#[repr(C)]
+pub enum MemoryPoolGlobalFlag {
+    KernArgInit = 1,
+    FineGrained = 2,
+    CoarseGrained = 4,
+    ExtendedScopeFineGrained = 8,
+}
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub enum MemoryPoolInfo {
+    Segment = 0,
+    GlobalFlags = 1,
+    Size = 2,
+    RuntimeAllocAllowed = 5,
+    RuntimeAllocGranule = 6,
+    RuntimeAllocAlignment = 7,
+    AccessibleByAll = 15,
+    AllocMaxSize = 16,
+    Location = 17,
+    RuntimeAllocRecGranule = 18,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub enum MemoryPoolLocation {
+    CPU = 0,
+    GPU = 1,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub enum AgentMemoryPoolInfo {
+    Access = 0,
+    NumLinkHops = 1,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub enum MemoryPoolAccess {
+    NeverAllowed = 0,
+    AllowedByDefault = 1,
+    DisallowedByDefault = 2,
+}
+
+// AMD inter-process sharing (hsa_ext_amd.h)
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[repr(C)]
+pub struct IpcHandle {
+    pub handle: [u32; 8],
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub enum AmdSignalAttribute {
+    AmdGpuOnly = 1,
+    Ipc = 2,
 }
 
 // 2.8 Code object loading
@@ -969,7 +1401,7 @@ pub enum RegionInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct ISA {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -1017,7 +1449,7 @@ pub enum RoundMethod {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct Wavefront {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -1029,7 +1461,7 @@ pub enum WavefrontInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct CodeObjectReader {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -1048,7 +1480,7 @@ pub enum ExecutableState {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct LoadedCodeObject {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -1062,7 +1494,7 @@ pub enum ExecutableInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct ExecutableSymbol {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -1129,14 +1561,14 @@ pub enum ExecutableSymbolInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct CodeObject {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[deprecated]
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct CallbackData {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 #[deprecated]
@@ -1162,7 +1594,7 @@ pub enum CodeObjectInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct CodeSymbol {
-    handle: u64,
+    pub(crate) handle: u64,
 }
 
 /*
@@ -1197,12 +1629,12 @@ pub enum CodeSymbolInfo {
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(C)]
 pub struct Dim3 {
-    x: u32,
-    y: u32,
-    z: u32,
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) z: u32,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[repr(C)]
 pub enum AccessPermission {
     RO = 1,
@@ -1210,7 +1642,7 @@ pub enum AccessPermission {
     RW = 3,
 }
 
-//pub type HSAFile = c_int;
+pub type HSAFile = c_int;
 
 // 3.2 HSAIL finalization (Extension)
 
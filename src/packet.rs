@@ -0,0 +1,256 @@
+use std::mem::zeroed;
+use std::os::raw::c_void;
+
+use native::*;
+use signal::SignalBase;
+use super::{Error, ErrorStatus};
+
+#[derive(Copy, Clone)]
+struct Header {
+    barrier: bool,
+    acquire_fence: FenceScope,
+    release_fence: FenceScope,
+}
+
+impl Header {
+    fn new() -> Header {
+        Header {
+            barrier: false,
+            acquire_fence: FenceScope::System,
+            release_fence: FenceScope::System,
+        }
+    }
+
+    fn encode(&self, typ: PacketType) -> u16 {
+        ((typ as u16) << (PacketHeader::Type as u16)) |
+            ((self.barrier as u16) << (PacketHeader::Barrier as u16)) |
+            ((self.acquire_fence as u16) << (PacketHeader::ScacquireFenceScope as u16)) |
+            ((self.release_fence as u16) << (PacketHeader::ScreleaseFenceScope as u16))
+    }
+}
+
+macro_rules! header_methods {
+    () => {
+        pub fn barrier(mut self, barrier: bool) -> Self {
+            self.header.barrier = barrier;
+            self
+        }
+
+        pub fn acquire_fence(mut self, scope: FenceScope) -> Self {
+            self.header.acquire_fence = scope;
+            self
+        }
+
+        pub fn release_fence(mut self, scope: FenceScope) -> Self {
+            self.header.release_fence = scope;
+            self
+        }
+
+        pub fn completion_signal<S: SignalBase>(mut self, signal: &S) -> Self {
+            self.packet.completion_signal = signal.handle();
+            self
+        }
+    }
+}
+
+pub struct KernelDispatchPacketBuilder {
+    header: Header,
+    packet: KernelDispatchPacket,
+    grid_size: Vec<u32>,
+    workgroup_size: Vec<u16>,
+}
+
+impl KernelDispatchPacket {
+    pub fn builder(kernel_object: u64) -> KernelDispatchPacketBuilder {
+        let mut packet: KernelDispatchPacket = unsafe { zeroed() };
+        packet.kernel_object = kernel_object;
+        KernelDispatchPacketBuilder {
+            header: Header::new(),
+            packet,
+            grid_size: Vec::new(),
+            workgroup_size: Vec::new(),
+        }
+    }
+}
+
+impl KernelDispatchPacketBuilder {
+    header_methods!();
+
+    pub fn grid_size(mut self, grid_size: &[u32]) -> Self {
+        self.grid_size = grid_size.to_vec();
+        self
+    }
+
+    pub fn workgroup_size(mut self, workgroup_size: &[u16]) -> Self {
+        self.workgroup_size = workgroup_size.to_vec();
+        self
+    }
+
+    pub fn private_segment_size(mut self, size: u32) -> Self {
+        self.packet.private_segment_size = size;
+        self
+    }
+
+    pub fn group_segment_size(mut self, size: u32) -> Self {
+        self.packet.group_segment_size = size;
+        self
+    }
+
+    pub fn kernarg_address<T>(mut self, kernarg_address: *const T) -> Self {
+        self.packet.kernarg_address = kernarg_address as *const c_void;
+        self
+    }
+
+    pub fn build(self) -> Result<KernelDispatchPacket, Error> {
+        let dims = self.grid_size.len();
+        if dims == 0 || dims > 3 || dims != self.workgroup_size.len() ||
+            self.workgroup_size.contains(&0)
+        {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        let mut packet = self.packet;
+        packet.header = self.header.encode(PacketType::KernelDispatch);
+        packet.setup = (dims as u16) << (KernelDispatchPacketSetup::Dimensions as u16);
+        packet.grid_size_x = self.grid_size[0];
+        packet.grid_size_y = *self.grid_size.get(1).unwrap_or(&1);
+        packet.grid_size_z = *self.grid_size.get(2).unwrap_or(&1);
+        packet.workgroup_size_x = self.workgroup_size[0];
+        packet.workgroup_size_y = *self.workgroup_size.get(1).unwrap_or(&1);
+        packet.workgroup_size_z = *self.workgroup_size.get(2).unwrap_or(&1);
+        Ok(packet)
+    }
+}
+
+pub struct AgentDispatchPacketBuilder {
+    header: Header,
+    packet: AgentDispatchPacket,
+    args: Vec<u64>,
+}
+
+impl AgentDispatchPacket {
+    pub fn builder(typ: u16) -> AgentDispatchPacketBuilder {
+        let mut packet: AgentDispatchPacket = unsafe { zeroed() };
+        packet.typ = typ;
+        AgentDispatchPacketBuilder {
+            header: Header::new(),
+            packet,
+            args: Vec::new(),
+        }
+    }
+}
+
+impl AgentDispatchPacketBuilder {
+    header_methods!();
+
+    pub fn arg(mut self, arg: u64) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    pub fn return_address<T>(mut self, return_address: *mut T) -> Self {
+        self.packet.return_address = return_address as *const c_void;
+        self
+    }
+
+    pub fn build(self) -> Result<AgentDispatchPacket, Error> {
+        if self.args.len() > 4 {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        let mut packet = self.packet;
+        packet.header = self.header.encode(PacketType::AgentDispatch);
+        packet.args[..self.args.len()].copy_from_slice(&self.args);
+        Ok(packet)
+    }
+}
+
+macro_rules! barrier_packet_builder {
+    ($packet:ident, $builder:ident, $typ:expr) => {
+        pub struct $builder {
+            header: Header,
+            packet: $packet,
+            dep_signals: Vec<SignalHandle>,
+        }
+
+        impl $packet {
+            pub fn builder() -> $builder {
+                $builder {
+                    header: Header::new(),
+                    packet: unsafe { zeroed() },
+                    dep_signals: Vec::new(),
+                }
+            }
+        }
+
+        impl $builder {
+            header_methods!();
+
+            pub fn dep_signal<S: SignalBase>(mut self, signal: &S) -> Self {
+                self.dep_signals.push(signal.handle());
+                self
+            }
+
+            pub fn build(self) -> Result<$packet, Error> {
+                if self.dep_signals.len() > 5 {
+                    return Err(ErrorStatus::InvalidArgument.into());
+                }
+                let mut packet = self.packet;
+                packet.header = self.header.encode($typ);
+                packet.dep_signal[..self.dep_signals.len()].copy_from_slice(&self.dep_signals);
+                Ok(packet)
+            }
+        }
+    }
+}
+
+barrier_packet_builder!(BarrierAndPacket, BarrierAndPacketBuilder, PacketType::BarrierAnd);
+barrier_packet_builder!(BarrierOrPacket, BarrierOrPacketBuilder, PacketType::BarrierOr);
+
+#[cfg(test)]
+mod tests {
+    use native::*;
+
+    #[test]
+    fn builders_encode_headers_and_reject_bad_packets() {
+        let packet = KernelDispatchPacket::builder(7)
+            .grid_size(&[64, 2])
+            .workgroup_size(&[16, 2])
+            .build()
+            .unwrap();
+        assert_eq!(packet.header, 2 | 2 << 9 | 2 << 11);
+        assert_eq!(packet.setup, 2);
+        assert_eq!((packet.grid_size_y, packet.grid_size_z, packet.workgroup_size_z), (2, 1, 1));
+        assert_eq!(packet.kernel_object, 7);
+        assert!(KernelDispatchPacket::builder(7).build().is_err());
+        assert!(KernelDispatchPacket::builder(7).grid_size(&[1, 1]).workgroup_size(&[1])
+            .build()
+            .is_err());
+        assert!(KernelDispatchPacket::builder(7).grid_size(&[1]).workgroup_size(&[0])
+            .build()
+            .is_err());
+        assert!(KernelDispatchPacket::builder(7).grid_size(&[1; 4]).workgroup_size(&[1; 4])
+            .build()
+            .is_err());
+
+        let signal = SignalHandle { handle: 9 };
+        let barrier = BarrierAndPacket::builder()
+            .barrier(true)
+            .acquire_fence(FenceScope::Agent)
+            .release_fence(FenceScope::None)
+            .dep_signal(&signal)
+            .completion_signal(&signal)
+            .build()
+            .unwrap();
+        assert_eq!(barrier.header, 3 | 1 << 8 | 1 << 9);
+        assert_eq!((barrier.dep_signal[0].handle, barrier.dep_signal[1].handle), (9, 0));
+        assert_eq!(barrier.completion_signal.handle, 9);
+        let six = (0..6).fold(BarrierOrPacket::builder(), |b, _| b.dep_signal(&signal));
+        assert!(six.build().is_err());
+        let five = (0..5).fold(BarrierOrPacket::builder(), |b, _| b.dep_signal(&signal));
+        assert_eq!(five.build().unwrap().header & 0xff, 5);
+
+        let agent = AgentDispatchPacket::builder(3).arg(1).arg(2).build().unwrap();
+        assert_eq!((agent.header & 0xff, agent.typ), (4, 3));
+        assert_eq!(agent.args, [1, 2, 0, 0]);
+        assert!((0..5).fold(AgentDispatchPacket::builder(3), |b, a| b.arg(a)).build().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::ptr::null_mut;
+use std::sync::{Mutex, MutexGuard};
+
+use native::*;
+use runtime::Runtime;
+use super::{check, Error, ErrorStatus};
+
+const DEFAULT_CHUNK_SIZE: usize = 2 << 20;
+const MIN_BLOCK_SIZE: usize = 64;
+
+struct Chunk {
+    // free block offsets per order; a block of order n is MIN_BLOCK_SIZE << n bytes
+    free: Vec<BTreeSet<usize>>,
+}
+
+struct PoolState {
+    chunks: BTreeMap<usize, Chunk>,
+    in_use: usize,
+    requested: usize,
+    dedicated: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    pub chunks: usize,
+    pub reserved: usize,
+    pub in_use: usize,
+    pub requested: usize,
+    pub free: usize,
+    pub largest_free_block: usize,
+    pub dedicated: usize,
+}
+
+impl PoolStats {
+    pub fn fragmentation(&self) -> f64 {
+        if self.free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f64 / self.free as f64
+        }
+    }
+}
+
+pub struct RegionPool<'rt> {
+    region: Region,
+    chunk_size: usize,
+    max_order: usize,
+    alignment: usize,
+    alloc_max_size: usize,
+    state: Mutex<PoolState>,
+    runtime: PhantomData<&'rt Runtime>,
+}
+
+impl<'rt> RegionPool<'rt> {
+    pub fn new(runtime: &'rt Runtime, region: Region) -> Result<RegionPool<'rt>, Error> {
+        RegionPool::with_chunk_size(runtime, region, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(
+        _runtime: &'rt Runtime,
+        region: Region,
+        chunk_size: usize,
+    ) -> Result<RegionPool<'rt>, Error> {
+        if !region.runtime_alloc_allowed()? {
+            return Err(Error::from(ErrorStatus::InvalidRegion)
+                .with_context("region does not allow runtime allocation"));
+        }
+        let granule = region.runtime_alloc_granule()?;
+        let alloc_max_size = region.alloc_max_size()?;
+        let mut chunk_size = chunk_size.max(granule).max(MIN_BLOCK_SIZE).next_power_of_two();
+        while chunk_size > alloc_max_size && chunk_size > MIN_BLOCK_SIZE {
+            chunk_size /= 2;
+        }
+        Ok(RegionPool {
+            region,
+            chunk_size,
+            max_order: (chunk_size / MIN_BLOCK_SIZE).trailing_zeros() as usize,
+            alignment: region.runtime_alloc_alignment()?,
+            alloc_max_size,
+            state: Mutex::new(PoolState {
+                chunks: BTreeMap::new(),
+                in_use: 0,
+                requested: 0,
+                dedicated: 0,
+            }),
+            runtime: PhantomData,
+        })
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn allocate(&self, size: usize, align: usize) -> Result<PoolBlock<'_, 'rt>, Error> {
+        if size == 0 || !align.is_power_of_two() {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        if align > self.alignment {
+            return Err(Error::from(ErrorStatus::InvalidArgument)
+                .with_context("alignment exceeds the region's runtime_alloc_alignment"));
+        }
+        let block_size = size.max(align).max(MIN_BLOCK_SIZE).next_power_of_two();
+        if block_size > self.chunk_size {
+            return self.allocate_dedicated(size);
+        }
+        let order = (block_size / MIN_BLOCK_SIZE).trailing_zeros() as usize;
+        let mut state = self.state();
+        let (base, offset) = match take_block(&mut state.chunks, order) {
+            Some(block) => block,
+            None => {
+                let base = self.allocate_chunk()? as usize;
+                let mut free = vec![BTreeSet::new(); self.max_order + 1];
+                free[self.max_order].insert(0);
+                state.chunks.insert(base, Chunk { free });
+                take_block(&mut state.chunks, order).unwrap()
+            }
+        };
+        state.in_use += block_size;
+        state.requested += size;
+        Ok(PoolBlock {
+            pool: self,
+            ptr: (base + offset) as *mut u8,
+            size,
+            kind: BlockKind::Buddy {
+                chunk: base,
+                offset,
+                order,
+            },
+        })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.state();
+        let mut stats = PoolStats {
+            chunks: state.chunks.len(),
+            reserved: state.chunks.len() * self.chunk_size,
+            in_use: state.in_use,
+            requested: state.requested,
+            dedicated: state.dedicated,
+            ..PoolStats::default()
+        };
+        for chunk in state.chunks.values() {
+            for (order, offsets) in chunk.free.iter().enumerate() {
+                let size = MIN_BLOCK_SIZE << order;
+                stats.free += offsets.len() * size;
+                if !offsets.is_empty() {
+                    stats.largest_free_block = stats.largest_free_block.max(size);
+                }
+            }
+        }
+        stats
+    }
+
+    pub fn trim(&self) {
+        let max_order = self.max_order;
+        let mut state = self.state();
+        let empty: Vec<usize> = state.chunks.iter()
+            .filter(|&(_, chunk)| chunk.free[max_order].contains(&0))
+            .map(|(base, _)| *base)
+            .collect();
+        for base in empty {
+            state.chunks.remove(&base);
+            unsafe {
+                hsa_memory_free(base as *mut c_void);
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn allocate_chunk(&self) -> Result<*mut c_void, Error> {
+        let mut ptr: *mut c_void = null_mut();
+        check(unsafe { hsa_memory_allocate(self.region, self.chunk_size, &mut ptr) }, ())?;
+        Ok(ptr)
+    }
+
+    fn allocate_dedicated(&self, size: usize) -> Result<PoolBlock<'_, 'rt>, Error> {
+        if size > self.alloc_max_size {
+            return Err(Error::from(ErrorStatus::InvalidAllocation)
+                .with_context("allocation exceeds the region's alloc_max_size"));
+        }
+        let mut ptr: *mut c_void = null_mut();
+        check(unsafe { hsa_memory_allocate(self.region, size, &mut ptr) }, ())?;
+        let mut state = self.state();
+        state.dedicated += size;
+        state.requested += size;
+        Ok(PoolBlock {
+            pool: self,
+            ptr: ptr as *mut u8,
+            size,
+            kind: BlockKind::Dedicated,
+        })
+    }
+
+    fn release(&self, block: &PoolBlock) {
+        let mut state = self.state();
+        state.requested -= block.size;
+        match block.kind {
+            BlockKind::Buddy { chunk, offset, order } => {
+                state.in_use -= MIN_BLOCK_SIZE << order;
+                let max_order = self.max_order;
+                let chunk = state.chunks.get_mut(&chunk).unwrap();
+                let (mut offset, mut order) = (offset, order);
+                while order < max_order &&
+                    chunk.free[order].remove(&(offset ^ (MIN_BLOCK_SIZE << order)))
+                {
+                    offset &= !(MIN_BLOCK_SIZE << order);
+                    order += 1;
+                }
+                chunk.free[order].insert(offset);
+            }
+            BlockKind::Dedicated => {
+                state.dedicated -= block.size;
+                unsafe {
+                    hsa_memory_free(block.ptr as *mut c_void);
+                }
+            }
+        }
+    }
+}
+
+fn take_block(chunks: &mut BTreeMap<usize, Chunk>, order: usize) -> Option<(usize, usize)> {
+    for (base, chunk) in chunks.iter_mut() {
+        let found = (order..chunk.free.len()).find(|&o| !chunk.free[o].is_empty());
+        if let Some(mut o) = found {
+            let offset = *chunk.free[o].iter().next().unwrap();
+            chunk.free[o].remove(&offset);
+            while o > order {
+                o -= 1;
+                chunk.free[o].insert(offset + (MIN_BLOCK_SIZE << o));
+            }
+            return Some((*base, offset));
+        }
+    }
+    None
+}
+
+impl<'rt> Drop for RegionPool<'rt> {
+    fn drop(&mut self) {
+        let state = self.state();
+        for base in state.chunks.keys() {
+            unsafe {
+                hsa_memory_free(*base as *mut c_void);
+            }
+        }
+    }
+}
+
+enum BlockKind {
+    Buddy {
+        chunk: usize,
+        offset: usize,
+        order: usize,
+    },
+    Dedicated,
+}
+
+pub struct PoolBlock<'p, 'rt: 'p> {
+    pool: &'p RegionPool<'rt>,
+    ptr: *mut u8,
+    size: usize,
+    kind: BlockKind,
+}
+
+impl<'p, 'rt> PoolBlock<'p, 'rt> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr
+    }
+}
+
+impl<'p, 'rt> Drop for PoolBlock<'p, 'rt> {
+    fn drop(&mut self) {
+        self.pool.release(self);
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use super::{RegionPool, MIN_BLOCK_SIZE};
+
+    fn fine_grained_region() -> Region {
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        agent.fine_grained_global_regions().unwrap()[0]
+    }
+
+    #[test]
+    fn blocks_are_carved_from_chunks_and_coalesce() {
+        let runtime = ::init().unwrap();
+        let pool = RegionPool::with_chunk_size(&runtime, fine_grained_region(), 1 << 16).unwrap();
+        {
+            let blocks: Vec<_> = (0..100)
+                .map(|i| pool.allocate(1 + i % 200, 16).unwrap())
+                .collect();
+            let stats = pool.stats();
+            assert_eq!(stats.chunks, 1);
+            assert_eq!(stats.requested, (0..100).map(|i| 1 + i % 200).sum::<usize>());
+            assert_eq!(stats.in_use + stats.free, stats.reserved);
+            for block in &blocks {
+                assert_eq!(block.as_ptr() as usize % 16, 0);
+            }
+            let large = pool.allocate(1 << 20, 16).unwrap();
+            assert_eq!(pool.stats().dedicated, 1 << 20);
+            drop(large);
+        }
+        let stats = pool.stats();
+        assert_eq!((stats.in_use, stats.requested, stats.dedicated), (0, 0, 0));
+        assert_eq!(stats.largest_free_block, 1 << 16);
+        assert_eq!(stats.fragmentation(), 0.0);
+        pool.trim();
+        assert_eq!(pool.stats().chunks, 0);
+        let block = pool.allocate(1, 1).unwrap();
+        assert_eq!(block.len(), 1);
+        assert_eq!(pool.stats().in_use, MIN_BLOCK_SIZE);
+    }
+}
@@ -1,46 +1,178 @@
-use std::u32;
 use std::os::raw::c_void;
-use std::mem::{size_of, zeroed};
-use std::ptr::{copy_nonoverlapping, null};
-use std::intrinsics::atomic_store_rel;
+use std::future::Future;
+use std::mem::{size_of, zeroed, ManuallyDrop};
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::ptr::{self, copy_nonoverlapping};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::task::{Context, Poll};
+use std::thread;
 
 use native::*;
-use super::{check, ErrorStatus};
+use super::{bitflags, check, Error, ErrorStatus, Flags};
 use signal::*;
+use runtime::Runtime;
+use waiter::SignalFuture;
 
-pub struct Queue {
+type ErrorCallback = Box<dyn Fn(&Error, &Queue) + Send + Sync>;
+
+struct Failure {
+    error: Option<Error>,
+    pending: Vec<SignalHandle>,
+    failed: Vec<SignalHandle>,
+}
+
+pub(crate) struct QueueState {
+    agent: Option<Agent>,
+    callback: Option<ErrorCallback>,
+    fail_pending: AtomicBool,
+    inactive: AtomicBool,
+    failure: Mutex<Failure>,
+    // held across a whole submission on Single queues, which allow one producer at a time
+    producer: Mutex<()>,
+}
+
+impl QueueState {
+    fn new(agent: Option<Agent>, callback: Option<ErrorCallback>) -> Arc<QueueState> {
+        Arc::new(QueueState {
+            agent,
+            callback,
+            fail_pending: AtomicBool::new(false),
+            inactive: AtomicBool::new(false),
+            producer: Mutex::new(()),
+            failure: Mutex::new(Failure {
+                error: None,
+                pending: Vec::new(),
+                failed: Vec::new(),
+            }),
+        })
+    }
+
+    fn failure(&self) -> MutexGuard<'_, Failure> {
+        self.failure.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn agent(&self) -> Option<Agent> {
+        self.agent
+    }
+
+    pub(crate) fn track(&self, signal: SignalHandle) {
+        self.failure().pending.push(signal);
+    }
+
+    pub(crate) fn untrack(&self, signal: SignalHandle) {
+        let mut failure = self.failure();
+        failure.pending.retain(|s| *s != signal);
+        failure.failed.retain(|s| *s != signal);
+    }
+
+    // a queue that has reported an error or was inactivated no longer consumes packets
+    pub(crate) fn usable(&self) -> Result<(), Error> {
+        if let Some(ref e) = self.failure().error {
+            return Err(e.clone());
+        }
+        if self.inactive.load(Ordering::Acquire) {
+            return Err(Error::from(ErrorStatus::InvalidQueue).with_context("queue is inactive"));
+        }
+        Ok(())
+    }
+
+    // a recycled signal starts over without the failure of its previous packet
+    pub(crate) fn reset(&self, signal: SignalHandle) {
+        let mut failure = self.failure();
+        failure.failed.retain(|s| *s != signal);
+        if !failure.pending.contains(&signal) {
+            failure.pending.push(signal);
+        }
+    }
+
+    pub(crate) fn result(&self, signal: SignalHandle) -> Result<(), Error> {
+        let failure = self.failure();
+        match failure.error {
+            Some(ref e) if failure.failed.contains(&signal) => Err(e.clone()),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct Queue<'rt> {
     handle: *const QueueHandle,
+    state: Arc<QueueState>,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
-impl Queue {
-    pub fn new(agent: Agent, size: u32, typ: QueueType) -> Result<Queue, ErrorStatus> {
+// the handle is only read, the ring indices are the runtime's atomics and submissions to a
+// Single queue are serialized, so submitters on several threads never share a slot
+unsafe impl<'rt> Send for Queue<'rt> {}
+unsafe impl<'rt> Sync for Queue<'rt> {}
+
+impl<'rt> Queue<'rt> {
+    pub fn new(
+        runtime: &'rt Runtime,
+        agent: Agent,
+        size: u32,
+        typ: QueueType,
+    ) -> Result<Queue<'rt>, Error> {
+        Queue::create(runtime, agent, size, typ, None)
+    }
+
+    pub fn with_error_callback<F>(
+        runtime: &'rt Runtime,
+        agent: Agent,
+        size: u32,
+        typ: QueueType,
+        callback: F,
+    ) -> Result<Queue<'rt>, Error>
+    where
+        F: Fn(&Error, &Queue) + Send + Sync + 'static,
+    {
+        Queue::create(runtime, agent, size, typ, Some(Box::new(callback)))
+    }
+
+    fn create(
+        _runtime: &'rt Runtime,
+        agent: Agent,
+        size: u32,
+        typ: QueueType,
+        callback: Option<ErrorCallback>,
+    ) -> Result<Queue<'rt>, Error> {
+        let state = QueueState::new(Some(agent), callback);
         unsafe {
-            let handle: *const QueueHandle = zeroed();
+            let mut handle: *const QueueHandle = zeroed();
             check(
                 hsa_queue_create(
                     agent,
                     size,
                     typ,
-                    null(),
-                    null(),
+                    Some(queue_error_callback),
+                    Arc::as_ptr(&state) as *mut c_void,
                     u32::MAX,
                     u32::MAX,
-                    &handle,
+                    &mut handle,
                 ),
                 (),
-            ).map(|_| Queue { handle: handle })
+            ).map(|_| {
+                Queue {
+                    handle,
+                    state,
+                    runtime: PhantomData,
+                }
+            })
         }
     }
 
     pub fn new_soft(
+        _runtime: &'rt Runtime,
         region: Region,
         size: u32,
         typ: QueueType,
         features: u32,
         doorbell_signal: SignalHandle,
-    ) -> Result<Queue, ErrorStatus> {
+    ) -> Result<Queue<'rt>, Error> {
         unsafe {
-            let handle: *const QueueHandle = zeroed();
+            let mut handle: *const QueueHandle = zeroed();
             check(
                 hsa_soft_queue_create(
                     region,
@@ -48,15 +180,23 @@ impl Queue {
                     typ,
                     features,
                     doorbell_signal,
-                    &handle
+                    &mut handle
                 ),
                 (),
-            ).map(|_| Queue { handle: handle })
+            ).map(|_| {
+                Queue {
+                    handle,
+                    state: QueueState::new(None, None),
+                    runtime: PhantomData,
+                }
+            })
         }
     }
 
-    pub fn inactivate(&self) -> Result<(), ErrorStatus> {
-        unsafe { check(hsa_queue_inactivate(self.handle), ()) }
+    pub fn inactivate(&self) -> Result<(), Error> {
+        unsafe { check(hsa_queue_inactivate(self.handle), ())? };
+        self.state.inactive.store(true, Ordering::Release);
+        Ok(())
     }
 
     pub fn load_read_index_scacquire(&self) -> u64 {
@@ -160,24 +300,222 @@ impl Queue {
     pub fn doorbell_signal(&self) -> SignalHandle {
         unsafe { (*self.handle).doorbell_signal }
     }
+    pub fn features(&self) -> Flags<QueueFeature> {
+        bitflags(unsafe { (*self.handle).features })
+    }
 
-    pub fn copy_kernel_dispatch_packet(&self, packet: &KernelDispatchPacket, index: u64) {
-        let src = packet as *const KernelDispatchPacket;
+    pub fn error(&self) -> Option<Error> {
+        self.state.failure().error.clone()
+    }
+
+    pub fn fail_pending_on_error(&self, enable: bool) {
+        self.state.fail_pending.store(enable, Ordering::Release);
+    }
+
+    pub fn queue_type(&self) -> QueueType {
+        match unsafe { (*self.handle).typ } {
+            0 => QueueType::Multi,
+            _ => QueueType::Single,
+        }
+    }
+
+    pub fn submit<P: Packet>(&self, packet: &P) -> Result<u64, Error> {
+        if let Some(feature) = P::required_feature() {
+            if !self.features().contains(&feature) {
+                return Err(ErrorStatus::InvalidPacketFormat.into());
+            }
+        }
+        let _producer = match self.queue_type() {
+            QueueType::Single => {
+                Some(self.state.producer.lock().unwrap_or_else(|e| e.into_inner()))
+            }
+            QueueType::Multi => None,
+        };
+        let index = self.reserve()?;
+        self.copy_packet(packet, index);
+        self.doorbell_signal().store_screlease(index as SignalValue);
+        Ok(index)
+    }
+
+    pub fn dispatch<P: Packet>(&self, mut packet: P) -> Result<Completion<'rt>, Error> {
+        if packet.completion_signal().handle != 0 {
+            return Err(ErrorStatus::InvalidArgument.into());
+        }
+        let signal = Signal::create(1, &[])?;
+        packet.set_completion_signal(signal.handle());
+        self.state.track(signal.handle());
+        match self.submit(&packet) {
+            Ok(index) => Ok(Completion {
+                signal: ManuallyDrop::new(signal),
+                index,
+                state: self.state.clone(),
+            }),
+            Err(e) => {
+                self.state.untrack(signal.handle());
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn state(&self) -> &QueueState {
+        &self.state
+    }
+
+    fn reserve(&self) -> Result<u64, Error> {
+        let size = self.size() as u64;
+        let multi = self.queue_type() == QueueType::Multi;
+        self.state.usable()?;
+        loop {
+            let index = self.load_write_index_scacquire();
+            if index - self.load_read_index_scacquire() >= size {
+                self.state.usable()?;
+                thread::yield_now();
+                continue;
+            }
+            if !multi {
+                self.store_write_index_relaxed(index + 1);
+                return Ok(index);
+            }
+            if self.cas_write_index_scacq_screl(index, index + 1) == index {
+                return Ok(index);
+            }
+        }
+    }
+
+    pub fn copy_packet<P: Packet>(&self, packet: &P, index: u64) {
         let rounded_index = (index & ((self.size() - 1) as u64)) as isize;
-        let size = size_of::<KernelDispatchPacket>();
         unsafe {
-            let dst = (self.base_address() as *mut KernelDispatchPacket).offset(rounded_index);
-            let src_u8 = src as *const u8;
-            let dst_u8 = dst as *mut u8;
-            let src_header = src as *const u16;
-            let dst_header = dst as *mut u16;
-            copy_nonoverlapping(src_u8.offset(2), dst_u8.offset(2), size - 2);
-            atomic_store_rel(dst_header, *src_header);
+            let dst = (self.base_address() as *mut P).offset(rounded_index);
+            store_packet(dst, packet);
         }
     }
+
+    pub fn copy_kernel_dispatch_packet(&self, packet: &KernelDispatchPacket, index: u64) {
+        self.copy_packet(packet, index)
+    }
+}
+
+// how long a dropped completion waits between checks of the queue state
+const DROP_POLL_HINT: u64 = 1_000_000;
+
+pub struct Completion<'rt> {
+    signal: ManuallyDrop<Signal<'rt>>,
+    index: u64,
+    state: Arc<QueueState>,
 }
 
-impl Drop for Queue {
+impl<'rt> Completion<'rt> {
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn signal(&self) -> &Signal<'rt> {
+        &self.signal
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.signal.load_scacquire() == 0
+    }
+
+    pub fn wait_async(&self) -> CompletionFuture<'_> {
+        CompletionFuture::new(
+            self.signal.wait_async(SignalCondition::Eq, 0),
+            &self.state,
+            self.signal.handle(),
+        )
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        while self.signal.wait_scacquire(SignalCondition::Eq, 0, u64::MAX, WaitState::Blocked) != 0 {
+        }
+        self.state.result(self.signal.handle())
+    }
+}
+
+// dropping a Completion blocks until its packet retires
+impl<'rt> Drop for Completion<'rt> {
+    fn drop(&mut self) {
+        // the packet processor still writes the signal when the packet retires, so it can only
+        // be destroyed once it reaches 0; a failed or inactive queue never retires the packet
+        // and the signal is leaked instead
+        let handle = self.signal.handle();
+        let mut retired = true;
+        while self.signal.wait_scacquire(SignalCondition::Eq, 0, DROP_POLL_HINT, WaitState::Blocked) != 0 {
+            if self.state.usable().is_err() {
+                retired = false;
+                break;
+            }
+        }
+        self.state.untrack(handle);
+        if retired {
+            unsafe { ManuallyDrop::drop(&mut self.signal) };
+        }
+    }
+}
+
+pub struct CompletionFuture<'a> {
+    future: SignalFuture<'a>,
+    state: &'a QueueState,
+    signal: SignalHandle,
+}
+
+impl<'a> CompletionFuture<'a> {
+    pub(crate) fn new(
+        future: SignalFuture<'a>,
+        state: &'a QueueState,
+        signal: SignalHandle,
+    ) -> CompletionFuture<'a> {
+        CompletionFuture {
+            future,
+            state,
+            signal,
+        }
+    }
+}
+
+impl<'a> Future for CompletionFuture<'a> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.future).poll(cx) {
+            Poll::Ready(Ok(_)) => Poll::Ready(this.state.result(this.signal)),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+extern "C" fn queue_error_callback(status: HSAStatus, queue: *const QueueHandle, data: *mut c_void) {
+    let _ = catch_unwind(AssertUnwindSafe(|| unsafe {
+        let state = ManuallyDrop::new(Arc::from_raw(data as *const QueueState));
+        let error = Error::from_status(status, "").with_context("queue error callback");
+        {
+            let mut failure = state.failure();
+            failure.error = Some(error.clone());
+            if state.fail_pending.load(Ordering::Acquire) {
+                let pending = failure.pending.clone();
+                for signal in pending {
+                    if signal.load_scacquire() != 0 {
+                        failure.failed.push(signal);
+                        signal.store_screlease(0);
+                    }
+                }
+            }
+        }
+        if let Some(ref callback) = state.callback {
+            let mut queue = ManuallyDrop::new(Queue {
+                handle: queue,
+                state: Arc::clone(&state),
+                runtime: PhantomData,
+            });
+            callback(&error, &queue);
+            ptr::drop_in_place(&mut queue.state);
+        }
+    }));
+}
+
+impl<'rt> Drop for Queue<'rt> {
     fn drop(&mut self) {
         unsafe {
             hsa_queue_destroy(self.handle);
@@ -186,6 +524,7 @@ impl Drop for Queue {
 }
 
 impl KernelDispatchPacket {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<T0: Sized, T1: SignalBase>(
         packet_type: PacketType,
         fences: &[(FenceScope, PacketHeader)],
@@ -197,9 +536,9 @@ impl KernelDispatchPacket {
         kernel_object: u64,
         kernarg_address: *const T0,
         completion_signal: T1,
-    ) -> Result<KernelDispatchPacket, ()> {
+    ) -> Result<KernelDispatchPacket, Error> {
         if dims == 0 || dims as usize != workgroup_size.len() || dims as usize != grid_size.len() {
-            return Err(());
+            return Err(ErrorStatus::InvalidArgument.into());
         }
         let mut packet: KernelDispatchPacket = unsafe { zeroed() };
         packet.header = {
@@ -232,3 +571,281 @@ impl KernelDispatchPacket {
         Ok(packet)
     }
 }
+
+/// # Safety
+///
+/// Implementors must be 64-byte AQL packets whose first field is the 16-bit header.
+pub unsafe trait Packet: Sized {
+    fn header(&self) -> u16;
+
+    fn completion_signal(&self) -> SignalHandle;
+
+    fn set_completion_signal(&mut self, signal: SignalHandle);
+
+    fn required_feature() -> Option<QueueFeature> {
+        None
+    }
+}
+
+macro_rules! impl_packet {
+    ($t:ty, $feature:expr) => {
+        unsafe impl Packet for $t {
+            fn header(&self) -> u16 {
+                self.header
+            }
+
+            fn completion_signal(&self) -> SignalHandle {
+                self.completion_signal
+            }
+
+            fn set_completion_signal(&mut self, signal: SignalHandle) {
+                self.completion_signal = signal;
+            }
+
+            fn required_feature() -> Option<QueueFeature> {
+                $feature
+            }
+        }
+    }
+}
+
+impl_packet!(KernelDispatchPacket, Some(QueueFeature::KernelDispatch));
+impl_packet!(AgentDispatchPacket, Some(QueueFeature::AgentDispatch));
+impl_packet!(BarrierAndPacket, None);
+impl_packet!(BarrierOrPacket, None);
+
+unsafe fn store_packet<P: Packet>(dst: *mut P, packet: &P) {
+    store_packet_body(dst, packet);
+    publish_packet_header(dst, packet.header());
+}
+
+unsafe fn store_packet_body<P: Packet>(dst: *mut P, packet: &P) {
+    let size = size_of::<P>();
+    let src_u8 = packet as *const P as *const u8;
+    let dst_u8 = dst as *mut u8;
+    copy_nonoverlapping(src_u8.offset(2), dst_u8.offset(2), size - 2);
+}
+
+unsafe fn publish_packet_header<P: Packet>(dst: *mut P, header: u16) {
+    (*(dst as *const AtomicU16)).store(header, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{size_of, zeroed};
+
+    use native::*;
+    use super::{publish_packet_header, store_packet, store_packet_body, Packet};
+
+    #[repr(C, align(64))]
+    struct Slot([u8; 64]);
+
+    fn invalid_slot() -> Slot {
+        let mut slot = Slot([0xAA; 64]);
+        let invalid = (PacketType::Invalid as u16) << (PacketHeader::Type as u16);
+        slot.0[0] = invalid as u8;
+        slot.0[1] = (invalid >> 8) as u8;
+        slot
+    }
+
+    fn bytes<P: Packet>(packet: &P) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(packet as *const P as *const u8, size_of::<P>()) }
+    }
+
+    fn check_header_written_last<P: Packet>(packet: P) {
+        assert_eq!(size_of::<P>(), 64);
+        let mut slot = invalid_slot();
+        let dst = &mut slot as *mut Slot as *mut P;
+        let expected = bytes(&packet).to_vec();
+
+        unsafe { store_packet_body(dst, &packet) };
+        assert_eq!(&slot.0[..2], &invalid_slot().0[..2]);
+        assert_eq!(&slot.0[2..], &expected[2..]);
+
+        unsafe { publish_packet_header(dst, packet.header()) };
+        assert_eq!(&slot.0[..], &expected[..]);
+
+        let mut slot = invalid_slot();
+        unsafe { store_packet(&mut slot as *mut Slot as *mut P, &packet) };
+        assert_eq!(&slot.0[..], &expected[..]);
+    }
+
+    fn header(typ: PacketType) -> u16 {
+        ((typ as u16) << (PacketHeader::Type as u16)) |
+            ((FenceScope::System as u16) << (PacketHeader::ScacquireFenceScope as u16))
+    }
+
+    #[test]
+    fn kernel_dispatch_header_is_written_last() {
+        let mut packet: KernelDispatchPacket = unsafe { zeroed() };
+        packet.header = header(PacketType::KernelDispatch);
+        packet.setup = 1;
+        packet.workgroup_size_x = 256;
+        packet.grid_size_x = 1024;
+        packet.kernel_object = 0x1234_5678;
+        packet.completion_signal.handle = 42;
+        check_header_written_last(packet);
+    }
+
+    #[test]
+    fn agent_dispatch_header_is_written_last() {
+        let mut packet: AgentDispatchPacket = unsafe { zeroed() };
+        packet.header = header(PacketType::AgentDispatch);
+        packet.typ = 0x8000;
+        packet.args = [1, 2, 3, 4];
+        packet.completion_signal.handle = 42;
+        check_header_written_last(packet);
+    }
+
+    #[test]
+    fn barrier_header_is_written_last() {
+        let mut packet: BarrierAndPacket = unsafe { zeroed() };
+        packet.header = header(PacketType::BarrierAnd);
+        packet.dep_signal[0].handle = 7;
+        packet.completion_signal.handle = 42;
+        check_header_written_last(packet);
+
+        let mut packet: BarrierOrPacket = unsafe { zeroed() };
+        packet.header = header(PacketType::BarrierOr);
+        packet.dep_signal[4].handle = 9;
+        packet.completion_signal.handle = 42;
+        check_header_written_last(packet);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn queue_error_fails_pending_completions() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use super::Queue;
+        use ErrorStatus;
+
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let calls = Arc::new(AtomicU32::new(0));
+        let c = calls.clone();
+        let queue = Queue::with_error_callback(&runtime, agent, 64, QueueType::Single, move |e, _| {
+            assert_eq!(*e, ErrorStatus::Exception);
+            c.fetch_add(1, Ordering::SeqCst);
+        }).unwrap();
+        queue.fail_pending_on_error(true);
+
+        let packet = KernelDispatchPacket::builder(0xdead)
+            .grid_size(&[1])
+            .workgroup_size(&[1])
+            .build()
+            .unwrap();
+        let completion = queue.dispatch(packet).unwrap();
+        let err = completion.wait().unwrap_err();
+        assert_eq!(err, ErrorStatus::Exception);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let error = queue.error().unwrap();
+        assert_eq!(error.kind(), Some(ErrorStatus::Exception));
+        assert!(error.to_string().starts_with("queue error callback: "));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn submit_waits_for_read_index() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+        use std::time::Duration;
+        use super::Queue;
+        use signal::{Signal, SignalBase};
+
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let gate = Signal::create(1, &[]).unwrap();
+
+        let blocked = BarrierAndPacket::builder().dep_signal(&gate).build().unwrap();
+        assert_eq!(queue.submit(&blocked).unwrap(), 0);
+        let empty = BarrierAndPacket::builder().build().unwrap();
+        for _ in 1..64 {
+            queue.submit(&empty).unwrap();
+        }
+
+        let submitted = AtomicBool::new(false);
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let index = queue.submit(&empty).unwrap();
+                submitted.store(true, Ordering::SeqCst);
+                index
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!submitted.load(Ordering::SeqCst));
+            gate.store_screlease(0);
+            assert_eq!(waiter.join().unwrap(), 64);
+        });
+        let last = queue.dispatch(empty).unwrap();
+        last.wait().unwrap();
+        assert_eq!(queue.load_read_index_scacquire(), 66);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn submit_fails_on_unusable_queue() {
+        use std::thread;
+        use super::Queue;
+        use signal::{Signal, SignalBase};
+        use ErrorStatus;
+
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        let empty = BarrierAndPacket::builder().build().unwrap();
+
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        queue.inactivate().unwrap();
+        assert_eq!(queue.submit(&empty).unwrap_err(), ErrorStatus::InvalidQueue);
+
+        // the completion of a packet the failed queue never retires is dropped without waiting
+        let queue = Queue::new(&runtime, agent, 64, QueueType::Single).unwrap();
+        let packet = KernelDispatchPacket::builder(0xdead)
+            .grid_size(&[1])
+            .workgroup_size(&[1])
+            .build()
+            .unwrap();
+        let gate = Signal::create(1, &[]).unwrap();
+        queue.submit(&BarrierAndPacket::builder().dep_signal(&gate).build().unwrap()).unwrap();
+        let failing = queue.dispatch(packet).unwrap();
+        let stuck = queue.dispatch(BarrierAndPacket::builder().build().unwrap()).unwrap();
+        gate.store_screlease(0);
+        while queue.error().is_none() {
+            thread::yield_now();
+        }
+        drop(failing);
+        drop(stuck);
+        assert_eq!(queue.submit(&empty).unwrap_err(), ErrorStatus::Exception);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn submitters_on_several_threads_get_distinct_slots() {
+        use std::thread;
+        use super::Queue;
+
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        for &typ in &[QueueType::Single, QueueType::Multi] {
+            let queue = Queue::new(&runtime, agent, 64, typ).unwrap();
+            assert_eq!(queue.queue_type(), typ);
+            let mut indices = thread::scope(|s| {
+                let workers: Vec<_> = (0..4).map(|_| {
+                    let queue = &queue;
+                    s.spawn(move || {
+                        (0..100).map(|_| {
+                            let packet = BarrierAndPacket::builder().build().unwrap();
+                            let completion = queue.dispatch(packet).unwrap();
+                            completion.wait().unwrap();
+                            completion.index()
+                        }).collect::<Vec<_>>()
+                    })
+                }).collect();
+                workers.into_iter().flat_map(|w| w.join().unwrap()).collect::<Vec<_>>()
+            });
+            indices.sort();
+            assert_eq!(indices, (0..400).collect::<Vec<u64>>());
+            assert_eq!(queue.load_write_index_scacquire(), 400);
+        }
+    }
+}
@@ -1,140 +1,350 @@
-use std::u32;
 use std::os::raw::c_void;
-use std::ptr::{null, null_mut};
+use std::ptr::null_mut;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::slice;
 
 use native::*;
-use super::{bitflags, check, get_info, ErrorStatus, Flags};
+use runtime::Runtime;
+use kernarg::{DeviceConstPtr, DevicePtr};
+use access::{self, AccessSet};
+use super::{bitflags, check, get_info, Error, ErrorStatus, Flags};
 
 impl Region {
-    pub fn segment(&self) -> Result<RegionSegment, ErrorStatus> {
+    pub fn segment(&self) -> Result<RegionSegment, Error> {
         get_info(|x| self.get_info(RegionInfo::Segment, x))
     }
 
-    pub fn global_flags(&self) -> Result<Flags<RegionGlobalFlag>, ErrorStatus> {
+    pub fn global_flags(&self) -> Result<Flags<RegionGlobalFlag>, Error> {
         get_info(|x| self.get_info(RegionInfo::GlobalFlags, x))
-            .map(|flags: RegionGlobalFlag| bitflags(flags as u32))
+            .map(|flags: u32| bitflags(flags))
     }
 
-    pub fn size(&self) -> Result<usize, ErrorStatus> {
+    pub fn size(&self) -> Result<usize, Error> {
         get_info(|x| self.get_info(RegionInfo::Size, x))
     }
 
-    pub fn alloc_max_size(&self) -> Result<usize, ErrorStatus> {
+    pub fn alloc_max_size(&self) -> Result<usize, Error> {
         get_info(|x| self.get_info(RegionInfo::AllocMaxSize, x))
     }
 
-    pub fn alloc_max_private_workgroup_size(&self) -> Result<u32, ErrorStatus> {
+    pub fn alloc_max_private_workgroup_size(&self) -> Result<u32, Error> {
         get_info(|x| {
             self.get_info(RegionInfo::AllocMaxPrivateWorkgroupSize, x)
         })
     }
 
-    pub fn runtime_alloc_allowed(&self) -> Result<bool, ErrorStatus> {
+    pub fn runtime_alloc_allowed(&self) -> Result<bool, Error> {
         get_info(|x| self.get_info(RegionInfo::RuntimeAllocAllowed, x))
     }
 
-    pub fn runtime_alloc_granule(&self) -> Result<usize, ErrorStatus> {
+    pub fn runtime_alloc_granule(&self) -> Result<usize, Error> {
         get_info(|x| self.get_info(RegionInfo::RuntimeAllocGranule, x))
     }
 
-    pub fn runtime_alloc_alignment(&self) -> Result<usize, ErrorStatus> {
+    pub fn runtime_alloc_alignment(&self) -> Result<usize, Error> {
         get_info(|x| self.get_info(RegionInfo::RuntimeAllocAlignment, x))
     }
 
+    pub fn host_accessible(&self) -> Result<bool, Error> {
+        match get_info(|x| self.get_info(RegionInfo::HostAccessible, x)) {
+            Err(ref e) if *e == ErrorStatus::InvalidArgument => {
+                let flags = self.global_flags()?;
+                Ok(flags.contains(&RegionGlobalFlag::FineGrained) ||
+                    flags.contains(&RegionGlobalFlag::KernArg))
+            }
+            r => r,
+        }
+    }
+
     fn get_info(&self, attr: RegionInfo, v: *mut c_void) -> HSAStatus {
         unsafe { hsa_region_get_info(*self, attr, v) }
     }
 }
 
-pub enum Memory<T> {
-    RegionMemory(*mut T),
-    Registered(*mut T, usize),
-    None,
+enum MemoryKind {
+    Region,
+    Registered(usize),
+}
+
+pub struct Memory<'rt, T> {
+    ptr: *mut T,
+    size: usize,
+    kind: MemoryKind,
+    access: AccessSet,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
-impl Memory<u8> {
-    pub fn allocate(region: Region, size: usize) -> Result<Memory<u8>, ErrorStatus> {
-        let ptr: *mut c_void = null_mut();
+impl<'rt> Memory<'rt, u8> {
+    pub fn allocate(
+        _runtime: &'rt Runtime,
+        region: Region,
+        size: usize,
+    ) -> Result<Memory<'rt, u8>, Error> {
+        let mut ptr: *mut c_void = null_mut();
         unsafe {
-            check(hsa_memory_allocate(region, size, &ptr), ()).map(
+            check(hsa_memory_allocate(region, size, &mut ptr), ()).map(
                 |_| {
-                    Memory::RegionMemory(ptr as *mut u8)
+                    Memory {
+                        ptr: ptr as *mut u8,
+                        size,
+                        kind: MemoryKind::Region,
+                        access: AccessSet::new(ptr, size, access::fine_grained(region)),
+                        runtime: PhantomData,
+                    }
                 },
             )
         }
     }
 
-    pub fn register(ptr: *mut u8, size: usize) -> Result<Memory<u8>, ErrorStatus> {
+    pub fn register(
+        _runtime: &'rt Runtime,
+        ptr: *mut u8,
+        size: usize,
+    ) -> Result<Memory<'rt, u8>, Error> {
         unsafe {
-            check(hsa_memory_register(ptr as *mut c_void, size), ())
-                .map(|_| Memory::Registered(ptr, size))
+            check(hsa_memory_register(ptr as *mut c_void, size), ()).map(|_| {
+                Memory {
+                    ptr,
+                    size,
+                    kind: MemoryKind::Registered(size),
+                    access: AccessSet::new(ptr as *const c_void, size, true),
+                    runtime: PhantomData,
+                }
+            })
         }
     }
 }
 
-impl<T> Memory<T> {
-    pub fn new(region: Region) -> Result<Memory<T>, ErrorStatus> {
+impl<'rt, T> Memory<'rt, T> {
+    pub fn new(_runtime: &'rt Runtime, region: Region) -> Result<Memory<'rt, T>, Error> {
         use std::mem::size_of;
-        let ptr: *mut c_void = null_mut();
+        let mut ptr: *mut c_void = null_mut();
         let size = size_of::<T>();
         unsafe {
-            check(hsa_memory_allocate(region, size, &ptr), ()).map(
+            check(hsa_memory_allocate(region, size, &mut ptr), ()).map(
                 |_| {
-                    Memory::RegionMemory(ptr as *mut T)
+                    Memory {
+                        ptr: ptr as *mut T,
+                        size,
+                        kind: MemoryKind::Region,
+                        access: AccessSet::new(ptr, size, access::fine_grained(region)),
+                        runtime: PhantomData,
+                    }
                 },
             )
         }
     }
 
     pub fn as_ptr(&self) -> *const T {
-        match *self {
-            Memory::RegionMemory(x) |
-            Memory::Registered(x, _) => x,
-            Memory::None => null(),
-        }
+        self.ptr
     }
 
     pub fn as_mut_ptr(&self) -> *mut T {
-        match *self {
-            Memory::RegionMemory(x) |
-            Memory::Registered(x, _) => x,
-            Memory::None => null_mut(),
-        }
+        self.ptr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    pub fn assign_agent(&self, agent: Agent, access: AccessPermission) -> Result<(), ErrorStatus> {
+    pub fn assign_agent(&self, agent: Agent, access: AccessPermission) -> Result<(), Error> {
         let ptr = self.as_mut_ptr();
         if ptr.is_null() {
-            return Err(ErrorStatus::InvalidArgument);
+            return Err(ErrorStatus::InvalidArgument.into());
         }
-        check(unsafe {
-            hsa_memory_assign_agent(ptr as *mut c_void, agent, access)
-        }, ())
+        self.access.grant(&[agent], access)
+    }
+
+    pub fn access(&self) -> &AccessSet {
+        &self.access
     }
 
     pub fn copy_from(&mut self, src: &T) {
         unsafe {
             use std::ptr::copy_nonoverlapping;
-            let ptr = src as *const _ as *const T;
+            let ptr = src as *const T;
             copy_nonoverlapping(ptr, self.as_mut_ptr(), 1);
         }
     }
 }
 
-impl<T> Drop for Memory<T> {
+impl<'rt, T> Drop for Memory<'rt, T> {
     fn drop(&mut self) {
-        match *self {
-            Memory::RegionMemory(x) => unsafe {
-                hsa_memory_free(x as *mut c_void);
+        match self.kind {
+            MemoryKind::Region => unsafe {
+                hsa_memory_free(self.ptr as *mut c_void);
             },
-            Memory::Registered(x, sz) => unsafe {
-                hsa_memory_deregister(x as *mut c_void, sz);
+            MemoryKind::Registered(sz) => unsafe {
+                hsa_memory_deregister(self.ptr as *mut c_void, sz);
             },
-            _ => (),
         }
     }
 }
 
-pub unsafe fn copy<T>(src: *const T, dst: *mut T, bytes: usize) -> Result<(), ErrorStatus> {
+/// # Safety
+///
+/// `as_mut_slice` must return the same allocation every time it is called, and the memory
+/// must not move or be freed while the storage is registered with the runtime.
+pub unsafe trait HostStorage {
+    type Elem: Copy;
+
+    fn as_mut_slice(&mut self) -> &mut [Self::Elem];
+}
+
+unsafe impl<T: Copy> HostStorage for Vec<T> {
+    type Elem = T;
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+unsafe impl<T: Copy> HostStorage for Box<[T]> {
+    type Elem = T;
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+unsafe impl<T: Copy> HostStorage for &mut [T] {
+    type Elem = T;
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+// the storage is never resized while registered, so its heap buffer stays put even if
+// the RegisteredMemory itself is moved
+pub struct RegisteredMemory<'rt, S: HostStorage> {
+    storage: Option<S>,
+    ptr: *mut S::Elem,
+    len: usize,
+    access: AccessSet,
+    runtime: PhantomData<&'rt Runtime>,
+}
+
+impl<'rt, S: HostStorage> RegisteredMemory<'rt, S> {
+    pub fn new(_runtime: &'rt Runtime, mut storage: S) -> Result<RegisteredMemory<'rt, S>, Error> {
+        let (ptr, len) = {
+            let s = storage.as_mut_slice();
+            (s.as_mut_ptr(), s.len())
+        };
+        let size = len * size_of::<S::Elem>();
+        if size != 0 {
+            check(unsafe { hsa_memory_register(ptr as *mut c_void, size) }, ())?;
+        }
+        Ok(RegisteredMemory {
+            storage: Some(storage),
+            ptr,
+            len,
+            access: AccessSet::new(ptr as *const c_void, size, true),
+            runtime: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[S::Elem] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [S::Elem] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    pub fn device_const_ptr(&self) -> DeviceConstPtr<'_, S::Elem> {
+        DeviceConstPtr::new(self.ptr)
+    }
+
+    pub fn device_ptr(&mut self) -> DevicePtr<'_, S::Elem> {
+        DevicePtr::new(self.ptr)
+    }
+
+    pub fn assign_agent(&self, agent: Agent, access: AccessPermission) -> Result<(), Error> {
+        self.access.grant(&[agent], access)
+    }
+
+    pub fn access(&self) -> &AccessSet {
+        &self.access
+    }
+
+    // the storage comes back even when deregistration fails, so it is never lost
+    pub fn into_inner(mut self) -> Result<S, (Error, S)> {
+        let result = self.deregister();
+        let storage = self.storage.take().unwrap();
+        match result {
+            Ok(()) => Ok(storage),
+            Err(e) => Err((e, storage)),
+        }
+    }
+
+    fn deregister(&mut self) -> Result<(), Error> {
+        let size = self.len * size_of::<S::Elem>();
+        if size == 0 {
+            return Ok(());
+        }
+        self.len = 0;
+        check(unsafe { hsa_memory_deregister(self.ptr as *mut c_void, size) }, ())
+    }
+}
+
+impl<'rt, S: HostStorage> Drop for RegisteredMemory<'rt, S> {
+    fn drop(&mut self) {
+        let _ = self.deregister();
+    }
+}
+
+/// # Safety
+///
+/// `src` and `dst` must be valid for `bytes` bytes and must not overlap.
+pub unsafe fn copy<T>(src: *const T, dst: *mut T, bytes: usize) -> Result<(), Error> {
     check(hsa_memory_copy(dst as *mut c_void, src as *const c_void, bytes), ())
 }
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::os::raw::c_void;
+
+    use native::*;
+    use super::RegisteredMemory;
+    use ErrorStatus;
+
+    unsafe extern "C" fn failing_deregister(_: *mut c_void, _: usize) -> HSAStatus {
+        ErrorStatus::InvalidArgument as HSAStatus
+    }
+
+    #[test]
+    fn registration_keeps_host_storage_alive() {
+        let runtime = ::init().unwrap();
+        let mut registered = RegisteredMemory::new(&runtime, vec![0u32; 16]).unwrap();
+        let ptr = registered.device_ptr().as_ptr();
+        unsafe { *ptr.add(3) = 7 };
+        assert_eq!(registered.as_slice()[3], 7);
+        let v = registered.into_inner().unwrap();
+        assert_eq!((v.as_ptr(), v[3]), (ptr as *const u32, 7));
+
+        let mut array = [1.0f32; 4];
+        {
+            let mut registered = RegisteredMemory::new(&runtime, &mut array[..]).unwrap();
+            registered.as_mut_slice()[0] = 2.0;
+        }
+        assert_eq!(array[0], 2.0);
+        assert!(RegisteredMemory::new(&runtime, Vec::<u8>::new()).unwrap().is_empty());
+
+        let mut api = Api::current();
+        api.hsa_memory_deregister = failing_deregister;
+        let registered = RegisteredMemory::new(&runtime, vec![5u8; 4]).unwrap();
+        let (e, v) = with_api(&api, || registered.into_inner()).unwrap_err();
+        assert_eq!(e, ErrorStatus::InvalidArgument);
+        assert_eq!(v, vec![5u8; 4]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::mem::forget;
+use std::sync::Mutex;
+
+use native::*;
+use super::{check, Error};
+use waiter;
+#[cfg(feature = "dynamic")]
+use super::ErrorStatus;
+
+static RUNTIMES: Mutex<usize> = Mutex::new(0);
+
+pub struct Runtime {
+    _private: (),
+}
+
+impl Runtime {
+    fn new() -> Result<Runtime, Error> {
+        let mut count = RUNTIMES.lock().unwrap_or_else(|e| e.into_inner());
+        check(unsafe { hsa_init() }, ())?;
+        *count += 1;
+        Ok(Runtime { _private: () })
+    }
+
+    pub fn shutdown(self) -> Result<(), Error> {
+        forget(self);
+        release()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        let _ = release();
+    }
+}
+
+fn release() -> Result<(), Error> {
+    let mut count = RUNTIMES.lock().unwrap_or_else(|e| e.into_inner());
+    *count -= 1;
+    if *count == 0 {
+        waiter::stop();
+    }
+    check(unsafe { hsa_shut_down() }, ())
+}
+
+// the emulator takes precedence over a dynamically loaded runtime, as in native.rs
+#[cfg(not(all(feature = "dynamic", not(feature = "emulator"))))]
+pub fn init() -> Result<Runtime, Error> {
+    Runtime::new()
+}
+
+#[cfg(all(feature = "dynamic", not(feature = "emulator")))]
+pub fn init() -> Result<Runtime, Error> {
+    if !api_is_set() {
+        let mut result = Err(ErrorStatus::LibraryNotFound.into());
+        for name in DEFAULT_LIBRARY_NAMES {
+            result = load_library(name);
+            match result {
+                Err(ref e) if *e == ErrorStatus::LibraryNotFound => continue,
+                _ => break,
+            }
+        }
+        result?;
+    }
+    Runtime::new()
+}
+
+#[cfg(feature = "dynamic")]
+pub fn init_with_library<P: AsRef<::std::ffi::OsStr>>(path: P) -> Result<Runtime, Error> {
+    load_library(path)?;
+    Runtime::new()
+}
+
+#[cfg(feature = "dynamic")]
+fn load_library<P: AsRef<::std::ffi::OsStr>>(path: P) -> Result<(), Error> {
+    match ::native::load_library(path) {
+        Ok(api) => {
+            set_api(api);
+            Ok(())
+        }
+        Err(LoadError::LibraryNotFound(message)) => {
+            Err(Error::from_status(ErrorStatus::LibraryNotFound as HSAStatus, "dlopen")
+                .with_message(message))
+        }
+        Err(LoadError::SymbolNotFound(name)) => {
+            Err(Error::from_status(ErrorStatus::SymbolNotFound as HSAStatus, "dlsym")
+                .with_message(name))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dynamic", not(feature = "emulator")))]
+mod tests {
+    use native::*;
+    use super::init_with_library;
+    use ErrorStatus;
+
+    #[test]
+    fn loading_failures_are_reported() {
+        // nothing is loaded yet, so every call fails instead of panicking
+        assert_eq!(Agent::list().unwrap_err(), ErrorStatus::NotInitialized);
+
+        let e = init_with_library("/nonexistent/libhsa-runtime64.so").err().unwrap();
+        assert_eq!(e, ErrorStatus::LibraryNotFound);
+        if cfg!(target_os = "linux") {
+            let e = init_with_library("libc.so.6").err().unwrap();
+            assert_eq!(e, ErrorStatus::SymbolNotFound);
+            assert!(e.to_string().contains("hsa_init"));
+        }
+        assert!(!api_is_set());
+    }
+}
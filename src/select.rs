@@ -0,0 +1,202 @@
+use std::cmp::Reverse;
+
+use native::*;
+use super::{Error, Flags};
+
+#[derive(Clone, Debug, Default)]
+pub struct RegionSelector {
+    segment: Option<RegionSegment>,
+    flags: Flags<RegionGlobalFlag>,
+    min_size: usize,
+    runtime_alloc_allowed: bool,
+    host_accessible: Option<bool>,
+}
+
+impl RegionSelector {
+    pub fn new() -> RegionSelector {
+        RegionSelector::default()
+    }
+
+    pub fn segment(mut self, segment: RegionSegment) -> Self {
+        self.segment = Some(segment);
+        self
+    }
+
+    // may be repeated; a region must carry every requested flag
+    pub fn flag(mut self, flag: RegionGlobalFlag) -> Self {
+        self.flags.insert(flag);
+        self
+    }
+
+    pub fn min_size(mut self, size: usize) -> Self {
+        self.min_size = size;
+        self
+    }
+
+    pub fn runtime_alloc_allowed(mut self) -> Self {
+        self.runtime_alloc_allowed = true;
+        self
+    }
+
+    pub fn host_accessible(mut self, host_accessible: bool) -> Self {
+        self.host_accessible = Some(host_accessible);
+        self
+    }
+
+    // regions whose attributes cannot be queried do not match
+    pub fn matches(&self, region: Region) -> bool {
+        let check = || -> Result<bool, Error> {
+            if let Some(segment) = self.segment {
+                if region.segment()? != segment {
+                    return Ok(false);
+                }
+            }
+            if !self.flags.is_empty() && !self.flags.is_subset(&region.global_flags()?) {
+                return Ok(false);
+            }
+            if self.min_size > 0 && region.size()? < self.min_size {
+                return Ok(false);
+            }
+            if self.runtime_alloc_allowed && !region.runtime_alloc_allowed()? {
+                return Ok(false);
+            }
+            if let Some(host_accessible) = self.host_accessible {
+                if region.host_accessible()? != host_accessible {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        check().unwrap_or(false)
+    }
+
+    // best first: regions without flags beyond the requested ones, then larger regions
+    pub fn select(&self, agent: Agent) -> Result<Vec<Region>, Error> {
+        let mut regions = agent.regions()?;
+        regions.retain(|&r| self.matches(r));
+        regions.sort_by_cached_key(|r| {
+            let extra = r.global_flags().map(|f| f.difference(&self.flags).count()).unwrap_or(0);
+            (extra, Reverse(r.size().unwrap_or(0)))
+        });
+        Ok(regions)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AgentSelector {
+    device: Option<DeviceType>,
+    features: Flags<AgentFeature>,
+    isa_name: Option<String>,
+    queue_type: Option<QueueType>,
+    profile: Option<Profile>,
+}
+
+impl AgentSelector {
+    pub fn new() -> AgentSelector {
+        AgentSelector::default()
+    }
+
+    pub fn device(mut self, device: DeviceType) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn feature(mut self, feature: AgentFeature) -> Self {
+        self.features.insert(feature);
+        self
+    }
+
+    pub fn isa_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.isa_name = Some(name.into());
+        self
+    }
+
+    // every agent serves Single queues, so only Multi narrows the selection
+    pub fn queue_type(mut self, queue_type: QueueType) -> Self {
+        self.queue_type = Some(queue_type);
+        self
+    }
+
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn matches(&self, agent: Agent) -> bool {
+        let check = || -> Result<bool, Error> {
+            if let Some(device) = self.device {
+                if agent.device()? != device {
+                    return Ok(false);
+                }
+            }
+            if !self.features.is_empty() && !self.features.is_subset(&agent.feature()?) {
+                return Ok(false);
+            }
+            if let Some(ref name) = self.isa_name {
+                let mut found = false;
+                for isa in agent.isas()? {
+                    if isa.name()? == *name {
+                        found = true;
+                        break;
+                    }
+                }
+                if !found {
+                    return Ok(false);
+                }
+            }
+            if self.queue_type == Some(QueueType::Multi) && agent.queue_type()? != QueueType::Multi {
+                return Ok(false);
+            }
+            if let Some(profile) = self.profile {
+                if agent.profile()? != profile {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        check().unwrap_or(false)
+    }
+
+    // best first: kernel dispatch agents, then GPUs, otherwise in runtime order
+    pub fn select(&self) -> Result<Vec<Agent>, Error> {
+        let mut agents = Agent::list()?;
+        agents.retain(|&a| self.matches(a));
+        agents.sort_by_cached_key(|a| {
+            let dispatch = a.feature().map(|f| f.contains(&AgentFeature::KernelDispatch));
+            let gpu = a.device().map(|d| d == DeviceType::GPU);
+            (Reverse(dispatch.unwrap_or(false)), Reverse(gpu.unwrap_or(false)))
+        });
+        Ok(agents)
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use super::{AgentSelector, RegionSelector};
+
+    #[test]
+    fn selectors_filter_and_rank() {
+        let _runtime = ::init().unwrap();
+        let agents = AgentSelector::new()
+            .device(DeviceType::CPU)
+            .feature(AgentFeature::KernelDispatch)
+            .queue_type(QueueType::Single)
+            .select()
+            .unwrap();
+        assert_eq!(agents.len(), 1);
+        assert!(AgentSelector::new().isa_name("no-such-isa").select().unwrap().is_empty());
+
+        let agent = agents[0];
+        let coarse = RegionSelector::new()
+            .segment(RegionSegment::Global)
+            .host_accessible(false)
+            .select(agent)
+            .unwrap();
+        assert_eq!(coarse.len(), 1);
+        assert!(coarse[0].global_flags().unwrap().contains(&RegionGlobalFlag::CoarseGrained));
+        let all = RegionSelector::new().runtime_alloc_allowed().select(agent).unwrap();
+        assert_eq!(all[0], coarse[0]);
+        assert!(RegionSelector::new().min_size(usize::MAX).select(agent).unwrap().is_empty());
+    }
+}
@@ -1,15 +1,22 @@
 use std::mem::zeroed;
+use std::marker::PhantomData;
 use std::collections::HashMap;
-use super::{check, ErrorStatus};
+use std::rc::Rc;
+use std::sync::Arc;
+use super::{check, Error, ErrorStatus};
 use native::*;
+use runtime::Runtime;
+use waiter::{self, SignalFuture};
 
-pub struct Signal {
+pub struct Signal<'rt> {
     handle: SignalHandle,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
-pub struct SignalGroup<T> {
+pub struct SignalGroup<'rt, T> {
     handle: SignalGroupHandle,
     signals: HashMap<u64, T>,
+    runtime: PhantomData<&'rt Runtime>,
 }
 
 pub trait SignalBase {
@@ -266,6 +273,14 @@ pub trait SignalBase {
         unsafe { hsa_signal_xor_release(self.handle(), value) }
     }
 
+    fn wait_async<'a>(
+        &'a self,
+        condition: SignalCondition,
+        compare_value: SignalValue,
+    ) -> SignalFuture<'a> {
+        SignalFuture::new(self.handle(), condition, compare_value)
+    }
+
     fn wait_scacquire(
         &self,
         condition: SignalCondition,
@@ -328,49 +343,96 @@ impl SignalBase for SignalHandle {
     }
 }
 
-impl SignalBase for Signal {
+impl<'rt> SignalBase for Signal<'rt> {
     fn handle(&self) -> SignalHandle {
         self.handle
     }
 }
 
-impl Signal {
-    pub fn new(initial_value: SignalValue, consumers: &[Agent]) -> Result<Signal, ErrorStatus> {
+impl<S: SignalBase> SignalBase for &S {
+    fn handle(&self) -> SignalHandle {
+        (*self).handle()
+    }
+}
+
+impl<S: SignalBase> SignalBase for Rc<S> {
+    fn handle(&self) -> SignalHandle {
+        (**self).handle()
+    }
+}
+
+impl<S: SignalBase> SignalBase for Arc<S> {
+    fn handle(&self) -> SignalHandle {
+        (**self).handle()
+    }
+}
+
+impl<'rt> Signal<'rt> {
+    pub fn new(
+        _runtime: &'rt Runtime,
+        initial_value: SignalValue,
+        consumers: &[Agent],
+    ) -> Result<Signal<'rt>, Error> {
+        Signal::create(initial_value, consumers)
+    }
+
+    pub(crate) fn from_handle(handle: SignalHandle) -> Signal<'rt> {
+        Signal {
+            handle,
+            runtime: PhantomData,
+        }
+    }
+
+    pub(crate) fn create(
+        initial_value: SignalValue,
+        consumers: &[Agent],
+    ) -> Result<Signal<'rt>, Error> {
         let v = {
             let mut tmp = Vec::new();
             tmp.extend_from_slice(consumers);
             tmp
         };
         unsafe {
-            let handle: SignalHandle = {
+            let mut handle: SignalHandle = {
                 zeroed()
             };
             check(
-                hsa_signal_create(initial_value, v.len() as u32, v.as_ptr(), &handle),
+                hsa_signal_create(initial_value, v.len() as u32, v.as_ptr(), &mut handle),
                 (),
-            ).map(|_| Signal { handle: handle })
+            ).map(|_| {
+                Signal {
+                    handle,
+                    runtime: PhantomData,
+                }
+            })
         }
     }
 }
 
-impl Drop for Signal {
+impl<'rt> Drop for Signal<'rt> {
     fn drop(&mut self) {
         if self.handle.handle != 0 {
-            unsafe {
-                hsa_signal_destroy(self.handle);
-            }
+            waiter::destroy(self.handle);
             self.handle.handle = 0;
         }
     }
 }
 
-impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
-    pub fn new(signals: &[T], consumers: &[Agent]) -> Result<SignalGroup<T>, ErrorStatus> {
+impl<'rt, T: Clone + SignalBase> SignalGroup<'rt, T> {
+    pub fn new(
+        _runtime: &'rt Runtime,
+        signals: &[T],
+        consumers: &[Agent],
+    ) -> Result<SignalGroup<'rt, T>, Error> {
+        SignalGroup::create(signals, consumers)
+    }
+
+    pub(crate) fn create(signals: &[T], consumers: &[Agent]) -> Result<SignalGroup<'rt, T>, Error> {
         let (signals, signals_map) = {
             let mut t0 = Vec::new();
             let mut t1 = HashMap::new();
             for s in signals {
-                let h = s.as_ref().handle;
+                let h = s.handle();
                 t0.push(h);
                 t1.insert(h.handle, s.clone());
             }
@@ -382,20 +444,21 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
             tmp
         };
         unsafe {
-            let handle: SignalGroupHandle = zeroed();
+            let mut handle: SignalGroupHandle = zeroed();
             check(
                 hsa_signal_group_create(
                     signals.len() as u32,
                     signals.as_ptr(),
                     consumers.len() as u32,
                     consumers.as_ptr(),
-                    &handle,
+                    &mut handle,
                 ),
                 (),
             ).map(|_| {
                 SignalGroup {
-                    handle: handle,
+                    handle,
                     signals: signals_map,
+                    runtime: PhantomData,
                 }
             })
         }
@@ -406,7 +469,7 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
         conditions: &[SignalCondition],
         compare_values: &[SignalValue],
         wait_state_hint: WaitState,
-    ) -> Result<(T, SignalValue), ErrorStatus> {
+    ) -> Result<(T, SignalValue), Error> {
         self.wait_any(false, conditions, compare_values, wait_state_hint)
     }
 
@@ -415,7 +478,7 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
         conditions: &[SignalCondition],
         compare_values: &[SignalValue],
         wait_state_hint: WaitState,
-    ) -> Result<(T, SignalValue), ErrorStatus> {
+    ) -> Result<(T, SignalValue), Error> {
         self.wait_any(true, conditions, compare_values, wait_state_hint)
     }
 
@@ -425,7 +488,7 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
         conditions: &[SignalCondition],
         compare_values: &[SignalValue],
         wait_state_hint: WaitState,
-    ) -> Result<(T, SignalValue), ErrorStatus> {
+    ) -> Result<(T, SignalValue), Error> {
         let (conditions, compare_values) = {
             let mut t0 = Vec::new();
             let mut t1 = Vec::new();
@@ -434,16 +497,16 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
             (t0, t1)
         };
         unsafe {
-            let signal: SignalHandle = zeroed();
-            let value: SignalValue = zeroed();
+            let mut signal: SignalHandle = zeroed();
+            let mut value: SignalValue = zeroed();
             let r = if is_relaxed {
                 hsa_signal_group_wait_any_relaxed(
                     self.handle,
                     conditions.as_ptr(),
                     compare_values.as_ptr(),
                     wait_state_hint,
-                    &signal,
-                    &value,
+                    &mut signal,
+                    &mut value,
                 )
             } else {
                 hsa_signal_group_wait_any_scacquire(
@@ -451,19 +514,19 @@ impl<T: Clone + AsRef<Signal>> SignalGroup<T> {
                     conditions.as_ptr(),
                     compare_values.as_ptr(),
                     wait_state_hint,
-                    &signal,
-                    &value,
+                    &mut signal,
+                    &mut value,
                 )
             };
             check(r, ()).and_then(|_| match self.signals.get(&signal.handle) {
                 Some(s) => Ok((s.clone(), value)),
-                _ => Err(ErrorStatus::Exception),
+                _ => Err(ErrorStatus::Exception.into()),
             })
         }
     }
 }
 
-impl<T> Drop for SignalGroup<T> {
+impl<'rt, T> Drop for SignalGroup<'rt, T> {
     fn drop(&mut self) {
         if self.handle.handle != 0 {
             unsafe {
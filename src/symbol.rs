@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::zeroed;
+use std::ptr::null;
+
+use native::*;
+use executable::Executable;
+use super::{check, Error, ErrorStatus};
+
+// the dispatch properties are queried once here; they cannot change while the
+// executable is borrowed
+#[derive(Copy, Clone)]
+pub struct Kernel<'e> {
+    symbol: ExecutableSymbol,
+    executable: &'e Executable<'e>,
+    object: u64,
+    kernarg_segment_size: u32,
+    kernarg_segment_alignment: u32,
+    group_segment_size: u32,
+    private_segment_size: u32,
+    dynamic_callstack: bool,
+}
+
+impl<'e> Kernel<'e> {
+    pub fn new(executable: &'e Executable<'e>, symbol: ExecutableSymbol) -> Result<Kernel<'e>, Error> {
+        let kind = symbol.kind()?;
+        if kind != SymbolKind::Kernel {
+            return Err(wrong_kind("kernel", kind));
+        }
+        Ok(Kernel {
+            symbol,
+            executable,
+            object: symbol.kernel_object()?,
+            kernarg_segment_size: symbol.kernel_kernarg_segment_size()?,
+            kernarg_segment_alignment: symbol.kernel_kernarg_segment_alignment()?,
+            group_segment_size: symbol.kernel_group_segment_size()?,
+            private_segment_size: symbol.kernel_private_segment_size()?,
+            dynamic_callstack: symbol.kernel_dynamic_callstack()?,
+        })
+    }
+
+    pub fn symbol(&self) -> ExecutableSymbol {
+        self.symbol
+    }
+
+    pub fn executable(&self) -> &'e Executable<'e> {
+        self.executable
+    }
+
+    pub fn object(&self) -> u64 {
+        self.object
+    }
+
+    pub fn kernarg_segment_size(&self) -> u32 {
+        self.kernarg_segment_size
+    }
+
+    pub fn kernarg_segment_alignment(&self) -> u32 {
+        self.kernarg_segment_alignment
+    }
+
+    pub fn group_segment_size(&self) -> u32 {
+        self.group_segment_size
+    }
+
+    pub fn private_segment_size(&self) -> u32 {
+        self.private_segment_size
+    }
+
+    // when set, private_segment_size is only the fixed part of the kernel's stack
+    pub fn dynamic_callstack(&self) -> bool {
+        self.dynamic_callstack
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Variable<'e> {
+    symbol: ExecutableSymbol,
+    executable: &'e Executable<'e>,
+}
+
+impl<'e> Variable<'e> {
+    pub fn symbol(&self) -> ExecutableSymbol {
+        self.symbol
+    }
+
+    pub fn executable(&self) -> &'e Executable<'e> {
+        self.executable
+    }
+
+    pub fn address(&self) -> Result<u64, Error> {
+        self.symbol.variable_address()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct IndirectFunction<'e> {
+    symbol: ExecutableSymbol,
+    executable: &'e Executable<'e>,
+}
+
+impl<'e> IndirectFunction<'e> {
+    pub fn symbol(&self) -> ExecutableSymbol {
+        self.symbol
+    }
+
+    pub fn executable(&self) -> &'e Executable<'e> {
+        self.executable
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    pub fn object(&self) -> Result<u32, Error> {
+        self.symbol.indirect_function_object()
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    pub fn object(&self) -> Result<u64, Error> {
+        self.symbol.indirect_function_object()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Symbol<'e> {
+    Kernel(Kernel<'e>),
+    Variable(Variable<'e>),
+    IndirectFunction(IndirectFunction<'e>),
+}
+
+fn wrong_kind(expected: &str, found: SymbolKind) -> Error {
+    Error::from(ErrorStatus::InvalidExecutableSymbol)
+        .with_context(format!("expected a {} symbol, found {:?}", expected, found))
+}
+
+impl<'e> Symbol<'e> {
+    pub fn new(executable: &'e Executable<'e>, symbol: ExecutableSymbol) -> Result<Symbol<'e>, Error> {
+        Ok(match symbol.kind()? {
+            SymbolKind::Kernel => Symbol::Kernel(Kernel::new(executable, symbol)?),
+            SymbolKind::Variable => Symbol::Variable(Variable {
+                symbol,
+                executable,
+            }),
+            SymbolKind::IndirectFunction => Symbol::IndirectFunction(IndirectFunction {
+                symbol,
+                executable,
+            }),
+        })
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        match *self {
+            Symbol::Kernel(_) => SymbolKind::Kernel,
+            Symbol::Variable(_) => SymbolKind::Variable,
+            Symbol::IndirectFunction(_) => SymbolKind::IndirectFunction,
+        }
+    }
+
+    pub fn symbol(&self) -> ExecutableSymbol {
+        match *self {
+            Symbol::Kernel(ref k) => k.symbol,
+            Symbol::Variable(ref v) => v.symbol,
+            Symbol::IndirectFunction(ref f) => f.symbol,
+        }
+    }
+
+    pub fn into_kernel(self) -> Result<Kernel<'e>, Error> {
+        match self {
+            Symbol::Kernel(k) => Ok(k),
+            other => Err(wrong_kind("kernel", other.kind())),
+        }
+    }
+
+    pub fn into_variable(self) -> Result<Variable<'e>, Error> {
+        match self {
+            Symbol::Variable(v) => Ok(v),
+            other => Err(wrong_kind("variable", other.kind())),
+        }
+    }
+
+    pub fn into_indirect_function(self) -> Result<IndirectFunction<'e>, Error> {
+        match self {
+            Symbol::IndirectFunction(f) => Ok(f),
+            other => Err(wrong_kind("indirect function", other.kind())),
+        }
+    }
+}
+
+impl<'rt> Executable<'rt> {
+    // agent symbols are found by passing their agent; None finds program symbols
+    pub fn symbol(&self, linker_name: &str, agent: Option<Agent>) -> Result<Symbol<'_>, Error> {
+        let name = CString::new(linker_name).map_err(|_| ErrorStatus::InvalidSymbolName)?;
+        let agent_ptr = agent.as_ref().map_or(null(), |a| a as *const Agent);
+        let lookup = if Api::current().hsa_executable_get_symbol_by_linker_name.is_some() {
+            hsa_executable_get_symbol_by_linker_name
+        } else {
+            hsa_executable_get_symbol_by_name
+        };
+        let mut symbol: ExecutableSymbol = unsafe { zeroed() };
+        check(unsafe { lookup(self.handle(), name.as_ptr(), agent_ptr, &mut symbol) }, ())
+            .map_err(|e| e.with_context(format!("symbol {:?}", linker_name)))?;
+        Symbol::new(self, symbol)
+    }
+
+    pub fn kernel(&self, linker_name: &str, agent: Agent) -> Result<Kernel<'_>, Error> {
+        self.symbol(linker_name, Some(agent))?.into_kernel()
+    }
+
+    // symbols cannot change once the executable is frozen, so the index is only
+    // built then
+    pub fn index(&self) -> Result<SymbolIndex<'_>, Error> {
+        if self.state()? != ExecutableState::Frozen {
+            return Err(Error::from(ErrorStatus::InvalidExecutable)
+                .with_context("the symbol index is built after freeze()"));
+        }
+        let mut index = SymbolIndex {
+            symbols: HashMap::new(),
+        };
+        for symbol in self.program_symbols()? {
+            index.insert(None, Symbol::new(self, symbol)?)?;
+        }
+        for agent in Agent::list()? {
+            for symbol in self.agent_symbols(agent)? {
+                index.insert(Some(agent), Symbol::new(self, symbol)?)?;
+            }
+        }
+        Ok(index)
+    }
+}
+
+pub struct SymbolIndex<'e> {
+    symbols: HashMap<String, Vec<(Option<Agent>, Symbol<'e>)>>,
+}
+
+impl<'e> SymbolIndex<'e> {
+    fn insert(&mut self, agent: Option<Agent>, symbol: Symbol<'e>) -> Result<(), Error> {
+        let name = symbol.symbol().linker_name()?;
+        let name = name.trim_end_matches('\0').to_string();
+        self.symbols.entry(name).or_default().push((agent, symbol));
+        Ok(())
+    }
+
+    pub fn get(&self, linker_name: &str, agent: Option<Agent>) -> Option<Symbol<'e>> {
+        self.symbols
+            .get(linker_name)?
+            .iter()
+            .find(|&&(a, _)| a == agent)
+            .map(|&(_, symbol)| symbol)
+    }
+
+    fn find(&self, linker_name: &str, agent: Option<Agent>) -> Result<Symbol<'e>, Error> {
+        self.get(linker_name, agent).ok_or_else(|| {
+            Error::from(ErrorStatus::InvalidSymbolName)
+                .with_context(format!("symbol {:?}", linker_name))
+        })
+    }
+
+    pub fn kernel(&self, linker_name: &str, agent: Agent) -> Result<Kernel<'e>, Error> {
+        self.find(linker_name, Some(agent))?.into_kernel()
+    }
+
+    pub fn variable(&self, linker_name: &str, agent: Option<Agent>) -> Result<Variable<'e>, Error> {
+        self.find(linker_name, agent)?.into_variable()
+    }
+
+    pub fn indirect_function(
+        &self,
+        linker_name: &str,
+        agent: Agent,
+    ) -> Result<IndirectFunction<'e>, Error> {
+        self.find(linker_name, Some(agent))?.into_indirect_function()
+    }
+
+    // one entry per (name, agent) pair
+    pub fn len(&self) -> usize {
+        self.symbols.values().map(|v| v.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(|k| &k[..])
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use native::*;
+    use emulator::{register_kernel, WorkItem};
+    use executable::Executable;
+    use ErrorStatus;
+
+    #[test]
+    fn symbols_are_found_by_linker_name() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        register_kernel("&__symbol_lookup_test", |_: &WorkItem, _: &u64| {});
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "")
+                .unwrap();
+
+        let kernel = executable.symbol("&__symbol_lookup_test", Some(agent)).unwrap()
+            .into_kernel()
+            .unwrap();
+        assert_eq!(kernel.kernarg_segment_size(), 8);
+        let e = executable.symbol("&__no_such_symbol", Some(agent)).err().unwrap();
+        assert_eq!(e, ErrorStatus::InvalidSymbolName);
+        assert!(executable.symbol("&__symbol_lookup_test", None).is_err());
+        let e = executable.symbol("&__symbol_lookup_test", Some(agent)).unwrap()
+            .into_variable()
+            .err()
+            .unwrap();
+        assert_eq!(e, ErrorStatus::InvalidExecutableSymbol);
+
+        assert_eq!(executable.index().err().unwrap(), ErrorStatus::InvalidExecutable);
+        executable.freeze("").unwrap();
+        let index = executable.index().unwrap();
+        let indexed = index.kernel("&__symbol_lookup_test", agent).unwrap();
+        assert_eq!(indexed.object(), kernel.object());
+        assert!(index.get("&__symbol_lookup_test", None).is_none());
+        assert!(index.names().any(|n| n == "&__symbol_lookup_test"));
+    }
+
+    #[test]
+    fn lookup_falls_back_to_get_symbol_by_name() {
+        let runtime = ::init().unwrap();
+        let agent = Agent::from_device_type(DeviceType::CPU).unwrap()[0];
+        register_kernel("&__symbol_fallback_test", |_: &WorkItem, _: &u32| {});
+        let executable =
+            Executable::new(&runtime, Profile::Full, DefaultFloatRoundingMode::Default, "")
+                .unwrap();
+
+        let mut api = Api::current();
+        api.hsa_executable_get_symbol_by_linker_name = None;
+        let kernel = with_api(&api, || executable.kernel("&__symbol_fallback_test", agent));
+        assert_eq!(kernel.unwrap().kernarg_segment_size(), 4);
+        api.hsa_executable_get_symbol_by_name = None;
+        let e = with_api(&api, || executable.kernel("&__symbol_fallback_test", agent).err());
+        assert_eq!(e.unwrap(), ErrorStatus::SymbolNotFound);
+    }
+}
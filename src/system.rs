@@ -1,41 +1,41 @@
 use std::os::raw::c_void;
-use super::{check, get_str, get_info, ErrorStatus, FromPrimitive};
+use super::{check, get_str, get_info, Error, FromPrimitive};
 use native::*;
 
 fn _get_info(attr: SystemInfo, v: *mut c_void) -> HSAStatus {
     unsafe { hsa_system_get_info(attr, v) }
 }
 
-pub fn version_major() -> Result<u16, ErrorStatus> {
+pub fn version_major() -> Result<u16, Error> {
     get_info(|x| _get_info(SystemInfo::VersionMajor, x))
 }
 
-pub fn version_minor() -> Result<u16, ErrorStatus> {
+pub fn version_minor() -> Result<u16, Error> {
     get_info(|x| _get_info(SystemInfo::VersionMinor, x))
 }
 
-pub fn timestamp() -> Result<u64, ErrorStatus> {
+pub fn timestamp() -> Result<u64, Error> {
     get_info(|x| _get_info(SystemInfo::Timestamp, x))
 }
 
-pub fn timestamp_frequency() -> Result<u64, ErrorStatus> {
+pub fn timestamp_frequency() -> Result<u64, Error> {
     get_info(|x| _get_info(SystemInfo::TimestampFrequency, x))
 }
 
-pub fn signal_max_wait() -> Result<u64, ErrorStatus> {
+pub fn signal_max_wait() -> Result<u64, Error> {
     get_info(|x| _get_info(SystemInfo::SignalMaxWait, x))
 }
 
-pub fn endianness() -> Result<Endianness, ErrorStatus> {
+pub fn endianness() -> Result<Endianness, Error> {
     get_info(|x| _get_info(SystemInfo::Endianness, x))
 }
 
-pub fn machine_model() -> Result<MachineModel, ErrorStatus> {
+pub fn machine_model() -> Result<MachineModel, Error> {
     get_info(|x| _get_info(SystemInfo::MachineModel, x))
 }
 
-pub fn extensions() -> Result<Vec<Extension>, ErrorStatus> {
-    let ret: Result<[u8; 128], ErrorStatus> = get_info(|x| _get_info(SystemInfo::Extensions, x));
+pub fn extensions() -> Result<Vec<Extension>, Error> {
+    let ret: Result<[u8; 128], Error> = get_info(|x| _get_info(SystemInfo::Extensions, x));
     ret.map(|x| {
         let mut v = Vec::new();
         for (i, b) in x.iter().enumerate().filter(|&(_, &b)| b != 0) {
@@ -49,7 +49,7 @@ pub fn extensions() -> Result<Vec<Extension>, ErrorStatus> {
     })
 }
 
-pub fn extension_names() -> Result<Vec<String>, ErrorStatus> {
+pub fn extension_names() -> Result<Vec<String>, Error> {
     extensions().map(|e| {
         e.iter()
             .filter_map(|id| get_extension_name(*id).ok())
@@ -57,7 +57,7 @@ pub fn extension_names() -> Result<Vec<String>, ErrorStatus> {
     })
 }
 
-pub fn get_extension_name(extension: Extension) -> Result<String, ErrorStatus> {
+pub fn get_extension_name(extension: Extension) -> Result<String, Error> {
     get_str(hsa_extension_get_name, extension)
 }
 
@@ -66,7 +66,7 @@ pub fn extension_supported(
     extension: Extension,
     version_major: u16,
     version_minor: u16,
-) -> Result<bool, ErrorStatus> {
+) -> Result<bool, Error> {
     let mut result = false;
     unsafe {
         check(
@@ -79,7 +79,7 @@ pub fn extension_supported(
 pub fn major_extension_supported(
     extension: Extension,
     version_major: u16,
-) -> Result<(u16, bool), ErrorStatus> {
+) -> Result<(u16, bool), Error> {
     let mut result = false;
     let mut version_minor = 0u16;
     check(
@@ -95,11 +95,11 @@ pub fn major_extension_supported(
     )
 }
 
-pub fn get_finalizer1_extension_table() -> Result<ExtFinalizer1, ErrorStatus> {
-    use std::mem::{size_of, zeroed};
+pub fn get_finalizer1_extension_table() -> Result<ExtFinalizer1, Error> {
+    use std::mem::{size_of, MaybeUninit};
     unsafe {
-        let mut table: ExtFinalizer1 = zeroed();
-        let p: *mut c_void = &mut table as *mut _ as *mut c_void;
+        let mut table: MaybeUninit<ExtFinalizer1> = MaybeUninit::uninit();
+        let p: *mut c_void = table.as_mut_ptr() as *mut c_void;
         check(
             hsa_system_get_major_extension_table(
                 Extension::Finalizer,
@@ -108,6 +108,6 @@ pub fn get_finalizer1_extension_table() -> Result<ExtFinalizer1, ErrorStatus> {
                 p,
             ),
             (),
-        ).map(|_| table)
+        ).map(|_| table.assume_init())
     }
 }
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::mem::zeroed;
+use std::ptr;
+use std::thread::{self, JoinHandle};
+
+use native::*;
+use signal::{SignalBase, SignalGroup};
+use super::{check, Error, ErrorStatus};
+
+struct Entry {
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    waker: Option<Waker>,
+    result: Option<Result<SignalValue, Error>>,
+}
+
+struct State {
+    entries: HashMap<u64, Entry>,
+    next_id: u64,
+    // the entries and signals the current group waits on
+    waiting: Vec<u64>,
+    watched: Vec<SignalHandle>,
+    // signals dropped while the group still holds them; destroyed when it is rebuilt
+    retired: Vec<SignalHandle>,
+    changed: bool,
+    stop: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    control: SignalHandle,
+}
+
+struct Waiter {
+    shared: Arc<Shared>,
+    thread: JoinHandle<()>,
+}
+
+static WAITER: Mutex<Option<Waiter>> = Mutex::new(None);
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn satisfied(condition: SignalCondition, value: SignalValue, compare_value: SignalValue) -> bool {
+    match condition {
+        SignalCondition::Eq => value == compare_value,
+        SignalCondition::Ne => value != compare_value,
+        SignalCondition::Lt => value < compare_value,
+        SignalCondition::Gte => value >= compare_value,
+    }
+}
+
+impl Waiter {
+    fn spawn() -> Result<Waiter, Error> {
+        let mut control: SignalHandle = unsafe { zeroed() };
+        check(unsafe { hsa_signal_create(0, 0, ptr::null(), &mut control) }, ())?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                next_id: 0,
+                waiting: Vec::new(),
+                watched: Vec::new(),
+                retired: Vec::new(),
+                changed: true,
+                stop: false,
+            }),
+            control,
+        });
+        let s = shared.clone();
+        match thread::Builder::new()
+            .name("hsa-signal-waiter".to_string())
+            .spawn(move || run(&s))
+        {
+            Ok(thread) => Ok(Waiter {
+                shared,
+                thread,
+            }),
+            Err(_) => {
+                unsafe { hsa_signal_destroy(control) };
+                Err(ErrorStatus::OutOfResources.into())
+            }
+        }
+    }
+
+    fn stop(self) {
+        let wakers = {
+            let mut state = lock(&self.shared.state);
+            state.stop = true;
+            // nothing watches the outstanding futures any more
+            let mut wakers = Vec::new();
+            for entry in state.entries.values_mut().filter(|e| e.result.is_none()) {
+                entry.result = Some(Err(ErrorStatus::NotInitialized.into()));
+                wakers.extend(entry.waker.take());
+            }
+            self.shared.control.store_screlease(1);
+            wakers
+        };
+        let _ = self.thread.join();
+        unsafe { hsa_signal_destroy(self.shared.control) };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+fn shared() -> Result<Arc<Shared>, Error> {
+    let mut waiter = lock(&WAITER);
+    if let Some(ref waiter) = *waiter {
+        return Ok(waiter.shared.clone());
+    }
+    let spawned = Waiter::spawn()?;
+    let shared = spawned.shared.clone();
+    *waiter = Some(spawned);
+    Ok(shared)
+}
+
+pub(crate) fn stop() {
+    let waiter = lock(&WAITER).take();
+    if let Some(waiter) = waiter {
+        waiter.stop();
+    }
+}
+
+// signals are destroyed through the waiter so that one it is still waiting on outlives the wait
+pub(crate) fn destroy(signal: SignalHandle) {
+    if let Some(ref waiter) = *lock(&WAITER) {
+        let mut state = lock(&waiter.shared.state);
+        if state.watched.contains(&signal) {
+            state.retired.push(signal);
+            state.changed = true;
+            waiter.shared.control.store_screlease(1);
+            return;
+        }
+    }
+    unsafe { hsa_signal_destroy(signal) };
+}
+
+fn run(shared: &Shared) {
+    let mut group: Option<Result<SignalGroup<'static, SignalHandle>, Error>> = None;
+    let mut conditions = Vec::new();
+    let mut compare_values = Vec::new();
+    loop {
+        {
+            let mut state = lock(&shared.state);
+            if state.stop {
+                break;
+            }
+            // the group is only rebuilt when the set of waiting entries changes
+            if state.changed {
+                // the old group must let go of the retired signals before they are destroyed
+                drop(group.take());
+                for signal in state.retired.drain(..) {
+                    unsafe { hsa_signal_destroy(signal) };
+                }
+                let mut signals = vec![shared.control];
+                conditions = vec![SignalCondition::Ne];
+                compare_values = vec![0];
+                let mut waiting = Vec::new();
+                for (id, entry) in &state.entries {
+                    if entry.result.is_none() {
+                        waiting.push(*id);
+                        signals.push(entry.signal);
+                        conditions.push(entry.condition);
+                        compare_values.push(entry.compare_value);
+                    }
+                }
+                state.watched = signals[1..].to_vec();
+                state.waiting = waiting;
+                state.changed = false;
+                group = Some(SignalGroup::create(&signals, &[]));
+            }
+        }
+        let outcome = match group {
+            Some(Ok(ref group)) => group
+                .wait_any_scacquire(&conditions, &compare_values, WaitState::Blocked)
+                .map(|_| ()),
+            Some(Err(ref e)) => Err(e.clone()),
+            None => Ok(()),
+        };
+        shared.control.store_relaxed(0);
+
+        let mut wakers = Vec::new();
+        {
+            let mut state = lock(&shared.state);
+            let state = &mut *state;
+            for id in &state.waiting {
+                let entry = match state.entries.get_mut(id) {
+                    Some(entry) if entry.result.is_none() => entry,
+                    _ => continue,
+                };
+                let value = entry.signal.load_scacquire();
+                if satisfied(entry.condition, value, entry.compare_value) {
+                    entry.result = Some(Ok(value));
+                } else if let Err(ref e) = outcome {
+                    entry.result = Some(Err(e.clone()));
+                } else {
+                    continue;
+                }
+                wakers.extend(entry.waker.take());
+                state.changed = true;
+            }
+            if outcome.is_err() {
+                state.changed = true;
+            }
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+    drop(group);
+    for signal in lock(&shared.state).retired.drain(..) {
+        unsafe { hsa_signal_destroy(signal) };
+    }
+}
+
+pub struct SignalFuture<'a> {
+    signal: SignalHandle,
+    condition: SignalCondition,
+    compare_value: SignalValue,
+    registration: Option<(Arc<Shared>, u64)>,
+    signal_lifetime: PhantomData<&'a ()>,
+}
+
+impl<'a> SignalFuture<'a> {
+    pub(crate) fn new(
+        signal: SignalHandle,
+        condition: SignalCondition,
+        compare_value: SignalValue,
+    ) -> SignalFuture<'a> {
+        SignalFuture {
+            signal,
+            condition,
+            compare_value,
+            registration: None,
+            signal_lifetime: PhantomData,
+        }
+    }
+}
+
+impl<'a> SignalFuture<'a> {
+    fn register(&mut self, shared: Arc<Shared>, waker: &Waker) -> Poll<Result<SignalValue, Error>> {
+        {
+            let mut state = lock(&shared.state);
+            if state.stop {
+                return Poll::Ready(Err(ErrorStatus::NotInitialized.into()));
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            state.entries.insert(
+                id,
+                Entry {
+                    signal: self.signal,
+                    condition: self.condition,
+                    compare_value: self.compare_value,
+                    waker: Some(waker.clone()),
+                    result: None,
+                },
+            );
+            state.changed = true;
+            shared.control.store_screlease(1);
+            self.registration = Some((shared.clone(), id));
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Future for SignalFuture<'a> {
+    type Output = Result<SignalValue, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<SignalValue, Error>> {
+        let this = self.get_mut();
+        if let Some((ref shared, id)) = this.registration {
+            let mut state = lock(&shared.state);
+            let pending = match state.entries.get_mut(&id) {
+                Some(entry) if entry.result.is_none() => {
+                    entry.waker = Some(cx.waker().clone());
+                    true
+                }
+                _ => false,
+            };
+            if pending {
+                return Poll::Pending;
+            }
+            let result = state.entries.remove(&id).and_then(|e| e.result);
+            drop(state);
+            this.registration = None;
+            return Poll::Ready(result.unwrap_or_else(|| Err(ErrorStatus::NotInitialized.into())));
+        }
+
+        let value = this.signal.load_scacquire();
+        if satisfied(this.condition, value, this.compare_value) {
+            return Poll::Ready(Ok(value));
+        }
+        match shared() {
+            Ok(shared) => this.register(shared, cx.waker()),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl<'a> Drop for SignalFuture<'a> {
+    fn drop(&mut self) {
+        // the group keeps the signal until the waiter rebuilds it and destroy() defers a signal
+        // it still holds, so cancelling only has to tell the waiter
+        if let Some((shared, id)) = self.registration.take() {
+            let mut state = lock(&shared.state);
+            state.entries.remove(&id);
+            if state.waiting.contains(&id) && !state.stop {
+                state.changed = true;
+                shared.control.store_screlease(1);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use native::*;
+    use signal::{Signal, SignalBase};
+    use super::{SignalFuture, Waiter};
+    use {Error, ErrorStatus};
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn waker() -> (Arc<Count>, Waker) {
+        let count = Arc::new(Count(AtomicUsize::new(0)));
+        (count.clone(), Waker::from(count))
+    }
+
+    fn poll(future: &mut SignalFuture, waker: &Waker) -> Poll<Result<SignalValue, Error>> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+
+    fn ready(poll: Poll<Result<SignalValue, Error>>) -> Result<SignalValue, Error> {
+        match poll {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("future is still pending"),
+        }
+    }
+
+    fn woken(count: &Count) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while count.0.load(Ordering::SeqCst) == 0 {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    #[test]
+    fn futures_are_woken_when_the_condition_holds() {
+        let _runtime = ::init().unwrap();
+        let signal = Signal::create(1, &[]).unwrap();
+        let (count, waker) = waker();
+        let mut future = signal.wait_async(SignalCondition::Lt, 1);
+        assert!(poll(&mut future, &waker).is_pending());
+        assert!(poll(&mut future, &waker).is_pending());
+        signal.store_screlease(0);
+        assert!(woken(&count));
+        assert_eq!(ready(poll(&mut future, &waker)).unwrap(), 0);
+
+        // a condition that already holds resolves without the waiter
+        let mut future = signal.wait_async(SignalCondition::Eq, 0);
+        assert_eq!(ready(poll(&mut future, &waker)).unwrap(), 0);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cancelled_futures_release_their_signals() {
+        let _runtime = ::init().unwrap();
+        let (count, waker) = waker();
+        let cancelled = Signal::create(1, &[]).unwrap();
+        let mut future = cancelled.wait_async(SignalCondition::Eq, 0);
+        assert!(poll(&mut future, &waker).is_pending());
+        drop(future);
+        // still in the waiter's group, so its destruction is left to the waiter
+        drop(cancelled);
+
+        let signal = Signal::create(1, &[]).unwrap();
+        let mut future = signal.wait_async(SignalCondition::Eq, 0);
+        assert!(poll(&mut future, &waker).is_pending());
+        signal.store_screlease(0);
+        assert!(woken(&count));
+        assert_eq!(ready(poll(&mut future, &waker)).unwrap(), 0);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn stopping_the_waiter_resolves_pending_futures() {
+        let _runtime = ::init().unwrap();
+        let waiter = Waiter::spawn().unwrap();
+        let shared = waiter.shared.clone();
+        let signal = Signal::create(1, &[]).unwrap();
+        let (count, waker) = waker();
+        let mut future = signal.wait_async(SignalCondition::Eq, 0);
+        assert!(future.register(shared.clone(), &waker).is_pending());
+
+        waiter.stop();
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        let err = ready(poll(&mut future, &waker)).unwrap_err();
+        assert_eq!(err, ErrorStatus::NotInitialized);
+
+        let mut late = signal.wait_async(SignalCondition::Eq, 0);
+        let err = ready(late.register(shared, &waker)).unwrap_err();
+        assert_eq!(err, ErrorStatus::NotInitialized);
+    }
+}
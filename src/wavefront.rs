@@ -1,10 +1,10 @@
 use std::os::raw::c_void;
 
 use native::*;
-use super::{get_info, ErrorStatus};
+use super::{get_info, Error};
 
 impl Wavefront {
-    pub fn size(&self) -> Result<u32, ErrorStatus> {
+    pub fn size(&self) -> Result<u32, Error> {
         get_info(|x| self.get_info(WavefrontInfo::Size, x))
     }
 
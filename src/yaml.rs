@@ -0,0 +1,180 @@
+use metadata::MetadataValue;
+use super::{Error, ErrorStatus};
+
+// the block-style subset that code object v2 metadata is written in: indented
+// mappings, "- " sequences, flow sequences of scalars and quoted or plain scalars
+
+// nesting in real metadata is a handful of levels; the limit only guards the stack
+const MAX_DEPTH: usize = 32;
+
+fn invalid(line: usize) -> Error {
+    Error::from(ErrorStatus::InvalidCodeObject)
+        .with_context(format!("invalid YAML metadata at line {}", line + 1))
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    next: usize,
+}
+
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if let Some(i) = text.find(": ") {
+        return Some((&text[..i], text[i + 2..].trim()));
+    }
+    text.strip_suffix(':').map(|key| (key, ""))
+}
+
+fn scalar(text: &str, line: usize) -> Result<MetadataValue, Error> {
+    let text = text.trim();
+    if text.starts_with('\'') {
+        if text.len() < 2 || !text.ends_with('\'') {
+            return Err(invalid(line));
+        }
+        return Ok(MetadataValue::Str(text[1..text.len() - 1].replace("''", "'")));
+    }
+    if text.starts_with('"') {
+        if text.len() < 2 || !text.ends_with('"') {
+            return Err(invalid(line));
+        }
+        let mut out = String::new();
+        let mut chars = text[1..text.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            out.push(match (c, c == '\\') {
+                (_, true) => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some(c) => c,
+                    None => return Err(invalid(line)),
+                },
+                (c, false) => c,
+            });
+        }
+        return Ok(MetadataValue::Str(out));
+    }
+    if text.starts_with('[') {
+        if !text.ends_with(']') {
+            return Err(invalid(line));
+        }
+        let inner = text[1..text.len() - 1].trim();
+        if inner.is_empty() {
+            return Ok(MetadataValue::Array(Vec::new()));
+        }
+        return inner.split(',').map(|item| scalar(item, line)).collect::<Result<_, _>>()
+            .map(MetadataValue::Array);
+    }
+    Ok(match text {
+        "" | "~" | "null" => MetadataValue::Nil,
+        _ => MetadataValue::Str(text.to_string()),
+    })
+}
+
+impl Parser {
+    fn node(&mut self, indent: usize, depth: usize) -> Result<MetadataValue, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::from(ErrorStatus::InvalidCodeObject)
+                .with_context("metadata is nested too deeply"));
+        }
+        match self.lines.get(self.next) {
+            Some(line) if line.text == "-" || line.text.starts_with("- ") => {
+                self.sequence(indent, depth)
+            }
+            Some(_) => self.mapping(indent, depth),
+            None => Ok(MetadataValue::Nil),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize, depth: usize) -> Result<MetadataValue, Error> {
+        let mut items = Vec::new();
+        while self.next < self.lines.len() && self.lines[self.next].indent == indent {
+            let (number, rest) = {
+                let line = &self.lines[self.next];
+                if line.text != "-" && !line.text.starts_with("- ") {
+                    break;
+                }
+                (line.number, line.text[1..].trim().to_string())
+            };
+            let quoted = rest.starts_with('\'') || rest.starts_with('"');
+            if rest.is_empty() {
+                self.next += 1;
+                items.push(self.child(indent, false, depth + 1)?);
+            } else if !quoted && (split_key(&rest).is_some() || rest.starts_with("- ")) {
+                // "- Key: value" starts a nested node that continues two columns in
+                let line = &mut self.lines[self.next];
+                line.indent = indent + 2;
+                line.text = rest;
+                items.push(self.node(indent + 2, depth + 1)?);
+            } else {
+                self.next += 1;
+                items.push(scalar(&rest, number)?);
+            }
+        }
+        Ok(MetadataValue::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize, depth: usize) -> Result<MetadataValue, Error> {
+        let mut entries = Vec::new();
+        while self.next < self.lines.len() && self.lines[self.next].indent == indent {
+            let (number, key, value) = {
+                let line = &self.lines[self.next];
+                let (key, value) = split_key(&line.text).ok_or_else(|| invalid(line.number))?;
+                (line.number, scalar(key, line.number)?, value.to_string())
+            };
+            self.next += 1;
+            let value = if value.is_empty() {
+                self.child(indent, true, depth + 1)?
+            } else {
+                scalar(&value, number)?
+            };
+            entries.push((key, value));
+        }
+        Ok(MetadataValue::Map(entries))
+    }
+
+    // the value of "Key:" or "-" on its own line; sequences may sit at the key's indent
+    fn child(&mut self, indent: usize, key: bool, depth: usize) -> Result<MetadataValue, Error> {
+        let next = match self.lines.get(self.next) {
+            Some(line) => (line.indent, line.text.starts_with('-')),
+            None => return Ok(MetadataValue::Nil),
+        };
+        match next {
+            (i, _) if i > indent => self.node(i, depth),
+            (i, true) if i == indent && key => self.sequence(i, depth),
+            _ => Ok(MetadataValue::Nil),
+        }
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<MetadataValue, Error> {
+    let mut lines = Vec::new();
+    for (number, raw) in text.lines().enumerate() {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" || trimmed == "..." {
+            continue;
+        }
+        if raw.starts_with('\t') {
+            return Err(invalid(number));
+        }
+        lines.push(Line {
+            number,
+            indent: raw.len() - raw.trim_start().len(),
+            text: raw.trim().to_string(),
+        });
+    }
+    let indent = lines.first().map(|l| l.indent).unwrap_or(0);
+    let mut parser = Parser {
+        lines,
+        next: 0,
+    };
+    let value = parser.node(indent, 0)?;
+    match parser.lines.get(parser.next) {
+        Some(line) => Err(invalid(line.number)),
+        None => Ok(value),
+    }
+}